@@ -0,0 +1,241 @@
+///
+/// breakpoints and watchpoints on top of a cpu, in both directions of time.
+/// Going backwards needs the cpu to record its history, see
+/// `Mos6502::record_history`.
+///
+pub mod dap;
+pub mod dbginfo;
+pub mod gdb;
+pub mod script;
+pub mod vice;
+
+use std::collections::BTreeSet;
+
+use crate::mos6502::{history::WriteRecord, Access, AccessKind, HaltReason, Mos6502};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access, // either
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn hit_by(&self, access: &Access) -> bool {
+        let kind = match (self.kind, access.kind) {
+            (_, AccessKind::Fetch) => false,
+            (WatchKind::Access, _) => true,
+            (WatchKind::Read, kind) => kind == AccessKind::Read,
+            (WatchKind::Write, kind) => kind == AccessKind::Write,
+        };
+        kind && self.start <= access.address && access.address <= self.end
+    }
+}
+
+///
+/// why execution stopped
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(u16),
+    Watchpoint(Watchpoint, Access),
+    Halted(HaltReason),
+    HistoryStart, // nothing left to undo
+    Limit,        // the instruction budget of a `resume` ran out
+}
+
+pub struct Debugger {
+    cpu: Mos6502,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl Debugger {
+    pub fn new(mut cpu: Mos6502) -> Self {
+        cpu.set_tracing(true);
+        Self {
+            cpu,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+
+    pub fn cpu(&self) -> &Mos6502 {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Mos6502 {
+        &mut self.cpu
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|watch| *watch != watchpoint);
+        self.watchpoints.len() != before
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn step(&mut self) -> Stop {
+        if let Some(reason) = self.cpu.halt_reason() {
+            return Stop::Halted(reason);
+        }
+        self.cpu.step();
+        if let Some(reason) = self.cpu.halt_reason() {
+            return Stop::Halted(reason);
+        }
+        self.watch_hit(self.cpu.accesses()).unwrap_or(Stop::Stepped)
+    }
+
+    ///
+    /// run until something stops execution, a breakpoint where the cpu
+    /// stands does not stop it from leaving
+    ///
+    pub fn resume(&mut self) -> Stop {
+        self.resume_for(u64::MAX)
+    }
+
+    ///
+    /// `resume` for at most `instructions` steps, frontends use this to keep
+    /// listening for an interrupt request
+    ///
+    pub fn resume_for(&mut self, instructions: u64) -> Stop {
+        for _ in 0..instructions {
+            let stop = self.step();
+            if stop != Stop::Stepped {
+                return stop;
+            }
+            let pc = self.cpu.registers().pc;
+            if self.breakpoints.contains(&pc) {
+                return Stop::Breakpoint(pc);
+            }
+        }
+        Stop::Limit
+    }
+
+    pub fn reverse_step(&mut self) -> Stop {
+        match self.cpu.reverse_step() {
+            Some(accesses) => self.watch_hit(&accesses).unwrap_or(Stop::Stepped),
+            None => Stop::HistoryStart,
+        }
+    }
+
+    ///
+    /// step backwards until landing on a breakpoint or undoing an access
+    /// a watchpoint covers
+    ///
+    pub fn reverse_resume(&mut self) -> Stop {
+        loop {
+            let stop = self.reverse_step();
+            if stop != Stop::Stepped {
+                return stop;
+            }
+            let pc = self.cpu.registers().pc;
+            if self.breakpoints.contains(&pc) {
+                return Stop::Breakpoint(pc);
+            }
+        }
+    }
+
+    ///
+    /// the instruction that last wrote `address`, as far back as the
+    /// history goes
+    ///
+    pub fn last_write(&self, address: u16) -> Option<WriteRecord> {
+        self.cpu.history()?.last_write(address)
+    }
+
+    fn watch_hit(&self, accesses: &[Access]) -> Option<Stop> {
+        accesses.iter().find_map(|access| {
+            self.watchpoints
+                .iter()
+                .find(|watch| watch.hit_by(access))
+                .map(|watch| Stop::Watchpoint(*watch, *access))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mos6502::history::{DEFAULT_BUDGET, DEFAULT_SNAPSHOT_INTERVAL};
+
+    ///
+    /// counts $10 up forever: inc $10, jmp $0200
+    ///
+    fn counter() -> Debugger {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &[0xe6, 0x10, 0x4c, 0x00, 0x02]);
+        cpu.record_history(DEFAULT_BUDGET, DEFAULT_SNAPSHOT_INTERVAL);
+        Debugger::new(cpu)
+    }
+
+    #[test]
+    fn test_resume_stops_at_breakpoint() {
+        let mut debugger = counter();
+        debugger.add_breakpoint(0x0200);
+        assert_eq!(Stop::Breakpoint(0x0200), debugger.resume());
+        assert_eq!(Stop::Breakpoint(0x0200), debugger.resume());
+        assert_eq!(2, debugger.cpu_mut().peek(0x10));
+        assert_eq!(Stop::Limit, counter().resume_for(10));
+    }
+
+    #[test]
+    fn test_watchpoint_stops_both_ways() {
+        let mut debugger = counter();
+        let watch = Watchpoint {
+            start: 0x10,
+            end: 0x10,
+            kind: WatchKind::Write,
+        };
+        debugger.add_watchpoint(watch);
+        let Stop::Watchpoint(hit, access) = debugger.resume() else {
+            panic!("no watchpoint hit");
+        };
+        assert_eq!(watch, hit);
+        assert_eq!(1, access.val);
+        debugger.resume();
+        debugger.step();
+        assert_eq!(
+            Some(2),
+            debugger.last_write(0x10).map(|write| write.instruction)
+        );
+        assert!(matches!(
+            debugger.reverse_resume(),
+            Stop::Watchpoint(_, Access { val: 2, .. })
+        ));
+        assert_eq!(1, debugger.cpu_mut().peek(0x10));
+        assert_eq!(Stop::Watchpoint(watch, access), debugger.reverse_resume());
+        assert_eq!(Stop::HistoryStart, debugger.reverse_resume());
+    }
+}
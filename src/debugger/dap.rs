@@ -0,0 +1,724 @@
+///
+/// Debug Adapter Protocol server for editors such as VS Code and Neovim.
+/// Messages are JSON behind a `Content-Length` header, over stdio or a
+/// localhost TCP port. A board with a console on stdout wants TCP.
+///
+/// `launch` and `attach` take the same arguments:
+///
+/// ```json
+/// {
+///     "machine": "board.toml",
+///     "program": "hello.bin",
+///     "loadAddress": "$0200",
+///     "dbgfile": "hello.dbg",
+///     "stopOnEntry": true
+/// }
+/// ```
+///
+/// `machine` falls back to the description the server was started with,
+/// `program` is loaded over the board and execution starts at its load
+/// address, `dbgfile` is the `ld65 --dbgfile` output source breakpoints
+/// are resolved with. Launch runs once configuration is done unless asked
+/// to stop on entry, attach always stops first.
+///
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+};
+
+use serde_json::{json, Value};
+
+use super::{dbginfo::DebugInfo, dbginfo::SourceLine, Debugger, Stop};
+use crate::{
+    machine::description,
+    mos6502::{
+        history::{DEFAULT_BUDGET, DEFAULT_SNAPSHOT_INTERVAL},
+        HaltReason,
+    },
+};
+
+pub const DEFAULT_PORT: u16 = 4711;
+
+const THREAD_ID: u64 = 1;
+
+// instructions run between looks at the incoming requests
+const SLICE: u32 = 10_000;
+
+const JSR: u8 = 0x20;
+const STACK_PAGE: u16 = 0x0100;
+
+const REGISTERS_REFERENCE: u64 = 1;
+const FLAGS_REFERENCE: u64 = 2;
+const ZERO_PAGE_REFERENCE: u64 = 3;
+
+const FLAGS: [(&str, u8); 7] = [
+    ("N", 0b10000000),
+    ("V", 0b01000000),
+    ("B", 0b00010000),
+    ("D", 0b00001000),
+    ("I", 0b00000100),
+    ("Z", 0b00000010),
+    ("C", 0b00000001),
+];
+
+///
+/// serve one client until it disconnects, `machine` is the description
+/// used when a launch request names none
+///
+pub fn serve(
+    input: impl Read + Send + 'static,
+    output: impl Write,
+    machine: Option<PathBuf>,
+) -> io::Result<()> {
+    let (sender, messages) = mpsc::channel();
+    thread::spawn(move || read_messages(input, sender));
+    Session {
+        output,
+        seq: 0,
+        machine,
+        target: None,
+        run: None,
+        source_breakpoints: HashMap::new(),
+        pending: None,
+    }
+    .serve(messages)
+}
+
+struct Target {
+    debugger: Debugger,
+    info: Option<DebugInfo>,
+    stop_on_entry: bool,
+}
+
+///
+/// what the target is running towards
+///
+enum Run {
+    Continue,
+    StepIn { from: Option<SourceLine> },
+    StepOver { from: Option<SourceLine>, sp: u8 },
+    StepOut { sp: u8 },
+}
+
+struct Session<W: Write> {
+    output: W,
+    seq: u64,
+    machine: Option<PathBuf>,
+    target: Option<Target>,
+    run: Option<Run>,
+    source_breakpoints: HashMap<PathBuf, Vec<u16>>,
+    pending: Option<(String, Value)>, // event to send after the response
+}
+
+impl<W: Write> Session<W> {
+    fn serve(&mut self, messages: Receiver<Value>) -> io::Result<()> {
+        loop {
+            let message = if self.run.is_some() {
+                self.run_slice()?;
+                match messages.try_recv() {
+                    Ok(message) => message,
+                    Err(TryRecvError::Empty) => continue,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match messages.recv() {
+                    Ok(message) => message,
+                    Err(_) => return Ok(()),
+                }
+            };
+            if message["type"] == "request" && !self.request(&message)? {
+                return Ok(());
+            }
+        }
+    }
+
+    ///
+    /// answer one request, false once the client is gone
+    ///
+    fn request(&mut self, request: &Value) -> io::Result<bool> {
+        let args = &request["arguments"];
+        let body = match request["command"].as_str().unwrap_or_default() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsStepBack": true,
+            })),
+            "launch" | "attach" => self.start(request, args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                self.respond(request, Ok(json!({})))?;
+                let Some(target) = &self.target else {
+                    return Ok(true);
+                };
+                if target.stop_on_entry {
+                    self.stopped("entry", None)?;
+                } else {
+                    self.run = Some(Run::Continue);
+                }
+                return Ok(true);
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "6502" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                scope("Registers", REGISTERS_REFERENCE),
+                scope("Flags", FLAGS_REFERENCE),
+                scope("Zero Page", ZERO_PAGE_REFERENCE),
+            ]})),
+            "variables" => self.variables(args["variablesReference"].as_u64().unwrap_or(0)),
+            "continue" | "next" | "stepIn" | "stepOut" => self.resume(request),
+            "stepBack" | "reverseContinue" => {
+                self.respond(request, Ok(json!({})))?;
+                return self.reverse(request["command"] == "stepBack").map(|_| true);
+            }
+            "pause" => {
+                self.respond(request, Ok(json!({})))?;
+                if self.run.take().is_some() {
+                    self.stopped("pause", None)?;
+                }
+                return Ok(true);
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(json!({})))?;
+                return Ok(false);
+            }
+            command => Err(format!("{} is not supported", command)),
+        };
+        self.respond(request, body)?;
+        Ok(true)
+    }
+
+    fn start(&mut self, request: &Value, args: &Value) -> Result<Value, String> {
+        let machine = args["machine"]
+            .as_str()
+            .map(PathBuf::from)
+            .or_else(|| self.machine.clone())
+            .ok_or("no machine description to start")?;
+        let mut cpu = description::load(&machine)
+            .map_err(|err| format!("cannot build {}: {}", machine.display(), err))?;
+        if let Some(program) = args["program"].as_str() {
+            let address =
+                parse_address(&args["loadAddress"]).ok_or("the program needs a loadAddress")?;
+            let image =
+                fs::read(program).map_err(|err| format!("cannot load {}: {}", program, err))?;
+            cpu.load(address, &image);
+        }
+        let info = match args["dbgfile"].as_str() {
+            Some(path) => Some(
+                DebugInfo::load(Path::new(path))
+                    .map_err(|err| format!("cannot read {}: {}", path, err))?,
+            ),
+            None => None,
+        };
+        cpu.record_history(DEFAULT_BUDGET, DEFAULT_SNAPSHOT_INTERVAL);
+        self.target = Some(Target {
+            debugger: Debugger::new(cpu),
+            info,
+            stop_on_entry: request["command"] == "attach" || args["stopOnEntry"] == true,
+        });
+        self.source_breakpoints.clear();
+        self.run = None;
+        // the client answers with its breakpoints and configurationDone
+        self.pending_event("initialized", json!({}));
+        Ok(json!({}))
+    }
+
+    ///
+    /// the lines of one source file, replacing those set before
+    ///
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let target = self.target.as_mut().ok_or("nothing launched")?;
+        let path = PathBuf::from(args["source"]["path"].as_str().unwrap_or_default());
+        for address in self.source_breakpoints.remove(&path).unwrap_or_default() {
+            target.debugger.remove_breakpoint(address);
+        }
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = breakpoint["line"].as_u64().unwrap_or(0);
+            let address = target
+                .info
+                .as_ref()
+                .and_then(|info| info.address_of(&path, line as u32));
+            match address {
+                Some(address) => {
+                    target.debugger.add_breakpoint(address);
+                    addresses.push(address);
+                    breakpoints.push(json!({ "verified": true, "line": line }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no code for this line in the debug info",
+                })),
+            }
+        }
+        self.source_breakpoints.insert(path, addresses);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn resume(&mut self, request: &Value) -> Result<Value, String> {
+        let target = self.target.as_ref().ok_or("nothing launched")?;
+        let registers = target.debugger.cpu().registers();
+        let from = target
+            .info
+            .as_ref()
+            .and_then(|info| info.line_at(registers.pc));
+        self.run = Some(match request["command"].as_str() {
+            Some("next") => Run::StepOver {
+                from,
+                sp: registers.sp,
+            },
+            Some("stepIn") => Run::StepIn { from },
+            Some("stepOut") => Run::StepOut { sp: registers.sp },
+            _ => Run::Continue,
+        });
+        Ok(json!({ "allThreadsContinued": true }))
+    }
+
+    ///
+    /// run through the history: back over one line, or back to the previous
+    /// breakpoint
+    ///
+    fn reverse(&mut self, step: bool) -> io::Result<()> {
+        let Some(target) = self.target.as_mut() else {
+            return Ok(());
+        };
+        self.run = None;
+        if !step {
+            let stop = target.debugger.reverse_resume();
+            return self.report(stop, "step");
+        }
+        let registers = target.debugger.cpu().registers();
+        let from = target
+            .info
+            .as_ref()
+            .and_then(|info| info.line_at(registers.pc));
+        let goal = Run::StepOver {
+            from,
+            sp: registers.sp,
+        };
+        loop {
+            let stop = target.debugger.reverse_step();
+            if stop != Stop::Stepped || reached(target, &goal) {
+                return self.report(stop, "step");
+            }
+        }
+    }
+
+    ///
+    /// run up to a slice of instructions towards the current goal
+    ///
+    fn run_slice(&mut self) -> io::Result<()> {
+        let (Some(target), Some(goal)) = (self.target.as_mut(), self.run.as_ref()) else {
+            self.run = None;
+            return Ok(());
+        };
+        for _ in 0..SLICE {
+            let stop = target.debugger.step();
+            let pc = target.debugger.cpu().registers().pc;
+            if stop == Stop::Stepped && target.debugger.has_breakpoint(pc) {
+                self.run = None;
+                return self.report(Stop::Breakpoint(pc), "breakpoint");
+            }
+            if stop != Stop::Stepped || reached(target, goal) {
+                self.run = None;
+                return self.report(stop, "step");
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// tell the client why the target stopped, `reason` for plain steps
+    ///
+    fn report(&mut self, stop: Stop, reason: &str) -> io::Result<()> {
+        match stop {
+            Stop::Halted(HaltReason::Exit(code)) => {
+                self.event("exited", json!({ "exitCode": code }))?;
+                self.event("terminated", json!({}))
+            }
+            Stop::Halted(HaltReason::AccessViolation(violation)) => {
+                self.stopped("exception", Some(violation.to_string()))
+            }
+            Stop::Halted(HaltReason::Stopped) => {
+                self.stopped("exception", Some("the cpu stopped".to_string()))
+            }
+            Stop::Halted(HaltReason::Diverged(cycle)) => self.stopped(
+                "exception",
+                Some(format!("the replay diverged at cycle {}", cycle)),
+            ),
+            Stop::Breakpoint(_) => self.stopped("breakpoint", None),
+            Stop::Watchpoint(..) => self.stopped("data breakpoint", None),
+            Stop::HistoryStart => {
+                self.stopped("step", Some("start of the recorded history".to_string()))
+            }
+            Stop::Stepped | Stop::Limit => self.stopped(reason, None),
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> io::Result<()> {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body)
+    }
+
+    ///
+    /// frames found by walking the stack page for return addresses that
+    /// point just past a JSR. Editors ask on every stop, so memory is only
+    /// looked at and bytes pointing into devices are no return addresses.
+    ///
+    fn stack_trace(&mut self) -> Result<Value, String> {
+        let target = self.target.as_ref().ok_or("nothing launched")?;
+        let cpu = target.debugger.cpu();
+        let registers = cpu.registers();
+        let mut addresses = vec![registers.pc];
+        let mut offset = registers.sp as u16 + 1;
+        while offset < 0xff {
+            let lsb = cpu.peek_quiet(STACK_PAGE | offset);
+            let msb = cpu.peek_quiet(STACK_PAGE | (offset + 1));
+            let call = u16::from_le_bytes([lsb, msb]).wrapping_sub(2);
+            if !cpu.device_mapped(call) && cpu.peek_quiet(call) == JSR {
+                addresses.push(call);
+                offset += 2;
+            } else {
+                offset += 1;
+            }
+        }
+        let frames: Vec<Value> = addresses
+            .iter()
+            .enumerate()
+            .map(|(id, address)| frame(target.info.as_ref(), id, *address))
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&mut self, reference: u64) -> Result<Value, String> {
+        let target = self.target.as_ref().ok_or("nothing launched")?;
+        let registers = target.debugger.cpu().registers();
+        let variables: Vec<Value> = match reference {
+            REGISTERS_REFERENCE => vec![
+                variable("A", format!("${:02x}", registers.ac)),
+                variable("X", format!("${:02x}", registers.xr)),
+                variable("Y", format!("${:02x}", registers.yr)),
+                variable("SP", format!("${:02x}", registers.sp)),
+                variable("PC", format!("${:04x}", registers.pc)),
+                variable("P", format!("${:02x}", registers.sr)),
+            ],
+            FLAGS_REFERENCE => FLAGS
+                .iter()
+                .map(|(name, mask)| variable(name, ((registers.sr & mask != 0) as u8).to_string()))
+                .collect(),
+            ZERO_PAGE_REFERENCE => (0..16u16)
+                .map(|row| {
+                    let cpu = target.debugger.cpu();
+                    let bytes: Vec<String> = (0..16)
+                        .map(|column| format!("{:02x}", cpu.peek_quiet(row << 4 | column)))
+                        .collect();
+                    variable(&format!("${:02x}", row << 4), bytes.join(" "))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)?;
+        self.flush_pending()
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    ///
+    /// an event that has to follow the response being prepared
+    ///
+    fn pending_event(&mut self, event: &str, body: Value) {
+        self.pending = Some((event.to_string(), body));
+    }
+
+    fn flush_pending(&mut self) -> io::Result<()> {
+        match self.pending.take() {
+            Some((event, body)) => self.event(&event, body),
+            None => Ok(()),
+        }
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+}
+
+///
+/// whether the last instruction completed the step the client asked for.
+/// Without debug info every instruction is a line of its own.
+///
+fn reached(target: &Target, goal: &Run) -> bool {
+    let registers = target.debugger.cpu().registers();
+    let line_changed = |from: &Option<SourceLine>| match &target.info {
+        Some(info) => info
+            .line_at(registers.pc)
+            .is_some_and(|line| Some(&line) != from.as_ref()),
+        None => true,
+    };
+    match goal {
+        Run::Continue => false,
+        Run::StepIn { from } => line_changed(from),
+        // deeper in the stack means inside a subroutine called from the line
+        Run::StepOver { from, sp } => registers.sp >= *sp && line_changed(from),
+        Run::StepOut { sp } => registers.sp > *sp,
+    }
+}
+
+fn frame(info: Option<&DebugInfo>, id: usize, address: u16) -> Value {
+    let name = match info.and_then(|info| info.label_before(address)) {
+        Some((label, 0)) => label.to_string(),
+        Some((label, offset)) => format!("{}+{}", label, offset),
+        None => format!("${:04x}", address),
+    };
+    let mut frame = json!({
+        "id": id,
+        "name": name,
+        "line": 0,
+        "column": 0,
+        "instructionPointerReference": format!("0x{:04x}", address),
+    });
+    if let Some(line) = info.and_then(|info| info.line_at(address)) {
+        let file_name = line.file.file_name().unwrap_or_default().to_string_lossy();
+        frame["source"] = json!({ "name": file_name, "path": line.file });
+        frame["line"] = json!(line.line);
+        frame["column"] = json!(1);
+    }
+    frame
+}
+
+fn scope(name: &str, reference: u64) -> Value {
+    json!({ "name": name, "variablesReference": reference, "expensive": false })
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+///
+/// a number, or a string in hex with a `$` or `0x` prefix
+///
+fn parse_address(value: &Value) -> Option<u16> {
+    if let Some(number) = value.as_u64() {
+        return u16::try_from(number).ok();
+    }
+    let text = value.as_str()?;
+    let digits = text.strip_prefix('$').or_else(|| text.strip_prefix("0x"))?;
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn read_messages(input: impl Read, sender: Sender<Value>) {
+    let mut input = BufReader::new(input);
+    while let Ok(Some(message)) = read_message(&mut input) {
+        if sender.send(message).is_err() {
+            return;
+        }
+    }
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() && len.is_some() {
+            break;
+        }
+        if let Some(val) = header.strip_prefix("Content-Length:") {
+            len = val.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; len.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    let message =
+        serde_json::from_slice(&body).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+    Ok(Some(message))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Cursor};
+
+    use super::*;
+    use crate::{
+        device::{Acia, BufferHost},
+        mos6502::{Mos6502, Registers},
+    };
+
+    fn framed(requests: &[Value]) -> Vec<u8> {
+        let mut input = Vec::new();
+        for (seq, request) in requests.iter().enumerate() {
+            let mut request = request.clone();
+            request["seq"] = json!(seq + 1);
+            request["type"] = json!("request");
+            write_message(&mut input, &request).unwrap();
+        }
+        input
+    }
+
+    fn messages(output: &[u8]) -> Vec<Value> {
+        let mut output = Cursor::new(output);
+        std::iter::from_fn(|| read_message(&mut output).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_framing_round_trips() {
+        let mut output = Vec::new();
+        write_message(&mut output, &json!({ "seq": 1 })).unwrap();
+        assert!(output.starts_with(b"Content-Length: 9\r\n\r\n"));
+        assert_eq!(vec![json!({ "seq": 1 })], messages(&output));
+    }
+
+    #[test]
+    fn test_load_address_forms() {
+        assert_eq!(Some(0x0200), parse_address(&json!(512)));
+        assert_eq!(Some(0x0200), parse_address(&json!("$0200")));
+        assert_eq!(Some(0xc000), parse_address(&json!("0xc000")));
+        assert_eq!(None, parse_address(&json!("0200")));
+    }
+
+    #[test]
+    fn test_source_breakpoint_and_stack() {
+        let dir = env::temp_dir().join(format!("martian6502-dap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("board.toml"), "").unwrap();
+        // main: jsr sub, jmp main; sub: inx, rts
+        fs::write(
+            dir.join("main.bin"),
+            [0x20, 0x06, 0x02, 0x4c, 0x00, 0x02, 0xe8, 0x60],
+        )
+        .unwrap();
+        fs::write(
+            dir.join("main.dbg"),
+            "version\tmajor=2,minor=0
+file\tid=0,name=\"main.s\",size=0,mtime=0x0,mod=0
+seg\tid=0,name=\"CODE\",start=0x000200,size=0x0008,addrsize=absolute,type=ro
+span\tid=0,seg=0,start=0,size=3
+span\tid=1,seg=0,start=6,size=1
+line\tid=0,file=0,line=2,span=0
+line\tid=1,file=0,line=5,span=1
+sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=0,val=0x200,seg=0,type=lab
+sym\tid=1,name=\"sub\",addrsize=absolute,scope=0,def=1,val=0x206,seg=0,type=lab
+",
+        )
+        .unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let input = framed(&[
+            json!({ "command": "initialize", "arguments": {} }),
+            json!({ "command": "launch", "arguments": {
+                "machine": path("board.toml"),
+                "program": path("main.bin"),
+                "loadAddress": "$0200",
+                "dbgfile": path("main.dbg"),
+            }}),
+            json!({ "command": "setBreakpoints", "arguments": {
+                "source": { "path": path("main.s") },
+                "breakpoints": [{ "line": 5 }, { "line": 3 }],
+            }}),
+            json!({ "command": "configurationDone" }),
+            json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+            json!({ "command": "disconnect" }),
+        ]);
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output, None).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let messages = messages(&output);
+        let reply = |command: &str| {
+            messages
+                .iter()
+                .find(|message| message["command"] == command)
+                .unwrap()
+                .clone()
+        };
+        let breakpoints = &reply("setBreakpoints")["body"]["breakpoints"];
+        assert_eq!(
+            json!([true, false]),
+            json!([breakpoints[0]["verified"], breakpoints[1]["verified"]])
+        );
+        assert!(messages
+            .iter()
+            .any(|message| message["event"] == "stopped"
+                && message["body"]["reason"] == "breakpoint"));
+        let frames = &reply("stackTrace")["body"]["stackFrames"];
+        assert_eq!("sub", frames[0]["name"]);
+        assert_eq!(5, frames[0]["line"]);
+        assert_eq!("main", frames[1]["name"]);
+        assert_eq!(2, frames[1]["line"]);
+    }
+
+    #[test]
+    fn test_stack_trace_leaves_devices_alone() {
+        let host = BufferHost::default();
+        host.type_bytes(b"x");
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &[0xea, 0xea]);
+        cpu.attach(0xc000, Box::new(Acia::new(Box::new(host), 1_000_000)));
+        cpu.poke(0xc003, 0x1f);
+        cpu.poke(0xc002, 0x0b);
+        cpu.run_until(2);
+        // a return address just past the ACIA data register
+        cpu.set_registers(Registers {
+            pc: 0x0202,
+            sp: 0xfd,
+            ..Registers::default()
+        });
+        cpu.poke(0x01fe, 0x02);
+        cpu.poke(0x01ff, 0xc0);
+        let mut session = Session {
+            output: Vec::new(),
+            seq: 0,
+            machine: None,
+            target: Some(Target {
+                debugger: Debugger::new(cpu),
+                info: None,
+                stop_on_entry: false,
+            }),
+            run: None,
+            source_breakpoints: HashMap::new(),
+            pending: None,
+        };
+        let frames = session.stack_trace().unwrap();
+        assert_eq!(1, frames["totalFrames"]);
+        session.variables(ZERO_PAGE_REFERENCE).unwrap();
+        let status = session.target.unwrap().debugger.cpu().peek_quiet(0xc001);
+        assert_eq!(0x08, status & 0x08); // RDRF
+    }
+}
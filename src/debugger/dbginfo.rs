@@ -0,0 +1,390 @@
+///
+/// ca65 debug information as written by `ld65 --dbgfile`. Each line is a
+/// record type followed by a tab and comma separated `key=value` pairs:
+///
+/// ```text
+/// file    id=0,name="main.s",size=312,mtime=0x6530a1f2,mod=0
+/// seg     id=0,name="CODE",start=0x000200,size=0x0024,addrsize=absolute,type=ro
+/// span    id=3,seg=0,start=4,size=3
+/// line    id=7,file=0,line=12,span=3
+/// sym     id=1,name="loop",addrsize=absolute,scope=0,def=5,val=0x204,seg=0,type=lab
+/// ```
+///
+/// Only what source level debugging needs is kept: which source line the
+/// bytes at an address came from, and the labels.
+///
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::mos6502::coverage::Coverage;
+
+// line records of `.dbg line` C sources and of macro expansions
+const LINE_TYPE_EXTERNAL: u32 = 1;
+const LINE_TYPE_MACRO: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: PathBuf,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LineSpan {
+    file: usize, // index into `files`
+    line: u32,
+    start: u16,
+    end: u16, // inclusive
+    external: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct DebugInfo {
+    files: Vec<PathBuf>,
+    lines: Vec<LineSpan>,
+    labels: Vec<(u16, String)>, // sorted by address
+}
+
+impl DebugInfo {
+    ///
+    /// source file names are taken relative to the directory of `path`,
+    /// which is where ld65 usually runs
+    ///
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    pub fn parse(text: &str, base: &Path) -> io::Result<Self> {
+        let mut files = HashMap::new();
+        let mut segments = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut lines = Vec::new();
+        let mut symbols = Vec::new();
+        for (number, record) in text.lines().enumerate() {
+            let Some((kind, fields)) = record.split_once('\t') else {
+                continue;
+            };
+            let fields = Fields::parse(fields)
+                .ok_or_else(|| invalid(format!("line {}: malformed record", number + 1)))?;
+            match kind {
+                "version" if fields.number("major") != Some(2) => {
+                    return Err(invalid(
+                        "only version 2 debug info is understood".to_string(),
+                    ));
+                }
+                "file" => {
+                    let name = fields.text("name").unwrap_or_default();
+                    files.insert(fields.id(), base.join(name));
+                }
+                "seg" => {
+                    segments.insert(fields.id(), fields.number("start").unwrap_or(0));
+                }
+                "span" => {
+                    let seg = fields.number("seg").unwrap_or(0);
+                    let start = fields.number("start").unwrap_or(0);
+                    let size = fields.number("size").unwrap_or(1).max(1);
+                    spans.insert(fields.id(), (seg, start, size));
+                }
+                "line" => lines.push(fields),
+                "sym" if fields.text("type") == Some("lab") => symbols.push(fields),
+                _ => {}
+            }
+        }
+
+        let mut info = DebugInfo::default();
+        let mut file_index = HashMap::new();
+        for (id, file) in files {
+            file_index.insert(id, info.files.len());
+            info.files.push(file);
+        }
+        for line in lines {
+            let kind = line.number("type").unwrap_or(0);
+            if kind == LINE_TYPE_MACRO {
+                continue;
+            }
+            let (Some(&file), Some(number)) = (
+                line.number("file").and_then(|id| file_index.get(&id)),
+                line.number("line"),
+            ) else {
+                continue;
+            };
+            for span in line.list("span") {
+                let Some(&(seg, start, size)) = spans.get(&span) else {
+                    continue;
+                };
+                let start = segments.get(&seg).copied().unwrap_or(0) + start;
+                info.lines.push(LineSpan {
+                    file,
+                    line: number,
+                    start: start as u16,
+                    end: (start + size - 1) as u16,
+                    external: kind == LINE_TYPE_EXTERNAL,
+                });
+            }
+        }
+        for symbol in symbols {
+            if let (Some(name), Some(val)) = (symbol.text("name"), symbol.number("val")) {
+                info.labels.push((val as u16, name.to_string()));
+            }
+        }
+        info.labels.sort();
+        Ok(info)
+    }
+
+    ///
+    /// the line whose code covers `address`, C lines win over the assembly
+    /// cc65 generated for them
+    ///
+    pub fn line_at(&self, address: u16) -> Option<SourceLine> {
+        self.lines
+            .iter()
+            .filter(|span| span.start <= address && address <= span.end)
+            .max_by_key(|span| (span.external, span.start))
+            .map(|span| SourceLine {
+                file: self.files[span.file].clone(),
+                line: span.line,
+            })
+    }
+
+    ///
+    /// first address of the code generated for `line` of `file`, a file
+    /// given by a bare or relative name matches the end of the full path
+    ///
+    pub fn address_of(&self, file: &Path, line: u32) -> Option<u16> {
+        self.lines
+            .iter()
+            .filter(|span| span.line == line)
+            .filter(|span| {
+                let known = &self.files[span.file];
+                known == file || known.ends_with(file) || file.ends_with(known)
+            })
+            .map(|span| span.start)
+            .min()
+    }
+
+    ///
+    /// lowest and highest address any line produced bytes for
+    ///
+    pub fn address_range(&self) -> Option<(u16, u16)> {
+        let start = self.lines.iter().map(|span| span.start).min()?;
+        let end = self.lines.iter().map(|span| span.end).max()?;
+        Some((start, end))
+    }
+
+    ///
+    /// per line coverage as an lcov tracefile, returns the lines found and
+    /// the lines hit. A line counts the most times any of its instructions
+    /// ran, lines whose bytes were only used as data are left out.
+    ///
+    pub fn write_lcov(
+        &self,
+        out: &mut dyn Write,
+        coverage: &Coverage,
+    ) -> io::Result<(usize, usize)> {
+        let mut files: BTreeMap<&Path, BTreeMap<u32, u32>> = BTreeMap::new();
+        for span in self.lines.iter() {
+            let addresses = span.start..=span.end;
+            let data = addresses.clone().all(|address| !coverage.is_code(address))
+                && addresses
+                    .clone()
+                    .any(|address| coverage.is_read(address) || coverage.is_written(address));
+            if data {
+                continue;
+            }
+            let count = addresses
+                .map(|address| coverage.executed(address))
+                .max()
+                .unwrap_or(0);
+            let line = files
+                .entry(&self.files[span.file])
+                .or_default()
+                .entry(span.line)
+                .or_default();
+            *line = (*line).max(count);
+        }
+        let (mut found, mut hit) = (0, 0);
+        for (file, lines) in files {
+            writeln!(out, "TN:")?;
+            writeln!(out, "SF:{}", file.display())?;
+            for (line, count) in lines.iter() {
+                writeln!(out, "DA:{},{}", line, count)?;
+            }
+            let lines_hit = lines.values().filter(|count| **count > 0).count();
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(out, "LH:{}", lines_hit)?;
+            writeln!(out, "end_of_record")?;
+            found += lines.len();
+            hit += lines_hit;
+        }
+        Ok((found, hit))
+    }
+
+    ///
+    /// where the label `name` points, the lowest address if it is defined
+    /// more than once
+    ///
+    pub fn label_address(&self, name: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find(|(_, label)| label == name)
+            .map(|(address, _)| *address)
+    }
+
+    ///
+    /// the closest label at or below `address`, with the distance to it
+    ///
+    pub fn label_before(&self, address: u16) -> Option<(&str, u16)> {
+        let index = self.labels.partition_point(|(val, _)| *val <= address);
+        let (val, name) = self.labels.get(index.checked_sub(1)?)?;
+        Some((name, address - val))
+    }
+}
+
+///
+/// the `key=value` pairs of one record
+///
+struct Fields<'a> {
+    pairs: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Fields<'a> {
+    fn parse(text: &'a str) -> Option<Self> {
+        let mut pairs = Vec::new();
+        let mut rest = text;
+        while !rest.is_empty() {
+            let (key, value) = rest.split_once('=')?;
+            let end = if let Some(quoted) = value.strip_prefix('"') {
+                quoted.find('"')? + 2
+            } else {
+                value.find(',').unwrap_or(value.len())
+            };
+            pairs.push((key, &value[..end]));
+            rest = value[end..].strip_prefix(',').unwrap_or(&value[end..]);
+        }
+        Some(Self { pairs })
+    }
+
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.pairs.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    fn id(&self) -> u32 {
+        self.number("id").unwrap_or(0)
+    }
+
+    fn number(&self, key: &str) -> Option<u32> {
+        parse_number(self.get(key)?)
+    }
+
+    fn text(&self, key: &str) -> Option<&'a str> {
+        let value = self.get(key)?;
+        let quoted = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'));
+        Some(quoted.unwrap_or(value))
+    }
+
+    ///
+    /// ids joined with `+`
+    ///
+    fn list(&self, key: &str) -> Vec<u32> {
+        self.get(key)
+            .map(|value| value.split('+').filter_map(parse_number).collect())
+            .unwrap_or_default()
+    }
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DBG: &str = "version\tmajor=2,minor=0
+info\tcsym=0,file=2,lib=0,line=3,mod=1,scope=1,seg=1,span=3,sym=2,type=0
+file\tid=0,name=\"src/main.s\",size=80,mtime=0x00000000,mod=0
+file\tid=1,name=\"src/main.c\",size=40,mtime=0x00000000,mod=0
+seg\tid=0,name=\"CODE\",start=0x000200,size=0x0008,addrsize=absolute,type=ro,oname=\"a.bin\",ooffs=0
+span\tid=0,seg=0,start=0,size=2
+span\tid=1,seg=0,start=2,size=3
+span\tid=2,seg=0,start=0,size=8
+line\tid=0,file=0,line=4,span=0
+line\tid=1,file=0,line=5,span=1
+line\tid=2,file=1,line=9,type=1,span=2
+sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=0,val=0x200,seg=0,type=lab
+sym\tid=1,name=\"SCREEN\",addrsize=absolute,scope=0,def=1,val=0x400,type=equ
+";
+
+    fn info() -> DebugInfo {
+        DebugInfo::parse(DBG, Path::new("/work")).unwrap()
+    }
+
+    #[test]
+    fn test_lines_map_to_addresses() {
+        let info = info();
+        assert_eq!(Some(0x0202), info.address_of(Path::new("main.s"), 5));
+        assert_eq!(
+            Some(0x0200),
+            info.address_of(Path::new("/work/src/main.s"), 4)
+        );
+        assert_eq!(None, info.address_of(Path::new("other.s"), 4));
+    }
+
+    #[test]
+    fn test_c_lines_win() {
+        let line = info().line_at(0x0203).unwrap();
+        assert_eq!(
+            (PathBuf::from("/work/src/main.c"), 9),
+            (line.file, line.line)
+        );
+        assert_eq!(None, info().line_at(0x0208));
+    }
+
+    #[test]
+    fn test_lcov() {
+        use crate::mos6502::{Mos6502, Registers};
+
+        // lda #$01 covers line 4 and the C line, line 5 never runs
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &[0xa9, 0x01, 0xea, 0xea, 0xea]);
+        cpu.set_registers(Registers {
+            pc: 0x0200,
+            ..Registers::default()
+        });
+        cpu.cover();
+        cpu.step();
+        let mut lcov = Vec::new();
+        let counts = info()
+            .write_lcov(&mut lcov, cpu.coverage().unwrap())
+            .unwrap();
+        assert_eq!((3, 2), counts);
+        assert_eq!(
+            "TN:\nSF:/work/src/main.c\nDA:9,1\nLF:1\nLH:1\nend_of_record\n\
+             TN:\nSF:/work/src/main.s\nDA:4,1\nDA:5,0\nLF:2\nLH:1\nend_of_record\n",
+            String::from_utf8(lcov).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_labels_only() {
+        let info = info();
+        assert_eq!(Some(("main", 4)), info.label_before(0x0204));
+        assert_eq!(Some(("main", 0x200)), info.label_before(0x0400));
+        assert_eq!(None, info.label_before(0x01ff));
+        assert_eq!(Some(0x0200), info.label_address("main"));
+        assert_eq!(None, info.label_address("SCREEN"));
+    }
+}
@@ -0,0 +1,527 @@
+///
+/// GDB remote serial protocol server. GDB has no 6502 target of its own, the
+/// registers are described to it through target.xml in the order A, X, Y,
+/// SP, PC, P; PC is 16 bits little endian, the others 8 bits.
+///
+/// Breakpoints of both kinds are the same thing here, watchpoints cover the
+/// length GDB asks for. With a recorded history `bs` and `bc` run backwards.
+///
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+};
+
+use super::{Debugger, Stop, WatchKind, Watchpoint};
+use crate::mos6502::{AccessKind, HaltReason, Registers};
+
+pub const DEFAULT_PORT: u16 = 6502;
+
+const PACKET_SIZE: usize = 0x1000;
+
+// instructions between looks at the connection for an interrupt request
+const INTERRUPT_POLL: u64 = 10_000;
+const INTERRUPT: u8 = 0x03;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.martian6502.cpu">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+///
+/// what a packet asks for beyond its reply
+///
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    Packet(String),
+    Step,
+    Resume,
+    ReverseStep,
+    ReverseResume,
+    Close(Option<String>), // the last packet to send
+}
+
+///
+/// serve one GDB connection on the localhost `port`, returns once GDB
+/// detaches, kills the target or hangs up
+///
+pub fn serve(debugger: &mut Debugger, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    Session {
+        debugger,
+        stream,
+        last_stop: Stop::Stepped,
+        ack: true,
+    }
+    .run()
+}
+
+struct Session<'a> {
+    debugger: &'a mut Debugger,
+    stream: TcpStream,
+    last_stop: Stop,
+    ack: bool, // until GDB switches to no ack mode
+}
+
+impl Session<'_> {
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            let stop = match handle(self.debugger, self.last_stop, &packet) {
+                Reply::Packet(reply) => {
+                    self.send(&reply)?;
+                    if packet == "QStartNoAckMode" {
+                        self.ack = false;
+                    }
+                    continue;
+                }
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        self.send(&reply)?;
+                    }
+                    return Ok(());
+                }
+                Reply::Step => self.debugger.step(),
+                Reply::ReverseStep => self.debugger.reverse_step(),
+                Reply::ReverseResume => self.debugger.reverse_resume(),
+                Reply::Resume => self.resume()?,
+            };
+            self.last_stop = stop;
+            self.send(&stop_reply(stop))?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// run in slices, GDB sends a lone 0x03 to interrupt
+    ///
+    fn resume(&mut self) -> io::Result<Stop> {
+        loop {
+            let stop = self.debugger.resume_for(INTERRUPT_POLL);
+            if stop != Stop::Limit {
+                return Ok(stop);
+            }
+            // an interrupted resume reports running out of its budget
+            self.stream.set_nonblocking(true)?;
+            let mut byte = [0];
+            let polled = self.stream.read(&mut byte);
+            self.stream.set_nonblocking(false)?;
+            match polled {
+                Ok(0) => return Err(ErrorKind::ConnectionAborted.into()),
+                Ok(_) if byte[0] == INTERRUPT => return Ok(Stop::Limit),
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    ///
+    /// next packet with its framing and checksum stripped, None once the
+    /// connection closes
+    ///
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+            if byte != b'$' {
+                // acks, and interrupts arriving while already stopped
+                continue;
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let (Some(high), Some(low)) = (self.read_byte()?, self.read_byte()?) else {
+                return Ok(None);
+            };
+            let expected = hex_digit(high).zip(hex_digit(low)).map(|(h, l)| h << 4 | l);
+            if self.ack {
+                if expected != Some(checksum(&data)) {
+                    self.stream.write_all(b"-")?;
+                    continue;
+                }
+                self.stream.write_all(b"+")?;
+            }
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(err) if err.kind() == ErrorKind::ConnectionReset => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        self.stream.write_all(&frame(data))
+    }
+}
+
+///
+/// reply to one packet, running the target is left to the caller
+///
+fn handle(debugger: &mut Debugger, last_stop: Stop, packet: &str) -> Reply {
+    // a byte GDB garbled can turn into a multibyte replacement character
+    let command_len = packet.chars().next().map_or(0, char::len_utf8);
+    let (command, args) = packet.split_at(command_len);
+    let reply = match command {
+        "?" => Some(stop_reply(last_stop)),
+        "g" => Some(read_registers(debugger.cpu().registers())),
+        "G" => write_registers(debugger, args),
+        "p" => read_register(debugger, args),
+        "P" => write_register(debugger, args),
+        "m" => read_memory(debugger, args),
+        "M" => write_memory(debugger, args),
+        "s" | "c" => {
+            if !args.is_empty() {
+                let Some(pc) = parse_hex(args) else {
+                    return Reply::Packet("E01".to_string());
+                };
+                let mut registers = debugger.cpu().registers();
+                registers.pc = pc;
+                debugger.cpu_mut().set_registers(registers);
+            }
+            return if command == "s" {
+                Reply::Step
+            } else {
+                Reply::Resume
+            };
+        }
+        "b" if args == "s" => return Reply::ReverseStep,
+        "b" if args == "c" => return Reply::ReverseResume,
+        "Z" | "z" => point(debugger, command == "Z", args),
+        "H" | "T" => Some("OK".to_string()),
+        "D" => return Reply::Close(Some("OK".to_string())),
+        "k" => return Reply::Close(None),
+        _ => query(packet),
+    };
+    Reply::Packet(reply.unwrap_or_else(|| "E01".to_string()))
+}
+
+///
+/// general queries, unknown ones get the empty reply GDB expects
+///
+fn query(packet: &str) -> Option<String> {
+    if packet.starts_with("qSupported") {
+        return Some(format!(
+            "PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+;QStartNoAckMode+;\
+             ReverseStep+;ReverseContinue+",
+            PACKET_SIZE
+        ));
+    }
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        let (offset, len) = range.split_once(',')?;
+        let offset = usize::from_str_radix(offset, 16)
+            .ok()?
+            .min(TARGET_XML.len());
+        let len = usize::from_str_radix(len, 16).ok()?;
+        let chunk = &TARGET_XML[offset..offset.saturating_add(len).min(TARGET_XML.len())];
+        let more = if offset + chunk.len() < TARGET_XML.len() {
+            'm'
+        } else {
+            'l'
+        };
+        return Some(format!("{}{}", more, chunk));
+    }
+    let reply = match packet {
+        "QStartNoAckMode" => "OK",
+        "qAttached" => "1",
+        "qC" => "QC1",
+        "qfThreadInfo" => "m1",
+        "qsThreadInfo" => "l",
+        _ => "",
+    };
+    Some(reply.to_string())
+}
+
+fn stop_reply(stop: Stop) -> String {
+    match stop {
+        Stop::Stepped => format!("S{:02x}", SIGTRAP),
+        Stop::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+        Stop::Watchpoint(watch, access) => {
+            let kind = match (watch.kind, access.kind) {
+                (WatchKind::Access, _) => "awatch",
+                (_, AccessKind::Read) => "rwatch",
+                _ => "watch",
+            };
+            format!("T{:02x}{}:{:04x};", SIGTRAP, kind, access.address)
+        }
+        Stop::Halted(HaltReason::Exit(code)) => format!("W{:02x}", code),
+        Stop::Halted(HaltReason::AccessViolation(_)) => format!("S{:02x}", SIGSEGV),
+        Stop::Halted(HaltReason::Stopped | HaltReason::Diverged(_)) => format!("S{:02x}", SIGTRAP),
+        Stop::HistoryStart => format!("T{:02x}replaylog:begin;", SIGTRAP),
+        Stop::Limit => format!("S{:02x}", SIGINT),
+    }
+}
+
+fn read_registers(registers: Registers) -> String {
+    let [pc_lsb, pc_msb] = registers.pc.to_le_bytes();
+    to_hex(&[
+        registers.ac,
+        registers.xr,
+        registers.yr,
+        registers.sp,
+        pc_lsb,
+        pc_msb,
+        registers.sr,
+    ])
+}
+
+fn write_registers(debugger: &mut Debugger, args: &str) -> Option<String> {
+    let bytes = from_hex(args)?;
+    let [ac, xr, yr, sp, pc_lsb, pc_msb, sr] = bytes.as_slice() else {
+        return None;
+    };
+    debugger.cpu_mut().set_registers(Registers {
+        pc: u16::from_le_bytes([*pc_lsb, *pc_msb]),
+        sp: *sp,
+        ac: *ac,
+        xr: *xr,
+        yr: *yr,
+        sr: *sr,
+    });
+    Some("OK".to_string())
+}
+
+fn read_register(debugger: &Debugger, args: &str) -> Option<String> {
+    let registers = debugger.cpu().registers();
+    let reply = match parse_hex(args)? {
+        0 => to_hex(&[registers.ac]),
+        1 => to_hex(&[registers.xr]),
+        2 => to_hex(&[registers.yr]),
+        3 => to_hex(&[registers.sp]),
+        4 => to_hex(&registers.pc.to_le_bytes()),
+        5 => to_hex(&[registers.sr]),
+        _ => return None,
+    };
+    Some(reply)
+}
+
+fn write_register(debugger: &mut Debugger, args: &str) -> Option<String> {
+    let (number, val) = args.split_once('=')?;
+    let val = from_hex(val)?;
+    let byte = *val.first()?;
+    let mut registers = debugger.cpu().registers();
+    match parse_hex(number)? {
+        0 => registers.ac = byte,
+        1 => registers.xr = byte,
+        2 => registers.yr = byte,
+        3 => registers.sp = byte,
+        4 => registers.pc = u16::from_le_bytes([byte, val.get(1).copied().unwrap_or(0)]),
+        5 => registers.sr = byte,
+        _ => return None,
+    }
+    debugger.cpu_mut().set_registers(registers);
+    Some("OK".to_string())
+}
+
+///
+/// gdb reads memory on every stop and while unwinding, so device registers
+/// are only looked at
+///
+fn read_memory(debugger: &Debugger, args: &str) -> Option<String> {
+    let (address, len) = args.split_once(',')?;
+    let address = parse_hex(address)?;
+    let len = usize::from_str_radix(len, 16).ok()?.min(PACKET_SIZE / 2);
+    let cpu = debugger.cpu();
+    let bytes: Vec<u8> = (0..len)
+        .map(|offset| cpu.peek_quiet(address.wrapping_add(offset as u16)))
+        .collect();
+    Some(to_hex(&bytes))
+}
+
+fn write_memory(debugger: &mut Debugger, args: &str) -> Option<String> {
+    let (range, data) = args.split_once(':')?;
+    let (address, _) = range.split_once(',')?;
+    let address = parse_hex(address)?;
+    for (offset, byte) in from_hex(data)?.into_iter().enumerate() {
+        debugger
+            .cpu_mut()
+            .deposit(address.wrapping_add(offset as u16), byte);
+    }
+    Some("OK".to_string())
+}
+
+///
+/// `Z<type>,<address>,<kind>` inserts, `z` removes
+///
+fn point(debugger: &mut Debugger, insert: bool, args: &str) -> Option<String> {
+    let mut fields = args.split(',');
+    let kind = fields.next()?;
+    let address = parse_hex(fields.next()?)?;
+    let len = parse_hex(fields.next()?)?.max(1);
+    let kind = match kind {
+        "0" | "1" => {
+            if insert {
+                debugger.add_breakpoint(address);
+            } else {
+                debugger.remove_breakpoint(address);
+            }
+            return Some("OK".to_string());
+        }
+        "2" => WatchKind::Write,
+        "3" => WatchKind::Read,
+        "4" => WatchKind::Access,
+        _ => return Some(String::new()),
+    };
+    let watchpoint = Watchpoint {
+        start: address,
+        end: address.saturating_add(len - 1),
+        kind,
+    };
+    if insert {
+        debugger.add_watchpoint(watchpoint);
+    } else {
+        debugger.remove_watchpoint(watchpoint);
+    }
+    Some("OK".to_string())
+}
+
+fn frame(data: &str) -> Vec<u8> {
+    format!("${}#{:02x}", data, checksum(data.as_bytes())).into_bytes()
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|digit| digit as u8)
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    let digits = text.as_bytes();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| Some(hex_digit(pair[0])? << 4 | hex_digit(pair[1])?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{Acia, BufferHost};
+    use crate::mos6502::Mos6502;
+
+    fn debugger() -> Debugger {
+        let mut cpu = Mos6502::default();
+        // lda #$42, sta $10
+        cpu.load(0x0200, &[0xa9, 0x42, 0x85, 0x10]);
+        Debugger::new(cpu)
+    }
+
+    fn reply(debugger: &mut Debugger, packet: &str) -> Reply {
+        handle(debugger, Stop::Stepped, packet)
+    }
+
+    fn text(text: &str) -> Reply {
+        Reply::Packet(text.to_string())
+    }
+
+    #[test]
+    fn test_frame_checksum() {
+        assert_eq!(b"$OK#9a".to_vec(), frame("OK"));
+        assert_eq!(b"$#00".to_vec(), frame(""));
+    }
+
+    #[test]
+    fn test_registers_in_target_order() {
+        let mut debugger = debugger();
+        assert_eq!(text("00000000000200"), reply(&mut debugger, "g"));
+        assert_eq!(text("OK"), reply(&mut debugger, "P4=3412"));
+        assert_eq!(text("3412"), reply(&mut debugger, "p4"));
+        assert_eq!(text("OK"), reply(&mut debugger, "G0102030400c0ff"));
+        let registers = debugger.cpu().registers();
+        assert_eq!(
+            (0x01, 0xc000, 0xff),
+            (registers.ac, registers.pc, registers.sr)
+        );
+    }
+
+    #[test]
+    fn test_memory_and_watchpoint_stop() {
+        let mut debugger = debugger();
+        assert_eq!(text("OK"), reply(&mut debugger, "M10,2:aa55"));
+        assert_eq!(text("aa55"), reply(&mut debugger, "m10,2"));
+        assert_eq!(text("OK"), reply(&mut debugger, "Z2,10,1"));
+        assert_eq!(Reply::Resume, reply(&mut debugger, "c"));
+        let stop = debugger.resume();
+        assert_eq!("T05watch:0010;", stop_reply(stop));
+        assert_eq!(text("OK"), reply(&mut debugger, "z2,10,1"));
+        assert!(debugger.watchpoints().is_empty());
+    }
+
+    #[test]
+    fn test_memory_reads_leave_devices_alone() {
+        let host = BufferHost::default();
+        host.type_bytes(b"x");
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &[0xea, 0xea]);
+        cpu.attach(0xc000, Box::new(Acia::new(Box::new(host), 1_000_000)));
+        cpu.poke(0xc003, 0x1f);
+        cpu.poke(0xc002, 0x0b);
+        cpu.run_until(2);
+        let mut debugger = Debugger::new(cpu);
+        // the received byte and RDRF with TDRE, twice
+        assert_eq!(text("7818"), reply(&mut debugger, "mc000,2"));
+        assert_eq!(text("7818"), reply(&mut debugger, "mc000,2"));
+    }
+
+    #[test]
+    fn test_target_xml_is_served_in_pieces() {
+        let Some(first) = query("qXfer:features:read:target.xml:0,10") else {
+            panic!("no reply");
+        };
+        assert_eq!("m<?xml version=\"1", first);
+        let rest = query("qXfer:features:read:target.xml:10,1000").unwrap();
+        assert!(rest.starts_with('l') && rest.ends_with("</target>\n"));
+    }
+
+    #[test]
+    fn test_malformed_packets_are_refused() {
+        let mut debugger = debugger();
+        assert_eq!(text(""), reply(&mut debugger, "\u{fffd}g"));
+        assert_eq!(text(""), reply(&mut debugger, "é"));
+        assert_eq!(text("E01"), reply(&mut debugger, "m10"));
+        assert_eq!(text("E01"), reply(&mut debugger, "Mzz,1:00"));
+        assert_eq!(text("E01"), reply(&mut debugger, "G0102"));
+        assert_eq!(text("E01"), reply(&mut debugger, "p"));
+        assert_eq!(text("E01"), reply(&mut debugger, "sxyz"));
+        let huge = query("qXfer:features:read:target.xml:10,ffffffffffffffff").unwrap();
+        assert!(huge.starts_with('l') && huge.ends_with("</target>\n"));
+    }
+}
@@ -0,0 +1,571 @@
+///
+/// end to end tests written as scripts instead of Rust. A script builds a
+/// machine, drives it and checks what it did, one command per line:
+///
+/// ```text
+/// # everything after a # outside quotes is a comment
+/// machine board.toml        # build from a description, a bare cpu otherwise
+/// console f001              # attach a console to the bare cpu
+/// load hello.bin 0200       # raw image at a hex address
+/// symbols hello.dbg         # ld65 debug info, labels go wherever addresses do
+/// run to $beef              # a $ keeps a label of that name from taking it
+/// poke 0300 01 02 03
+/// set pc=start a=00 sp=ff   # pc, a, x, y, sp and p
+/// reset
+/// type "HELLO\r"            # waits for the console and ACIA devices to take it
+/// timeout 1000000           # cycles a run may take, 10 million unless set
+/// run to done               # until pc reaches an address or label
+/// run cycles 50000          # until the cycle counter reaches a count
+/// expect mem 0300 01 02 03
+/// expect reg a=00 x=03
+/// expect output "HELLO"     # the devices sent this at some point
+/// ```
+///
+/// Paths are relative to the script, the first line that fails ends it.
+///
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    debugger::dbginfo::DebugInfo,
+    device::{BufferHost, Console, SerialHost},
+    machine::description,
+    mos6502::{Mos6502, Registers},
+};
+
+pub const EXTENSION: &str = "script";
+
+const DEFAULT_TIMEOUT: u64 = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+///
+/// run the script at `path`
+///
+pub fn run(path: &Path) -> Result<(), Failure> {
+    let text = fs::read_to_string(path).map_err(|err| Failure {
+        line: 0,
+        message: err.to_string(),
+    })?;
+    run_text(&text, path.parent().unwrap_or(Path::new(".")))
+}
+
+///
+/// run a script given as text, paths in it are relative to `base`
+///
+pub fn run_text(text: &str, base: &Path) -> Result<(), Failure> {
+    let mut session = Session::new(base);
+    for (number, line) in text.lines().enumerate() {
+        let failed = |message| Failure {
+            line: number + 1,
+            message,
+        };
+        let words = split(line).map_err(failed)?;
+        session.command(&words).map_err(failed)?;
+    }
+    Ok(())
+}
+
+///
+/// run the scripts at `paths`, a directory stands for the scripts in it.
+/// Each script gets a line in `report` and the counts close it, true if
+/// every script passed.
+///
+pub fn run_all(paths: &[PathBuf], report: &mut impl io::Write) -> io::Result<bool> {
+    let mut scripts = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            scripts.push(path.clone());
+            continue;
+        }
+        let found = scripts_in(path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("cannot list {}: {}", path.display(), err),
+            )
+        })?;
+        scripts.extend(found);
+    }
+    let mut failed = 0;
+    for path in scripts.iter() {
+        match run(path) {
+            Ok(()) => writeln!(report, "ok {}", path.display())?,
+            Err(failure) => {
+                writeln!(report, "FAIL {}: {}", path.display(), failure)?;
+                failed += 1;
+            }
+        }
+    }
+    writeln!(
+        report,
+        "{} passed, {} failed",
+        scripts.len() - failed,
+        failed
+    )?;
+    Ok(failed == 0)
+}
+
+///
+/// the scripts in `dir`, in the order their names sort
+///
+pub fn scripts_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut scripts = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == EXTENSION)
+        {
+            scripts.push(path);
+        }
+    }
+    scripts.sort();
+    Ok(scripts)
+}
+
+///
+/// a word of a command line, quoted text can hold any byte
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Word {
+    Bare(String),
+    Quoted(Vec<u8>),
+}
+
+fn split(line: &str) -> Result<Vec<Word>, String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            words.push(Word::Quoted(quoted(&mut chars)?));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| !c.is_whitespace() && **c != '#') {
+                word.push(c);
+                chars.next();
+            }
+            words.push(Word::Bare(word));
+        }
+    }
+    Ok(words)
+}
+
+///
+/// text up to the closing quote, with \r, \n, \t, \\, \" and \xNN escapes
+///
+fn quoted(chars: &mut impl Iterator<Item = char>) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    loop {
+        match chars.next() {
+            None => return Err("missing closing quote".to_string()),
+            Some('"') => return Ok(bytes),
+            Some('\\') => match chars.next() {
+                Some('r') => bytes.push(b'\r'),
+                Some('n') => bytes.push(b'\n'),
+                Some('t') => bytes.push(b'\t'),
+                Some(c @ ('\\' | '"')) => bytes.push(c as u8),
+                Some('x') => {
+                    let hex: String = chars.take(2).collect();
+                    let byte = u8::from_str_radix(&hex, 16)
+                        .map_err(|_| format!("bad escape \\x{}", hex))?;
+                    bytes.push(byte);
+                }
+                other => return Err(format!("bad escape \\{}", other.unwrap_or(' '))),
+            },
+            Some(c) => {
+                let mut utf8 = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            }
+        }
+    }
+}
+
+struct Session {
+    base: PathBuf,
+    cpu: Mos6502,
+    host: BufferHost, // every character device of the machine talks to it
+    symbols: Option<DebugInfo>,
+    timeout: u64,
+}
+
+impl Session {
+    fn new(base: &Path) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            cpu: Mos6502::default(),
+            host: BufferHost::default(),
+            symbols: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    fn command(&mut self, words: &[Word]) -> Result<(), String> {
+        let bare: Vec<&str> = words
+            .iter()
+            .map(|word| match word {
+                Word::Bare(word) => word.as_str(),
+                Word::Quoted(_) => "",
+            })
+            .collect();
+        let takes_text = match bare.as_slice() {
+            ["type", ..] => 1,
+            ["expect", "output", ..] => 2,
+            _ => words.len(),
+        };
+        let quoted = words
+            .iter()
+            .enumerate()
+            .any(|(index, word)| matches!(word, Word::Quoted(_)) && index != takes_text);
+        if quoted {
+            return Err("quoted text only goes with type and expect output".to_string());
+        }
+        match bare.as_slice() {
+            [] => Ok(()),
+            ["machine", path] => self.machine(path),
+            ["console", address] => {
+                let address = self.address(address)?;
+                let console = Console::with_host(Box::new(self.host.clone()));
+                self.cpu.attach(address, Box::new(console));
+                Ok(())
+            }
+            ["load", path, address] => {
+                let address = self.address(address)?;
+                let image = self.read(path)?;
+                self.cpu.load(address, &image);
+                Ok(())
+            }
+            ["symbols", path] => {
+                let info = DebugInfo::load(&self.base.join(path))
+                    .map_err(|err| format!("cannot load {}: {}", path, err))?;
+                self.symbols = Some(info);
+                Ok(())
+            }
+            ["poke", address, bytes @ ..] if !bytes.is_empty() => {
+                let address = self.address(address)?;
+                for (offset, byte) in bytes.iter().enumerate() {
+                    self.cpu
+                        .poke(address.wrapping_add(offset as u16), hex_byte(byte)?);
+                }
+                Ok(())
+            }
+            ["set", assignments @ ..] if !assignments.is_empty() => {
+                let mut registers = self.cpu.registers();
+                for assignment in assignments {
+                    let (name, value) = assignment
+                        .split_once('=')
+                        .ok_or_else(|| format!("expected register=value, got {}", assignment))?;
+                    match name {
+                        "pc" => registers.pc = self.address(value)?,
+                        _ => *register(&mut registers, name)? = hex_byte(value)?,
+                    }
+                }
+                self.cpu.set_registers(registers);
+                Ok(())
+            }
+            ["reset"] => {
+                self.cpu.reset();
+                Ok(())
+            }
+            ["type", ""] => match &words[1] {
+                Word::Quoted(text) => {
+                    self.host.type_bytes(text);
+                    Ok(())
+                }
+                Word::Bare(_) => Err("type takes quoted text".to_string()),
+            },
+            ["timeout", cycles] => {
+                self.timeout = cycles
+                    .parse()
+                    .map_err(|_| format!("bad cycle count {}", cycles))?;
+                Ok(())
+            }
+            ["run", "to", address] => {
+                let address = self.address(address)?;
+                self.run_to(address)
+            }
+            ["run", "cycles", cycles] => {
+                let cycles = cycles
+                    .parse()
+                    .map_err(|_| format!("bad cycle count {}", cycles))?;
+                self.run_cycles(cycles)
+            }
+            ["expect", "mem", address, bytes @ ..] if !bytes.is_empty() => {
+                let address = self.address(address)?;
+                for (offset, byte) in bytes.iter().enumerate() {
+                    let at = address.wrapping_add(offset as u16);
+                    let (expected, actual) = (hex_byte(byte)?, self.cpu.peek_quiet(at));
+                    if expected != actual {
+                        return Err(format!(
+                            "${:04x} is {:02x}, expected {:02x}",
+                            at, actual, expected
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            ["expect", "reg", assignments @ ..] if !assignments.is_empty() => {
+                let mut registers = self.cpu.registers();
+                for assignment in assignments {
+                    let (name, value) = assignment
+                        .split_once('=')
+                        .ok_or_else(|| format!("expected register=value, got {}", assignment))?;
+                    let (expected, actual) = match name {
+                        "pc" => (self.address(value)?, registers.pc),
+                        _ => (
+                            hex_byte(value)? as u16,
+                            *register(&mut registers, name)? as u16,
+                        ),
+                    };
+                    if expected != actual {
+                        return Err(format!(
+                            "{} is {:02x}, expected {:02x}",
+                            name, actual, expected
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            ["expect", "output", ""] => match &words[2] {
+                Word::Quoted(text) => {
+                    let output = self.host.output();
+                    if output
+                        .windows(text.len().max(1))
+                        .any(|window| window == text)
+                    {
+                        Ok(())
+                    } else {
+                        Err(format!(
+                            "no {:?} in the output {:?}",
+                            String::from_utf8_lossy(text),
+                            String::from_utf8_lossy(&output)
+                        ))
+                    }
+                }
+                Word::Bare(_) => Err("expect output takes quoted text".to_string()),
+            },
+            _ => Err(format!("unknown command {}", bare.join(" "))),
+        }
+    }
+
+    fn machine(&mut self, path: &str) -> Result<(), String> {
+        let path = self.base.join(path);
+        let text = fs::read_to_string(&path)
+            .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+        let host = self.host.clone();
+        let mut open = |_: &str| -> io::Result<Box<dyn SerialHost>> { Ok(Box::new(host.clone())) };
+        let base = path.parent().unwrap_or(Path::new("."));
+        self.cpu = description::build_with_hosts(&text, base, &mut open)
+            .map_err(|err| format!("cannot build {}: {}", path.display(), err))?;
+        Ok(())
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+        fs::read(self.base.join(path)).map_err(|err| format!("cannot load {}: {}", path, err))
+    }
+
+    ///
+    /// a label of the debug info, or a hex address. Labels such as `add`
+    /// read as hex too, they win unless the address starts with a $.
+    ///
+    fn address(&self, word: &str) -> Result<u16, String> {
+        let unknown = || format!("unknown address {}", word);
+        if let Some(hex) = word.strip_prefix('$') {
+            return u16::from_str_radix(hex, 16).map_err(|_| unknown());
+        }
+        let label = self
+            .symbols
+            .as_ref()
+            .and_then(|symbols| symbols.label_address(word));
+        label
+            .or_else(|| u16::from_str_radix(word, 16).ok())
+            .ok_or_else(unknown)
+    }
+
+    fn run_to(&mut self, address: u16) -> Result<(), String> {
+        let deadline = self.cpu.cycles().saturating_add(self.timeout);
+        self.cpu.resume();
+        loop {
+            self.cpu.step();
+            self.halted()?;
+            if self.cpu.registers().pc == address {
+                return Ok(());
+            }
+            if self.cpu.cycles() >= deadline {
+                return Err(format!(
+                    "${:04x} not reached in {} cycles, pc is ${:04x}",
+                    address,
+                    self.timeout,
+                    self.cpu.registers().pc
+                ));
+            }
+        }
+    }
+
+    fn run_cycles(&mut self, cycles: u64) -> Result<(), String> {
+        self.cpu.run_until(cycles);
+        self.halted()
+    }
+
+    fn halted(&self) -> Result<(), String> {
+        match self.cpu.halt_reason() {
+            Some(reason) => Err(format!(
+                "halted at ${:04x}: {:?}",
+                self.cpu.registers().pc,
+                reason
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+fn register<'a>(registers: &'a mut Registers, name: &str) -> Result<&'a mut u8, String> {
+    match name {
+        "a" => Ok(&mut registers.ac),
+        "x" => Ok(&mut registers.xr),
+        "y" => Ok(&mut registers.yr),
+        "sp" => Ok(&mut registers.sp),
+        "p" => Ok(&mut registers.sr),
+        _ => Err(format!("unknown register {}", name)),
+    }
+}
+
+fn hex_byte(word: &str) -> Result<u8, String> {
+    let hex = word.strip_prefix('$').unwrap_or(word);
+    u8::from_str_radix(hex, 16).map_err(|_| format!("bad byte {}", word))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    // lda $f001, beq *-3, sta $f001, sta $0300, inx, cmp #$0d, bne $0200, brk
+    const ECHO: [u8; 16] = [
+        0xad, 0x01, 0xf0, 0xf0, 0xfb, 0x8d, 0x01, 0xf0, 0x8d, 0x00, 0x03, 0xc9, 0x0d, 0xd0, 0xf1,
+        0x00,
+    ];
+
+    fn with_image(name: &str, script: &str) -> Result<(), Failure> {
+        let dir = env::temp_dir().join(format!("martian6502-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("echo.bin"), ECHO).unwrap();
+        fs::write(dir.join("echo.script"), script).unwrap();
+        let scripts = scripts_in(&dir).unwrap();
+        let result = run(&scripts[0]);
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn test_script_drives_a_program() {
+        let script = "
+            console f001
+            load echo.bin 0200    # the echo loop
+            set pc=0200 x=00
+            type \"hi\\r\"
+            run to 020f
+            expect mem 0300 0d
+            expect reg a=0d pc=020f
+            expect output \"hi\\r\"
+        ";
+        assert_eq!(Ok(()), with_image("script", script));
+    }
+
+    #[test]
+    fn test_failures_name_the_line() {
+        let script = "console f001\nload echo.bin 0200\nset pc=0200\ntype \"a\"\nrun to 0205\n\nexpect reg a=62\n";
+        let failure = with_image("failure", script).err().unwrap();
+        assert_eq!("line 7: a is 61, expected 62", failure.to_string());
+
+        let script = "set pc=0200\ntimeout 100\nrun to 0300\n";
+        let failure = run_text(script, Path::new(".")).err().unwrap();
+        assert_eq!(3, failure.line);
+        assert!(failure
+            .message
+            .starts_with("$0300 not reached in 100 cycles"));
+    }
+
+    #[test]
+    fn test_quoted_words() {
+        assert_eq!(
+            vec![
+                Word::Bare("type".to_string()),
+                Word::Quoted(b"# \"x\"\r\x01".to_vec())
+            ],
+            split("type \"# \\\"x\\\"\\r\\x01\" # comment").unwrap()
+        );
+        assert!(split("type \"open").is_err());
+    }
+
+    #[test]
+    fn test_quoted_text_only_goes_with_type_and_expect_output() {
+        let failure = run_text("load \"echo.bin\" 0200\n", Path::new("."))
+            .err()
+            .unwrap();
+        assert_eq!(
+            "line 1: quoted text only goes with type and expect output",
+            failure.to_string()
+        );
+        assert!(run_text("type \"a\" \"b\"\n", Path::new(".")).is_err());
+        assert!(run_text("expect \"output\" \"\"\n", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_labels_win_over_hex_without_a_dollar() {
+        let dir = env::temp_dir().join(format!("martian6502-labels-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sym =
+            "sym\tid=0,name=\"beef\",addrsize=absolute,scope=0,def=0,val=0x300,seg=0,type=lab\n";
+        fs::write(dir.join("echo.dbg"), sym).unwrap();
+        let script = "
+            symbols echo.dbg
+            poke beef 01
+            poke $beef 02
+            expect mem 0300 01
+            expect mem $beef 02
+            expect mem beef 01
+        ";
+        let result = run_text(script, &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Ok(()), result);
+    }
+
+    #[test]
+    fn test_directories_run_every_script_in_order() {
+        let dir = env::temp_dir().join(format!("martian6502-run-all-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b.script"), "poke 0300 01\nexpect mem 0300 02\n").unwrap();
+        fs::write(dir.join("a.script"), "poke 0300 01\nexpect mem 0300 01\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a script").unwrap();
+
+        let mut report = Vec::new();
+        let passed = run_all(std::slice::from_ref(&dir), &mut report).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!passed);
+        let expected = format!(
+            "ok {}\nFAIL {}: line 2: $0300 is 01, expected 02\n1 passed, 1 failed\n",
+            dir.join("a.script").display(),
+            dir.join("b.script").display(),
+        );
+        assert_eq!(expected, String::from_utf8(report).unwrap());
+    }
+}
@@ -0,0 +1,720 @@
+///
+/// VICE binary monitor protocol, version 2, for tools written against
+/// VICE's remote monitor. Commands and responses are binary frames:
+///
+/// ```text
+/// command:  02 02 <body length u32> <request id u32> <type u8> <body>
+/// response: 02 02 <body length u32> <type u8> <error u8> <request id u32> <body>
+/// ```
+///
+/// Responses the tool did not ask for, such as stop events, carry the
+/// request id ffffffff. The machine waits stopped for the first exit
+/// command, any command sent while it runs stops it the way VICE does.
+///
+/// Only the main cpu memory space exists, bank ids are ignored.
+///
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+};
+
+use super::{Debugger, Stop, WatchKind, Watchpoint};
+use crate::{
+    device::{StateReader, StateWriter},
+    mos6502::{AccessKind, Registers},
+};
+
+pub const DEFAULT_PORT: u16 = 6502;
+
+const STX: u8 = 0x02;
+const API_VERSION: u8 = 0x02;
+const COMMAND_HEADER: usize = 11;
+const EVENT: u32 = 0xffffffff;
+
+const MEMORY_GET: u8 = 0x01;
+const MEMORY_SET: u8 = 0x02;
+const CHECKPOINT_GET: u8 = 0x11; // also the checkpoint info response
+const CHECKPOINT_SET: u8 = 0x12;
+const CHECKPOINT_DELETE: u8 = 0x13;
+const CHECKPOINT_LIST: u8 = 0x14;
+const CHECKPOINT_TOGGLE: u8 = 0x15;
+const REGISTERS_GET: u8 = 0x31; // also the register info response
+const REGISTERS_SET: u8 = 0x32;
+const STOPPED: u8 = 0x62;
+const RESUMED: u8 = 0x63;
+const ADVANCE_INSTRUCTIONS: u8 = 0x71;
+const PING: u8 = 0x81;
+const REGISTERS_AVAILABLE: u8 = 0x83;
+const VICE_INFO: u8 = 0x85;
+const EXIT: u8 = 0xaa;
+const QUIT: u8 = 0xbb;
+
+const OK: u8 = 0x00;
+const NO_SUCH_OBJECT: u8 = 0x01;
+const INVALID_MEMSPACE: u8 = 0x02;
+const BAD_LENGTH: u8 = 0x80;
+const INVALID_PARAMETER: u8 = 0x81;
+const UNKNOWN_COMMAND: u8 = 0x83;
+
+const MAIN_MEMSPACE: u8 = 0x00;
+
+// checkpoint cpu operations
+const LOAD: u8 = 0x01;
+const STORE: u8 = 0x02;
+const EXEC: u8 = 0x04;
+
+// register ids of the VICE 6502 monitor
+const REGISTERS: [(u8, &str, u8); 6] = [
+    (0x00, "A", 8),
+    (0x01, "X", 8),
+    (0x02, "Y", 8),
+    (0x03, "PC", 16),
+    (0x04, "SP", 8),
+    (0x05, "FL", 8),
+];
+
+// instructions between looks at the connection while running
+const SLICE: u64 = 10_000;
+
+const JSR: u8 = 0x20;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Response {
+    kind: u8,
+    error: u8,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(kind: u8, body: Vec<u8>) -> Self {
+        Self {
+            kind,
+            error: OK,
+            body,
+        }
+    }
+
+    fn error(kind: u8, error: u8) -> Self {
+        Self {
+            kind,
+            error,
+            body: Vec::new(),
+        }
+    }
+}
+
+///
+/// what the machine does once the responses are out
+///
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Stay,
+    Resume,
+    Advance { instructions: u16, step_over: bool },
+    Quit,
+}
+
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    number: u32,
+    start: u16,
+    end: u16,
+    stop: bool, // otherwise only counted
+    enabled: bool,
+    operation: u8,
+    temporary: bool,
+    hits: u32,
+}
+
+impl Checkpoint {
+    fn info(&self, hit: bool) -> Vec<u8> {
+        let mut info = StateWriter::default();
+        info.u32(self.number)
+            .bool(hit)
+            .u16(self.start)
+            .u16(self.end)
+            .bool(self.stop)
+            .bool(self.enabled)
+            .u8(self.operation)
+            .bool(self.temporary)
+            .u32(self.hits)
+            .u32(0) // ignore count
+            .bool(false) // condition
+            .u8(MAIN_MEMSPACE);
+        info.into_bytes()
+    }
+
+    fn covers(&self, stop: Stop) -> bool {
+        match stop {
+            Stop::Breakpoint(pc) => self.operation & EXEC != 0 && self.contains(pc),
+            Stop::Watchpoint(_, access) => {
+                let operation = match access.kind {
+                    AccessKind::Write => STORE,
+                    _ => LOAD,
+                };
+                self.operation & operation != 0 && self.contains(access.address)
+            }
+            _ => false,
+        }
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        self.start <= address && address <= self.end
+    }
+}
+
+///
+/// serve one monitor connection on the localhost `port`, returns when the
+/// tool quits or hangs up
+///
+pub fn serve(debugger: &mut Debugger, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    let mut monitor = Monitor {
+        debugger,
+        checkpoints: Vec::new(),
+        next_number: 1,
+    };
+    Connection { stream }.run(&mut monitor)
+}
+
+struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+    fn run(&mut self, monitor: &mut Monitor) -> io::Result<()> {
+        self.stopped(monitor, Vec::new())?;
+        let mut action = Action::Stay;
+        loop {
+            action = match action {
+                Action::Quit => return Ok(()),
+                Action::Stay => match self.receive()? {
+                    Some((id, kind, body)) => self.command(monitor, id, kind, &body)?,
+                    None => return Ok(()),
+                },
+                Action::Resume => {
+                    let pc = monitor.debugger.cpu().registers().pc;
+                    self.send(EVENT, &Response::ok(RESUMED, pc.to_le_bytes().to_vec()))?;
+                    self.resume(monitor)?
+                }
+                Action::Advance {
+                    instructions,
+                    step_over,
+                } => {
+                    let stop = monitor.advance(instructions, step_over);
+                    let hits = monitor.hits(stop);
+                    self.stopped(monitor, hits)?;
+                    Action::Stay
+                }
+            };
+        }
+    }
+
+    ///
+    /// run until a checkpoint stops the machine or a command arrives
+    ///
+    fn resume(&mut self, monitor: &mut Monitor) -> io::Result<Action> {
+        loop {
+            let stop = monitor.debugger.resume_for(SLICE);
+            if stop != Stop::Limit {
+                let hits = monitor.hits(stop);
+                if matches!(stop, Stop::Halted(_)) || hits.iter().any(|hit| hit.stop) {
+                    self.stopped(monitor, hits)?;
+                    return Ok(Action::Stay);
+                }
+                // tracing checkpoints only report
+                for hit in hits {
+                    self.send(EVENT, &Response::ok(CHECKPOINT_GET, hit.info(true)))?;
+                }
+                continue;
+            }
+            self.stream.set_nonblocking(true)?;
+            let mut byte = [0];
+            let polled = self.stream.peek(&mut byte);
+            self.stream.set_nonblocking(false)?;
+            match polled {
+                Ok(0) => return Ok(Action::Quit),
+                Ok(_) => {
+                    self.stopped(monitor, Vec::new())?;
+                    return Ok(Action::Stay);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn command(
+        &mut self,
+        monitor: &mut Monitor,
+        id: u32,
+        kind: u8,
+        body: &[u8],
+    ) -> io::Result<Action> {
+        let (responses, action) = monitor.command(kind, body);
+        for response in responses {
+            self.send(id, &response)?;
+        }
+        Ok(action)
+    }
+
+    ///
+    /// checkpoints hit, registers, then the stop itself
+    ///
+    fn stopped(&mut self, monitor: &mut Monitor, hits: Vec<Checkpoint>) -> io::Result<()> {
+        for hit in hits {
+            self.send(EVENT, &Response::ok(CHECKPOINT_GET, hit.info(true)))?;
+        }
+        let registers = monitor.debugger.cpu().registers();
+        self.send(
+            EVENT,
+            &Response::ok(REGISTERS_GET, register_info(registers)),
+        )?;
+        self.send(
+            EVENT,
+            &Response::ok(STOPPED, registers.pc.to_le_bytes().to_vec()),
+        )
+    }
+
+    ///
+    /// next command as request id, type and body, None once the tool hangs up
+    ///
+    fn receive(&mut self) -> io::Result<Option<(u32, u8, Vec<u8>)>> {
+        let mut header = [0; COMMAND_HEADER];
+        match self.stream.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) if err.kind() == ErrorKind::ConnectionReset => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        if header[0] != STX || header[1] != API_VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "not a binary monitor command",
+            ));
+        }
+        let mut fields = StateReader::new(&header[2..]);
+        let len = fields.u32() as usize;
+        let id = fields.u32();
+        let kind = fields.u8();
+        let mut body = vec![0; len];
+        self.stream.read_exact(&mut body)?;
+        Ok(Some((id, kind, body)))
+    }
+
+    fn send(&mut self, id: u32, response: &Response) -> io::Result<()> {
+        let mut frame = StateWriter::default();
+        frame
+            .u8(STX)
+            .u8(API_VERSION)
+            .u32(response.body.len() as u32)
+            .u8(response.kind)
+            .u8(response.error)
+            .u32(id)
+            .bytes(&response.body);
+        self.stream.write_all(&frame.into_bytes())
+    }
+}
+
+struct Monitor<'a> {
+    debugger: &'a mut Debugger,
+    checkpoints: Vec<Checkpoint>,
+    next_number: u32,
+}
+
+impl Monitor<'_> {
+    fn command(&mut self, kind: u8, body: &[u8]) -> (Vec<Response>, Action) {
+        let mut args = StateReader::new(body);
+        let response = match kind {
+            MEMORY_GET if body.len() >= 8 => {
+                let (side_effects, start, end, memspace) =
+                    (args.bool(), args.u16(), args.u16(), args.u8());
+                if memspace != MAIN_MEMSPACE {
+                    Response::error(kind, INVALID_MEMSPACE)
+                } else if end < start {
+                    Response::error(kind, INVALID_PARAMETER)
+                } else {
+                    // memory views ask without side effects, devices are only looked at
+                    let cpu = self.debugger.cpu_mut();
+                    let bytes: Vec<u8> = (start..=end)
+                        .map(|address| {
+                            if side_effects {
+                                cpu.inspect(address)
+                            } else {
+                                cpu.peek_quiet(address)
+                            }
+                        })
+                        .collect();
+                    let mut reply = StateWriter::default();
+                    reply.u16(bytes.len() as u16).bytes(&bytes);
+                    Response::ok(kind, reply.into_bytes())
+                }
+            }
+            MEMORY_SET if body.len() >= 8 => {
+                let (_side_effects, start, end, memspace) =
+                    (args.u8(), args.u16(), args.u16(), args.u8());
+                let data = &body[8..];
+                if memspace != MAIN_MEMSPACE {
+                    Response::error(kind, INVALID_MEMSPACE)
+                } else if end < start || data.len() != (end - start) as usize + 1 {
+                    Response::error(kind, INVALID_PARAMETER)
+                } else {
+                    for (address, byte) in (start..=end).zip(data) {
+                        self.debugger.cpu_mut().deposit(address, *byte);
+                    }
+                    Response::ok(kind, Vec::new())
+                }
+            }
+            CHECKPOINT_GET if body.len() == 4 => match self.checkpoint(args.u32()) {
+                Some(checkpoint) => Response::ok(CHECKPOINT_GET, checkpoint.info(false)),
+                None => Response::error(kind, NO_SUCH_OBJECT),
+            },
+            CHECKPOINT_SET if body.len() >= 8 => {
+                let checkpoint = Checkpoint {
+                    number: self.next_number,
+                    start: args.u16(),
+                    end: args.u16(),
+                    stop: args.bool(),
+                    enabled: args.bool(),
+                    operation: args.u8(),
+                    temporary: args.bool(),
+                    hits: 0,
+                };
+                if checkpoint.end < checkpoint.start
+                    || checkpoint.operation & (LOAD | STORE | EXEC) == 0
+                {
+                    Response::error(kind, INVALID_PARAMETER)
+                } else {
+                    self.next_number += 1;
+                    let info = checkpoint.info(false);
+                    self.checkpoints.push(checkpoint);
+                    self.sync();
+                    Response::ok(CHECKPOINT_GET, info)
+                }
+            }
+            CHECKPOINT_DELETE if body.len() == 4 => {
+                let number = args.u32();
+                match self
+                    .checkpoints
+                    .iter()
+                    .position(|checkpoint| checkpoint.number == number)
+                {
+                    Some(index) => {
+                        self.checkpoints.remove(index);
+                        self.sync();
+                        Response::ok(kind, Vec::new())
+                    }
+                    None => Response::error(kind, NO_SUCH_OBJECT),
+                }
+            }
+            CHECKPOINT_LIST => {
+                let mut responses: Vec<Response> = self
+                    .checkpoints
+                    .iter()
+                    .map(|checkpoint| Response::ok(CHECKPOINT_GET, checkpoint.info(false)))
+                    .collect();
+                let mut count = StateWriter::default();
+                count.u32(self.checkpoints.len() as u32);
+                responses.push(Response::ok(kind, count.into_bytes()));
+                return (responses, Action::Stay);
+            }
+            CHECKPOINT_TOGGLE if body.len() == 5 => {
+                let (number, enabled) = (args.u32(), args.bool());
+                match self
+                    .checkpoints
+                    .iter_mut()
+                    .find(|checkpoint| checkpoint.number == number)
+                {
+                    Some(checkpoint) => {
+                        checkpoint.enabled = enabled;
+                        self.sync();
+                        Response::ok(kind, Vec::new())
+                    }
+                    None => Response::error(kind, NO_SUCH_OBJECT),
+                }
+            }
+            REGISTERS_GET if body.len() == 1 => match args.u8() {
+                MAIN_MEMSPACE => Response::ok(
+                    REGISTERS_GET,
+                    register_info(self.debugger.cpu().registers()),
+                ),
+                _ => Response::error(kind, INVALID_MEMSPACE),
+            },
+            REGISTERS_SET if body.len() >= 3 => self.set_registers(body),
+            ADVANCE_INSTRUCTIONS if body.len() == 3 => {
+                let step_over = args.bool();
+                let instructions = args.u16();
+                return (
+                    vec![Response::ok(kind, Vec::new())],
+                    Action::Advance {
+                        instructions,
+                        step_over,
+                    },
+                );
+            }
+            PING => Response::ok(kind, Vec::new()),
+            REGISTERS_AVAILABLE if body.len() == 1 => {
+                let mut reply = StateWriter::default();
+                reply.u16(REGISTERS.len() as u16);
+                for (id, name, bits) in REGISTERS {
+                    reply
+                        .u8(3 + name.len() as u8)
+                        .u8(id)
+                        .u8(bits)
+                        .u8(name.len() as u8);
+                    reply.bytes(name.as_bytes());
+                }
+                Response::ok(kind, reply.into_bytes())
+            }
+            VICE_INFO => Response::ok(kind, vec![4, 3, 7, 0, 0, 4, 0, 0, 0, 0]),
+            EXIT => return (vec![Response::ok(kind, Vec::new())], Action::Resume),
+            QUIT => return (vec![Response::ok(kind, Vec::new())], Action::Quit),
+            MEMORY_GET | MEMORY_SET | CHECKPOINT_GET | CHECKPOINT_SET | CHECKPOINT_DELETE
+            | CHECKPOINT_TOGGLE | REGISTERS_GET | REGISTERS_SET | ADVANCE_INSTRUCTIONS
+            | REGISTERS_AVAILABLE => Response::error(kind, BAD_LENGTH),
+            _ => Response::error(kind, UNKNOWN_COMMAND),
+        };
+        (vec![response], Action::Stay)
+    }
+
+    fn checkpoint(&self, number: u32) -> Option<&Checkpoint> {
+        self.checkpoints
+            .iter()
+            .find(|checkpoint| checkpoint.number == number)
+    }
+
+    fn set_registers(&mut self, body: &[u8]) -> Response {
+        let mut args = StateReader::new(body);
+        if args.u8() != MAIN_MEMSPACE {
+            return Response::error(REGISTERS_SET, INVALID_MEMSPACE);
+        }
+        let count = args.u16() as usize;
+        if body.len() != 3 + count * 4 {
+            return Response::error(REGISTERS_SET, BAD_LENGTH);
+        }
+        let mut registers = self.debugger.cpu().registers();
+        for _ in 0..count {
+            let (_size, id, val) = (args.u8(), args.u8(), args.u16());
+            match id {
+                0x00 => registers.ac = val as u8,
+                0x01 => registers.xr = val as u8,
+                0x02 => registers.yr = val as u8,
+                0x03 => registers.pc = val,
+                0x04 => registers.sp = val as u8,
+                0x05 => registers.sr = val as u8,
+                _ => return Response::error(REGISTERS_SET, INVALID_PARAMETER),
+            }
+        }
+        self.debugger.cpu_mut().set_registers(registers);
+        Response::ok(REGISTERS_GET, register_info(registers))
+    }
+
+    ///
+    /// the debugger's breakpoints and watchpoints from the enabled checkpoints
+    ///
+    fn sync(&mut self) {
+        let breakpoints: Vec<u16> = self.debugger.breakpoints().collect();
+        for address in breakpoints {
+            self.debugger.remove_breakpoint(address);
+        }
+        for watchpoint in self.debugger.watchpoints().to_vec() {
+            self.debugger.remove_watchpoint(watchpoint);
+        }
+        for checkpoint in self
+            .checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.enabled)
+        {
+            if checkpoint.operation & EXEC != 0 {
+                for address in checkpoint.start..=checkpoint.end {
+                    self.debugger.add_breakpoint(address);
+                }
+            }
+            let kind = match checkpoint.operation & (LOAD | STORE) {
+                LOAD => WatchKind::Read,
+                STORE => WatchKind::Write,
+                0 => continue,
+                _ => WatchKind::Access,
+            };
+            self.debugger.add_watchpoint(Watchpoint {
+                start: checkpoint.start,
+                end: checkpoint.end,
+                kind,
+            });
+        }
+    }
+
+    ///
+    /// count the hit on every enabled checkpoint covering `stop`, temporary
+    /// ones go away
+    ///
+    fn hits(&mut self, stop: Stop) -> Vec<Checkpoint> {
+        let mut hits = Vec::new();
+        for checkpoint in self.checkpoints.iter_mut() {
+            if checkpoint.enabled && checkpoint.covers(stop) {
+                checkpoint.hits += 1;
+                hits.push(checkpoint.clone());
+            }
+        }
+        let before = self.checkpoints.len();
+        self.checkpoints.retain(|checkpoint| {
+            !(checkpoint.temporary && hits.iter().any(|hit| hit.number == checkpoint.number))
+        });
+        if self.checkpoints.len() != before {
+            self.sync();
+        }
+        hits
+    }
+
+    ///
+    /// execute `instructions` instructions, a subroutine called on the way
+    /// counts as one when stepping over
+    ///
+    fn advance(&mut self, instructions: u16, step_over: bool) -> Stop {
+        let mut stop = Stop::Stepped;
+        for _ in 0..instructions {
+            let registers = self.debugger.cpu().registers();
+            let call = step_over && self.debugger.cpu().peek_quiet(registers.pc) == JSR;
+            stop = self.debugger.step();
+            while call && stop == Stop::Stepped && self.debugger.cpu().registers().sp < registers.sp
+            {
+                let pc = self.debugger.cpu().registers().pc;
+                if self.debugger.has_breakpoint(pc) {
+                    return Stop::Breakpoint(pc);
+                }
+                stop = self.debugger.step();
+            }
+            if stop != Stop::Stepped {
+                return stop;
+            }
+        }
+        stop
+    }
+}
+
+fn register_info(registers: Registers) -> Vec<u8> {
+    let values = [
+        registers.ac as u16,
+        registers.xr as u16,
+        registers.yr as u16,
+        registers.pc,
+        registers.sp as u16,
+        registers.sr as u16,
+    ];
+    let mut info = StateWriter::default();
+    info.u16(REGISTERS.len() as u16);
+    for ((id, _, _), val) in REGISTERS.iter().zip(values) {
+        info.u8(3).u8(*id).u16(val);
+    }
+    info.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{Acia, BufferHost};
+    use crate::mos6502::Mos6502;
+
+    fn debugger() -> Debugger {
+        let mut cpu = Mos6502::default();
+        // jsr $0206, sta $10, brk; inx, rts
+        cpu.load(0x0200, &[0x20, 0x06, 0x02, 0x85, 0x10, 0x00, 0xe8, 0x60]);
+        cpu.set_registers(Registers {
+            pc: 0x0200,
+            sp: 0xff,
+            ac: 0x42,
+            ..Registers::default()
+        });
+        Debugger::new(cpu)
+    }
+
+    fn monitor(debugger: &mut Debugger) -> Monitor<'_> {
+        Monitor {
+            debugger,
+            checkpoints: Vec::new(),
+            next_number: 1,
+        }
+    }
+
+    #[test]
+    fn test_memory_get_and_set() {
+        let mut debugger = debugger();
+        let mut monitor = monitor(&mut debugger);
+        let (responses, _) =
+            monitor.command(MEMORY_SET, &[0, 0x10, 0, 0x11, 0, 0, 0, 0, 0xaa, 0x55]);
+        assert_eq!(vec![Response::ok(MEMORY_SET, Vec::new())], responses);
+        let (responses, _) = monitor.command(MEMORY_GET, &[0, 0x10, 0, 0x11, 0, 0, 0, 0]);
+        assert_eq!(
+            vec![Response::ok(MEMORY_GET, vec![2, 0, 0xaa, 0x55])],
+            responses
+        );
+        let (responses, _) = monitor.command(MEMORY_GET, &[0, 0x10, 0, 0x11, 0, 1, 0, 0]);
+        assert_eq!(INVALID_MEMSPACE, responses[0].error);
+        let (responses, _) = monitor.command(MEMORY_GET, &[0]);
+        assert_eq!(BAD_LENGTH, responses[0].error);
+    }
+
+    #[test]
+    fn test_memory_get_has_side_effects_only_when_asked() {
+        let host = BufferHost::default();
+        host.type_bytes(b"x");
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &[0xea, 0xea]);
+        cpu.attach(0xc000, Box::new(Acia::new(Box::new(host), 1_000_000)));
+        cpu.poke(0xc003, 0x1f);
+        cpu.poke(0xc002, 0x0b);
+        cpu.run_until(2);
+        let mut debugger = Debugger::new(cpu);
+        let mut monitor = monitor(&mut debugger);
+        // data then status of the ACIA, only reading the data for real clears RDRF
+        let quiet = [0, 0x00, 0xc0, 0x01, 0xc0, 0];
+        let loud = [1, 0x00, 0xc0, 0x01, 0xc0, 0];
+        for (get, status) in [(quiet, 0x18), (quiet, 0x18), (loud, 0x10), (quiet, 0x10)] {
+            let (responses, _) = monitor.command(MEMORY_GET, &[&get[..], &[0, 0]].concat());
+            assert_eq!(vec![2, 0, b'x', status], responses[0].body);
+        }
+    }
+
+    #[test]
+    fn test_store_checkpoint_stops_and_is_listed() {
+        let mut debugger = debugger();
+        let mut monitor = monitor(&mut debugger);
+        // $10..$10, stop, enabled, store, not temporary
+        let (responses, _) = monitor.command(CHECKPOINT_SET, &[0x10, 0, 0x10, 0, 1, 1, STORE, 0]);
+        assert_eq!(CHECKPOINT_GET, responses[0].kind);
+        let stop = monitor.debugger.resume();
+        assert_eq!(0x0205, monitor.debugger.cpu().registers().pc);
+        let hits = monitor.hits(stop);
+        assert_eq!((1, 1), (hits[0].number, hits[0].hits));
+
+        let (responses, _) = monitor.command(CHECKPOINT_LIST, &[]);
+        assert_eq!(2, responses.len());
+        assert_eq!(vec![1, 0, 0, 0], responses[1].body);
+        monitor.command(CHECKPOINT_DELETE, &[1, 0, 0, 0]);
+        assert!(monitor.debugger.watchpoints().is_empty());
+    }
+
+    #[test]
+    fn test_registers_and_step_over() {
+        let mut debugger = debugger();
+        let mut monitor = monitor(&mut debugger);
+        let (responses, _) = monitor.command(REGISTERS_GET, &[MAIN_MEMSPACE]);
+        assert_eq!(&[6, 0, 3, 0x00, 0x42, 0x00], &responses[0].body[..6]);
+        // X = 5
+        let (responses, _) = monitor.command(REGISTERS_SET, &[MAIN_MEMSPACE, 1, 0, 3, 0x01, 5, 0]);
+        assert_eq!(REGISTERS_GET, responses[0].kind);
+        let (_, action) = monitor.command(ADVANCE_INSTRUCTIONS, &[1, 1, 0]);
+        let Action::Advance {
+            instructions,
+            step_over,
+        } = action
+        else {
+            panic!("not advancing");
+        };
+        monitor.advance(instructions, step_over);
+        let registers = monitor.debugger.cpu().registers();
+        assert_eq!((0x0203, 6), (registers.pc, registers.xr));
+    }
+}
@@ -0,0 +1,146 @@
+///
+/// peripherals that can be attached to the cpu bus, each device decodes a
+/// small window of addresses and sees offsets relative to its base address
+///
+mod acia;
+mod bank;
+mod console;
+mod pia;
+mod riot;
+mod serial;
+mod shared;
+mod state;
+mod terminal;
+mod via;
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::mos6502::scheduler::Scheduler;
+
+pub use acia::Acia;
+pub use bank::{BankedMemory, CnRom, Latch, Mapper, MapperPort, Mmc1, UxRom};
+pub use console::Console;
+pub use pia::Pia;
+pub use riot::Riot;
+#[cfg(target_os = "linux")]
+pub use serial::PtyHost;
+pub use serial::{open_host, BufferHost, SerialHost, StdioHost, TcpHost};
+pub use shared::{Remote, SharedRam};
+pub use state::{StateReader, StateWriter};
+pub use terminal::Terminal;
+pub use via::Via;
+
+pub trait Device {
+    ///
+    /// number of consecutive addresses the device answers to, 0 for a
+    /// device only wired to an interrupt line
+    ///
+    fn size(&self) -> u16;
+
+    fn read(&mut self, offset: u16) -> u8;
+
+    fn write(&mut self, offset: u16, val: u8);
+
+    ///
+    /// what a read at `offset` would return, without what reading does to
+    /// the device. None where that cannot be told, debuggers see open bus.
+    ///
+    fn peek(&self, _offset: u16) -> Option<u8> {
+        None
+    }
+
+    ///
+    /// let `cycles` cpu cycles pass, called after every instruction, or
+    /// after every bus cycle on a cycle accurate cpu
+    ///
+    fn tick(&mut self, _cycles: u32) {}
+
+    ///
+    /// the device was attached, it schedules its events through `scheduler`
+    /// from now on rather than waiting for ticks
+    ///
+    fn connect(&mut self, _scheduler: Scheduler) {}
+
+    ///
+    /// an event the device scheduled is due
+    ///
+    fn event(&mut self, _event: u32) {}
+
+    ///
+    /// level of the device's IRQ output, true while it requests an interrupt
+    ///
+    fn irq(&self) -> bool {
+        false
+    }
+
+    ///
+    /// internal state for snapshots, devices without any keep the default
+    ///
+    fn state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    ///
+    /// layout of `state`, it moves whenever the layout changes. 1 and 2 are
+    /// the layouts snapshots of those versions held, new layouts start at 3.
+    ///
+    fn state_version(&self) -> u8 {
+        1
+    }
+
+    ///
+    /// `state` as laid out by `version`, devices convert older layouts.
+    /// Snapshots restore the cycle counter first, so time is known by then.
+    ///
+    fn restore(&mut self, _state: &[u8], _version: u8) {}
+}
+
+///
+/// a shared device stays reachable after it is attached, so the machine can
+/// still wire its pins to other devices
+///
+impl<T: Device> Device for Rc<RefCell<T>> {
+    fn size(&self) -> u16 {
+        self.borrow().size()
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        self.borrow_mut().read(offset)
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        self.borrow_mut().write(offset, val)
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        self.borrow().peek(offset)
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.borrow_mut().tick(cycles)
+    }
+
+    fn connect(&mut self, scheduler: Scheduler) {
+        self.borrow_mut().connect(scheduler)
+    }
+
+    fn event(&mut self, event: u32) {
+        self.borrow_mut().event(event)
+    }
+
+    fn irq(&self) -> bool {
+        self.borrow().irq()
+    }
+
+    fn state(&self) -> Vec<u8> {
+        self.borrow().state()
+    }
+
+    fn state_version(&self) -> u8 {
+        self.borrow().state_version()
+    }
+
+    fn restore(&mut self, state: &[u8], version: u8) {
+        self.borrow_mut().restore(state, version)
+    }
+}
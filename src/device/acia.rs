@@ -0,0 +1,417 @@
+use super::{serial::SerialHost, Device, StateReader, StateWriter};
+use crate::mos6502::scheduler::{EventId, Scheduler};
+
+///
+/// MOS 6551 / WDC 65C51 asynchronous communication interface adapter. The
+/// line runs on scheduled events, it stays quiet until attached to a cpu.
+///
+const DATA: u16 = 0x0;
+const STATUS: u16 = 0x1; // writing it performs a programmed reset
+const COMMAND: u16 = 0x2;
+const CONTROL: u16 = 0x3;
+
+// status layout: IRQ DSR DCD TDRE RDRF overrun framing parity
+const STATUS_OVERRUN: u8 = 0b00000100;
+const STATUS_RDRF: u8 = 0b00001000;
+const STATUS_TDRE: u8 = 0b00010000;
+const STATUS_IRQ: u8 = 0b10000000;
+
+// command layout: parity (3) echo TX control (2) RX IRQ disable DTR
+const COMMAND_DTR: u8 = 0b00000001;
+const COMMAND_RX_IRQ_DISABLE: u8 = 0b00000010;
+const COMMAND_TX_CONTROL: u8 = 0b00001100;
+const COMMAND_TX_IRQ: u8 = 0b00000100;
+const COMMAND_ECHO: u8 = 0b00010000;
+const COMMAND_PARITY_ENABLE: u8 = 0b00100000;
+const COMMAND_PROGRAMMED_RESET: u8 = 0b00011111;
+
+// control layout: stop bits, word length (2), clock source, baud rate (4)
+const CONTROL_BAUD: u8 = 0b00001111;
+const CONTROL_WORD_LENGTH: u8 = 0b01100000;
+const CONTROL_STOP_BITS: u8 = 0b10000000;
+
+// scheduled events
+const TRANSMITTED: u32 = 0; // the character being sent left the line
+const RECEIVE: u32 = 1; // the next character can arrive
+
+const NO_DEADLINE: u64 = u64::MAX;
+
+// baud rates of the internal generator, 0 selects the external 16x clock
+const BAUD_RATES: [f64; 16] = [
+    0.0, 50.0, 75.0, 109.92, 134.58, 150.0, 300.0, 600.0, 1200.0, 1800.0, 2400.0, 3600.0, 4800.0,
+    7200.0, 9600.0, 19200.0,
+];
+
+pub struct Acia {
+    host: Box<dyn SerialHost>,
+    clock_hz: u32, // cpu clock, converts the baud rate into cycles
+    rx_data: u8,
+    tx_data: u8,  // transmit data register, free again once TDRE is set
+    tx_shift: u8, // the character on the line
+    status: u8,
+    command: u8,
+    control: u8,
+    tx_busy: bool, // a character is being shifted out
+    scheduler: Option<Scheduler>,
+    tx_event: Option<EventId>,
+    rx_event: Option<EventId>,
+}
+
+impl Acia {
+    pub fn new(host: Box<dyn SerialHost>, clock_hz: u32) -> Self {
+        Self {
+            host,
+            clock_hz,
+            rx_data: 0,
+            tx_data: 0,
+            tx_shift: 0,
+            status: STATUS_TDRE,
+            command: COMMAND_RX_IRQ_DISABLE,
+            control: 0,
+            tx_busy: false,
+            scheduler: None,
+            tx_event: None,
+            rx_event: None,
+        }
+    }
+
+    ///
+    /// cycles needed to move one frame: start bit, data bits, parity and stop bits
+    ///
+    fn character_cycles(&self) -> u64 {
+        let baud = BAUD_RATES[(self.control & CONTROL_BAUD) as usize];
+        if baud == 0.0 {
+            return 1;
+        }
+        let data_bits = 8 - ((self.control & CONTROL_WORD_LENGTH) >> 5) as u64;
+        let parity_bits = (self.command & COMMAND_PARITY_ENABLE != 0) as u64;
+        let stop_bits = if self.control & CONTROL_STOP_BITS != 0 {
+            2
+        } else {
+            1
+        };
+        let frame_bits = 1 + data_bits + parity_bits + stop_bits;
+        (self.clock_hz as f64 * frame_bits as f64 / baud).ceil() as u64
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.command & COMMAND_DTR != 0
+    }
+
+    fn rx_irq_enabled(&self) -> bool {
+        self.is_enabled() && self.command & COMMAND_RX_IRQ_DISABLE == 0
+    }
+
+    fn tx_irq_enabled(&self) -> bool {
+        self.is_enabled() && self.command & COMMAND_TX_CONTROL == COMMAND_TX_IRQ
+    }
+
+    fn data_bits_mask(&self) -> u8 {
+        0xff >> ((self.control & CONTROL_WORD_LENGTH) >> 5)
+    }
+
+    fn schedule_in(&self, cycles: u64, event: u32) -> Option<EventId> {
+        let scheduler = self.scheduler.as_ref()?;
+        Some(scheduler.schedule_in(cycles, event))
+    }
+
+    fn deadline(&self, event: Option<EventId>) -> u64 {
+        match (self.scheduler.as_ref(), event) {
+            (Some(scheduler), Some(id)) => scheduler.deadline(id).unwrap_or(NO_DEADLINE),
+            _ => NO_DEADLINE,
+        }
+    }
+
+    ///
+    /// move the transmit data register into the shift register, the program
+    /// can write the next character while this one is on the line
+    ///
+    fn start_frame(&mut self) {
+        self.tx_shift = self.tx_data;
+        self.tx_busy = true;
+        self.tx_event = self.schedule_in(self.character_cycles(), TRANSMITTED);
+        self.status |= STATUS_TDRE;
+        if self.tx_irq_enabled() {
+            self.status |= STATUS_IRQ;
+        }
+    }
+
+    fn transmitted(&mut self) {
+        self.tx_event = None;
+        self.host.send(self.tx_shift & self.data_bits_mask());
+        self.tx_busy = false;
+        if self.status & STATUS_TDRE == 0 {
+            self.start_frame();
+        }
+    }
+
+    fn receive(&mut self) {
+        self.rx_event = self.schedule_in(self.character_cycles(), RECEIVE);
+        // the host side waits while the receive register is full, so nothing is lost
+        if !self.is_enabled() || self.status & STATUS_RDRF != 0 {
+            return;
+        }
+        let host = &mut self.host;
+        let polled = match self.scheduler.as_ref() {
+            Some(scheduler) => scheduler.input(|| host.poll()),
+            None => host.poll(),
+        };
+        let Some(byte) = polled else {
+            return;
+        };
+        self.rx_data = byte & self.data_bits_mask();
+        self.status |= STATUS_RDRF;
+        if self.rx_irq_enabled() {
+            self.status |= STATUS_IRQ;
+        }
+        if self.command & COMMAND_ECHO != 0 && self.command & COMMAND_TX_CONTROL == 0 {
+            self.host.send(self.rx_data);
+        }
+    }
+}
+
+impl Device for Acia {
+    fn size(&self) -> u16 {
+        4
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x3 {
+            DATA => {
+                self.status &= !(STATUS_RDRF | STATUS_OVERRUN);
+                self.rx_data
+            }
+            STATUS => {
+                let status = self.status;
+                self.status &= !STATUS_IRQ;
+                status
+            }
+            COMMAND => self.command,
+            CONTROL => self.control,
+            _ => unreachable!(),
+        }
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        Some(match offset & 0x3 {
+            DATA => self.rx_data,
+            STATUS => self.status,
+            COMMAND => self.command,
+            _ => self.control,
+        })
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        match offset & 0x3 {
+            DATA => {
+                self.tx_data = val;
+                self.status &= !STATUS_TDRE;
+                if !self.tx_busy {
+                    self.start_frame();
+                }
+            }
+            STATUS => {
+                self.command &= !COMMAND_PROGRAMMED_RESET;
+                self.command |= COMMAND_RX_IRQ_DISABLE;
+                self.status &= !STATUS_OVERRUN;
+            }
+            COMMAND => self.command = val,
+            CONTROL => self.control = val,
+            _ => unreachable!(),
+        }
+    }
+
+    fn connect(&mut self, scheduler: Scheduler) {
+        self.rx_event = Some(scheduler.schedule_in(0, RECEIVE));
+        self.scheduler = Some(scheduler);
+    }
+
+    fn event(&mut self, event: u32) {
+        match event {
+            TRANSMITTED => self.transmitted(),
+            RECEIVE => self.receive(),
+            _ => {}
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.status & STATUS_IRQ != 0
+    }
+
+    fn state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        state
+            .u8(self.rx_data)
+            .u8(self.tx_data)
+            .u8(self.status)
+            .u8(self.command)
+            .u8(self.control)
+            .bool(self.tx_busy)
+            .u64(self.deadline(self.tx_event))
+            .u64(self.deadline(self.rx_event))
+            .u8(self.tx_shift);
+        state.into_bytes()
+    }
+
+    fn state_version(&self) -> u8 {
+        3 // 2: deadlines rather than countdowns, 3: the shift register
+    }
+
+    fn restore(&mut self, state: &[u8], version: u8) {
+        let mut state = StateReader::new(state);
+        self.rx_data = state.u8();
+        self.tx_data = state.u8();
+        self.status = state.u8();
+        self.command = state.u8();
+        self.control = state.u8();
+        self.tx_busy = state.bool();
+        let deadlines = match version {
+            1 => {
+                // cycles left, counted from the restored cycle counter
+                let now = self.scheduler.as_ref().map_or(0, Scheduler::now);
+                let tx = now + state.u32() as u64;
+                let rx = now + state.u32() as u64;
+                [if self.tx_busy { tx } else { NO_DEADLINE }, rx]
+            }
+            _ => [state.u64(), state.u64()],
+        };
+        if version < 3 {
+            // the character on the line was still in the data register
+            self.tx_shift = self.tx_data;
+            if self.tx_busy {
+                self.status |= STATUS_TDRE;
+            }
+        } else {
+            self.tx_shift = state.u8();
+        }
+        let Some(scheduler) = self.scheduler.as_ref() else {
+            return;
+        };
+        // the events pending now belong to another point in time
+        for event in [self.tx_event, self.rx_event].into_iter().flatten() {
+            scheduler.cancel(event);
+        }
+        let [tx, rx] = deadlines.map(|at| (at != NO_DEADLINE).then_some(at));
+        self.tx_event = tx.map(|at| scheduler.schedule(at, TRANSMITTED));
+        self.rx_event = rx.map(|at| scheduler.schedule(at, RECEIVE));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    use super::*;
+    use crate::mos6502::Mos6502;
+
+    struct Loopback {
+        input: VecDeque<u8>,
+        output: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl SerialHost for Loopback {
+        fn poll(&mut self) -> Option<u8> {
+            self.input.pop_front()
+        }
+
+        fn send(&mut self, byte: u8) {
+            self.output.borrow_mut().push(byte);
+        }
+    }
+
+    type Attached = (Mos6502, Rc<RefCell<Acia>>, Rc<RefCell<Vec<u8>>>);
+
+    ///
+    /// an acia on a cpu running NOPs, time passes with `run_until`
+    ///
+    fn acia_with(input: &[u8]) -> Attached {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let host = Loopback {
+            input: input.iter().copied().collect(),
+            output: output.clone(),
+        };
+        let (cpu, acia) = Mos6502::nops_with(0xc000, Acia::new(Box::new(host), 1_000_000));
+        (cpu, acia, output)
+    }
+
+    #[test]
+    fn test_transmit_takes_one_frame() {
+        let (mut cpu, acia, output) = acia_with(&[]);
+        // 8N1 at 9600 baud: 10 bits at 1 MHz are 1042 cycles
+        acia.borrow_mut().write(CONTROL, 0x0e);
+        acia.borrow_mut().write(COMMAND, 0x0b);
+        acia.borrow_mut().write(DATA, b'A');
+        cpu.run_until(1040);
+        assert!(output.borrow().is_empty());
+        cpu.run_until(1042);
+        assert_eq!(vec![b'A'], *output.borrow());
+        assert_eq!(STATUS_TDRE, acia.borrow_mut().read(STATUS) & STATUS_TDRE);
+    }
+
+    #[test]
+    fn test_next_character_waits_in_the_data_register() {
+        let (mut cpu, acia, output) = acia_with(&[]);
+        acia.borrow_mut().write(CONTROL, 0x0e);
+        acia.borrow_mut().write(COMMAND, 0x0b);
+        acia.borrow_mut().write(DATA, b'A');
+        // A went on the line, the register is free for B
+        assert_eq!(STATUS_TDRE, acia.borrow_mut().read(STATUS) & STATUS_TDRE);
+        acia.borrow_mut().write(DATA, b'B');
+        assert_eq!(0, acia.borrow_mut().read(STATUS) & STATUS_TDRE);
+        cpu.run_until(1042);
+        assert_eq!(vec![b'A'], *output.borrow());
+        assert_eq!(STATUS_TDRE, acia.borrow_mut().read(STATUS) & STATUS_TDRE);
+        cpu.run_until(2084);
+        assert_eq!(vec![b'A', b'B'], *output.borrow());
+    }
+
+    #[test]
+    fn test_receive_raises_irq_until_status_is_read() {
+        let (mut cpu, acia, _) = acia_with(b"hi");
+        acia.borrow_mut().write(CONTROL, 0x1f);
+        acia.borrow_mut().write(COMMAND, 0x09);
+        cpu.step();
+        let mut acia = acia.borrow_mut();
+        assert!(acia.irq());
+        assert_eq!(STATUS_IRQ | STATUS_TDRE | STATUS_RDRF, acia.read(STATUS));
+        assert!(!acia.irq());
+        assert_eq!(b'h', acia.read(DATA));
+        assert_eq!(0, acia.read(STATUS) & STATUS_RDRF);
+    }
+
+    #[test]
+    fn test_receiver_waits_for_full_register() {
+        let (mut cpu, acia, _) = acia_with(b"hi");
+        acia.borrow_mut().write(CONTROL, 0x1f);
+        acia.borrow_mut().write(COMMAND, 0x0b);
+        cpu.run_until(10_000);
+        assert_eq!(b'h', acia.borrow_mut().read(DATA));
+        cpu.run_until(20_000);
+        assert_eq!(b'i', acia.borrow_mut().read(DATA));
+    }
+
+    #[test]
+    fn test_pending_events_survive_a_snapshot() {
+        let (mut cpu, acia, output) = acia_with(&[]);
+        acia.borrow_mut().write(CONTROL, 0x0e);
+        acia.borrow_mut().write(COMMAND, 0x0b);
+        acia.borrow_mut().write(DATA, b'A');
+        let snapshot = cpu.snapshot();
+        cpu.run_until(2000);
+        cpu.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(0, cpu.cycles());
+        cpu.run_until(2000);
+        assert_eq!(vec![b'A', b'A'], *output.borrow());
+    }
+
+    #[test]
+    fn test_programmed_reset_keeps_control() {
+        let (_, acia, _) = acia_with(&[]);
+        let mut acia = acia.borrow_mut();
+        acia.write(CONTROL, 0x1f);
+        acia.write(COMMAND, 0xff);
+        acia.write(STATUS, 0);
+        assert_eq!(0xe2, acia.read(COMMAND));
+        assert_eq!(0x1f, acia.read(CONTROL));
+    }
+}
@@ -0,0 +1,441 @@
+use std::{cell::RefCell, io, rc::Rc};
+
+use super::Device;
+
+///
+/// register side of a bank switching scheme, it decides which bank of the
+/// backing memory each window of the banked space shows
+///
+pub trait Mapper {
+    ///
+    /// a write to the mapper registers, `offset` is relative to the device
+    /// the write went through
+    ///
+    fn write(&mut self, offset: u16, val: u8);
+
+    ///
+    /// bank shown in `window`, taken modulo the number of banks
+    ///
+    fn bank(&self, window: usize) -> usize;
+
+    fn state(&self) -> Vec<u8>;
+
+    fn restore(&mut self, state: &[u8]);
+}
+
+///
+/// memory larger than the address space seen through `windows` windows of
+/// `window_size` bytes each. Writes to banked ROM program the mapper the way
+/// cartridge mappers decode them, writes to banked RAM land in the bank.
+///
+pub struct BankedMemory {
+    memory: Vec<u8>,
+    window_size: usize,
+    windows: usize,
+    writable: bool,
+    mapper: Rc<RefCell<dyn Mapper>>,
+}
+
+impl BankedMemory {
+    pub fn rom(
+        memory: Vec<u8>,
+        window_size: usize,
+        windows: usize,
+        mapper: Rc<RefCell<dyn Mapper>>,
+    ) -> io::Result<Self> {
+        Self::new(memory, window_size, windows, false, mapper)
+    }
+
+    ///
+    /// banked RAM has its mapper registers elsewhere, see `MapperPort`
+    ///
+    pub fn ram(
+        memory: Vec<u8>,
+        window_size: usize,
+        windows: usize,
+        mapper: Rc<RefCell<dyn Mapper>>,
+    ) -> io::Result<Self> {
+        Self::new(memory, window_size, windows, true, mapper)
+    }
+
+    ///
+    /// the windows together have to fit a device window, up to $ffff bytes
+    ///
+    fn new(
+        memory: Vec<u8>,
+        window_size: usize,
+        windows: usize,
+        writable: bool,
+        mapper: Rc<RefCell<dyn Mapper>>,
+    ) -> io::Result<Self> {
+        let size = window_size.saturating_mul(windows);
+        if size == 0 || size > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("banked windows of {} bytes do not fit the bus", size),
+            ));
+        }
+        Ok(Self {
+            memory,
+            window_size,
+            windows,
+            writable,
+            mapper,
+        })
+    }
+
+    fn index(&self, offset: u16) -> usize {
+        let window = offset as usize / self.window_size;
+        let banks = (self.memory.len() / self.window_size).max(1);
+        let bank = self.mapper.borrow().bank(window) % banks;
+        bank * self.window_size + offset as usize % self.window_size
+    }
+}
+
+impl Device for BankedMemory {
+    fn size(&self) -> u16 {
+        (self.window_size * self.windows) as u16
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        self.memory.get(self.index(offset)).copied().unwrap_or(0)
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        Some(self.memory.get(self.index(offset)).copied().unwrap_or(0))
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        if !self.writable {
+            self.mapper.borrow_mut().write(offset, val);
+            return;
+        }
+        let index = self.index(offset);
+        if let Some(cell) = self.memory.get_mut(index) {
+            *cell = val;
+        }
+    }
+
+    ///
+    /// mapper registers followed by the banked RAM, ROM contents are not state
+    ///
+    fn state(&self) -> Vec<u8> {
+        let mapper = self.mapper.borrow().state();
+        let mut state = Vec::with_capacity(1 + mapper.len() + self.memory.len());
+        state.push(mapper.len() as u8);
+        state.extend_from_slice(&mapper);
+        if self.writable {
+            state.extend_from_slice(&self.memory);
+        }
+        state
+    }
+
+    fn restore(&mut self, state: &[u8], _version: u8) {
+        let Some((&mapper_len, rest)) = state.split_first() else {
+            return;
+        };
+        let (mapper, memory) = rest.split_at((mapper_len as usize).min(rest.len()));
+        self.mapper.borrow_mut().restore(mapper);
+        if self.writable && memory.len() == self.memory.len() {
+            self.memory.copy_from_slice(memory);
+        }
+    }
+}
+
+///
+/// mapper registers mapped apart from the banked window, such as the bank
+/// latch of a RAM expansion. Reads return nothing.
+///
+pub struct MapperPort {
+    mapper: Rc<RefCell<dyn Mapper>>,
+    size: u16,
+}
+
+impl MapperPort {
+    pub fn new(mapper: Rc<RefCell<dyn Mapper>>, size: u16) -> Self {
+        Self { mapper, size }
+    }
+}
+
+impl Device for MapperPort {
+    fn size(&self) -> u16 {
+        self.size
+    }
+
+    fn read(&mut self, _offset: u16) -> u8 {
+        0
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        self.mapper.borrow_mut().write(offset, val);
+    }
+}
+
+///
+/// NES UxROM: a switchable 16K window at $8000, the last bank fixed at $C000
+///
+pub struct UxRom {
+    banks: usize,
+    select: u8,
+}
+
+impl UxRom {
+    pub fn new(banks: usize) -> Self {
+        Self { banks, select: 0 }
+    }
+}
+
+impl Mapper for UxRom {
+    fn write(&mut self, _offset: u16, val: u8) {
+        self.select = val;
+    }
+
+    fn bank(&self, window: usize) -> usize {
+        match window {
+            0 => self.select as usize,
+            _ => self.banks.saturating_sub(1),
+        }
+    }
+
+    fn state(&self) -> Vec<u8> {
+        vec![self.select]
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        if let [select] = state {
+            self.select = *select;
+        }
+    }
+}
+
+///
+/// NES CNROM: fixed program ROM, the register picks the 8K character bank
+/// the PPU would see
+///
+#[derive(Default)]
+pub struct CnRom {
+    chr_bank: u8,
+}
+
+impl CnRom {
+    pub fn chr_bank(&self) -> u8 {
+        self.chr_bank
+    }
+}
+
+impl Mapper for CnRom {
+    fn write(&mut self, _offset: u16, val: u8) {
+        self.chr_bank = val & 0b11;
+    }
+
+    fn bank(&self, window: usize) -> usize {
+        window
+    }
+
+    fn state(&self) -> Vec<u8> {
+        vec![self.chr_bank]
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        if let [chr_bank] = state {
+            self.chr_bank = *chr_bank;
+        }
+    }
+}
+
+const MMC1_RESET: u8 = 0b10000000;
+const MMC1_PRG_MODE: u8 = 0b01100;
+const MMC1_PRG_FIX_FIRST: u8 = 0b01000;
+const MMC1_PRG_FIX_LAST: u8 = 0b01100;
+const MMC1_SHIFT_EMPTY: u8 = 0b10000; // the marker bit reaches bit 0 after five writes
+
+///
+/// NES MMC1: registers are loaded one bit per write through a serial port,
+/// the program ROM is seen through two 16K windows at $8000 and $C000
+///
+pub struct Mmc1 {
+    banks: usize, // 16K program banks
+    shift: u8,
+    control: u8,
+    chr_banks: [u8; 2],
+    prg_bank: u8,
+}
+
+impl Mmc1 {
+    pub fn new(banks: usize) -> Self {
+        Self {
+            banks,
+            shift: MMC1_SHIFT_EMPTY,
+            control: MMC1_PRG_FIX_LAST,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+        }
+    }
+
+    pub fn chr_banks(&self) -> [u8; 2] {
+        self.chr_banks
+    }
+
+    pub fn mirroring(&self) -> u8 {
+        self.control & 0b11
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn write(&mut self, offset: u16, val: u8) {
+        if val & MMC1_RESET != 0 {
+            self.shift = MMC1_SHIFT_EMPTY;
+            self.control |= MMC1_PRG_FIX_LAST;
+            return;
+        }
+        let full = self.shift & 0b1 != 0;
+        self.shift = self.shift >> 1 | (val & 0b1) << 4;
+        if !full {
+            return;
+        }
+        // bits 13 and 14 of the address of the fifth write pick the register
+        match (offset >> 13) & 0b11 {
+            0 => self.control = self.shift,
+            1 => self.chr_banks[0] = self.shift,
+            2 => self.chr_banks[1] = self.shift,
+            _ => self.prg_bank = self.shift & 0b1111,
+        }
+        self.shift = MMC1_SHIFT_EMPTY;
+    }
+
+    fn bank(&self, window: usize) -> usize {
+        let prg_bank = self.prg_bank as usize;
+        match (self.control & MMC1_PRG_MODE, window) {
+            (MMC1_PRG_FIX_FIRST, 0) => 0,
+            (MMC1_PRG_FIX_FIRST, _) => prg_bank,
+            (MMC1_PRG_FIX_LAST, 0) => prg_bank,
+            (MMC1_PRG_FIX_LAST, _) => self.banks.saturating_sub(1),
+            // 32K mode ignores the low bit
+            (_, window) => prg_bank & !0b1 | window,
+        }
+    }
+
+    fn state(&self) -> Vec<u8> {
+        vec![
+            self.shift,
+            self.control,
+            self.chr_banks[0],
+            self.chr_banks[1],
+            self.prg_bank,
+        ]
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        if let [shift, control, chr_0, chr_1, prg_bank] = state {
+            self.shift = *shift;
+            self.control = *control;
+            self.chr_banks = [*chr_0, *chr_1];
+            self.prg_bank = *prg_bank;
+        }
+    }
+}
+
+///
+/// one register, the last value written selects the bank of the first window
+///
+#[derive(Default)]
+pub struct Latch {
+    select: u8,
+}
+
+impl Mapper for Latch {
+    fn write(&mut self, _offset: u16, val: u8) {
+        self.select = val;
+    }
+
+    fn bank(&self, window: usize) -> usize {
+        match window {
+            0 => self.select as usize,
+            _ => 0,
+        }
+    }
+
+    fn state(&self) -> Vec<u8> {
+        vec![self.select]
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        if let [select] = state {
+            self.select = *select;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K16: usize = 0x4000;
+
+    ///
+    /// every 16K bank filled with its own number
+    ///
+    fn numbered_banks(banks: usize) -> Vec<u8> {
+        (0..banks).flat_map(|bank| vec![bank as u8; K16]).collect()
+    }
+
+    #[test]
+    fn test_uxrom_switches_low_window_only() {
+        let mapper = Rc::new(RefCell::new(UxRom::new(8)));
+        let mut rom = BankedMemory::rom(numbered_banks(8), K16, 2, mapper).unwrap();
+        assert_eq!(0, rom.read(0x0000));
+        assert_eq!(7, rom.read(0x4000));
+        rom.write(0x1234, 5);
+        assert_eq!(5, rom.read(0x0000));
+        assert_eq!(7, rom.read(0x7fff));
+    }
+
+    #[test]
+    fn test_mmc1_loads_registers_serially() {
+        let mapper = Rc::new(RefCell::new(Mmc1::new(8)));
+        let mut rom = BankedMemory::rom(numbered_banks(8), K16, 2, mapper.clone()).unwrap();
+        // prg bank 3, written lowest bit first to $E000
+        for bit in [1, 1, 0, 0, 0] {
+            rom.write(0x6000, bit);
+        }
+        assert_eq!(3, rom.read(0x0000));
+        assert_eq!(7, rom.read(0x4000));
+        // a reset in the middle throws the partial value away
+        rom.write(0x6000, 1);
+        rom.write(0x6000, MMC1_RESET);
+        assert_eq!(MMC1_SHIFT_EMPTY, mapper.borrow().shift);
+    }
+
+    #[test]
+    fn test_latch_banks_ram_and_state_round_trips() {
+        let mapper: Rc<RefCell<dyn Mapper>> = Rc::new(RefCell::new(Latch::default()));
+        let mut ram = BankedMemory::ram(vec![0; 4 * K16], K16, 1, mapper.clone()).unwrap();
+        let mut port = MapperPort::new(mapper, 1);
+        port.write(0, 2);
+        ram.write(0x0010, 0xaa);
+        port.write(0, 1);
+        assert_eq!(0x00, ram.read(0x0010));
+        let state = ram.state();
+        port.write(0, 0);
+        ram.write(0x0010, 0x55);
+        ram.restore(&state, ram.state_version());
+        assert_eq!(0x00, ram.read(0x0010));
+        port.write(0, 2);
+        assert_eq!(0xaa, ram.read(0x0010));
+    }
+
+    #[test]
+    fn test_windows_have_to_fit_the_bus() {
+        let mapper: Rc<RefCell<dyn Mapper>> = Rc::new(RefCell::new(Latch::default()));
+        let err = BankedMemory::ram(vec![0; 0x20000], 0x10000, 1, mapper.clone())
+            .err()
+            .unwrap();
+        assert_eq!(
+            "banked windows of 65536 bytes do not fit the bus",
+            err.to_string()
+        );
+        assert!(BankedMemory::rom(numbered_banks(8), K16, 4, mapper.clone()).is_err());
+        assert!(BankedMemory::ram(vec![0; K16], 0, 1, mapper.clone()).is_err());
+        assert!(BankedMemory::ram(vec![0; K16], 0xffff, 1, mapper).is_ok());
+    }
+}
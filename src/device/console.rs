@@ -0,0 +1,88 @@
+use super::{
+    serial::{SerialHost, StdioHost},
+    Device,
+};
+use crate::mos6502::scheduler::Scheduler;
+
+///
+/// single register character device, writing prints the character on the
+/// host and reading returns the next key typed or 0 when none is waiting.
+/// py65 maps the same behaviour at $F001.
+///
+pub struct Console {
+    host: Box<dyn SerialHost>,
+    scheduler: Option<Scheduler>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::with_host(Box::new(StdioHost::new()))
+    }
+
+    ///
+    /// the characters go to and come from `host` instead of the terminal
+    ///
+    pub fn with_host(host: Box<dyn SerialHost>) -> Self {
+        Self {
+            host,
+            scheduler: None,
+        }
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Console {
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn read(&mut self, _offset: u16) -> u8 {
+        let host = &mut self.host;
+        match self.scheduler.as_ref() {
+            Some(scheduler) => scheduler.input(|| host.poll()),
+            None => host.poll(),
+        }
+        .unwrap_or(0)
+    }
+
+    fn write(&mut self, _offset: u16, val: u8) {
+        self.host.send(val);
+    }
+
+    fn connect(&mut self, scheduler: Scheduler) {
+        self.scheduler = Some(scheduler);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::BufferHost;
+    use crate::mos6502::Mos6502;
+
+    #[test]
+    fn test_writes_reach_the_host() {
+        let host = BufferHost::default();
+        let mut console = Console::with_host(Box::new(host.clone()));
+        console.write(0, b'h');
+        console.write(0, b'i');
+        assert_eq!(b"hi".to_vec(), host.output());
+    }
+
+    #[test]
+    fn test_reads_take_typed_keys_then_zero() {
+        let host = BufferHost::default();
+        host.type_bytes(b"ok");
+        let mut cpu = Mos6502::default();
+        cpu.attach(0xf001, Box::new(Console::with_host(Box::new(host.clone()))));
+        assert_eq!(b'o', cpu.peek(0xf001));
+        assert_eq!(b'k', cpu.peek(0xf001));
+        assert_eq!(0, cpu.peek(0xf001));
+        assert_eq!(0, host.pending());
+    }
+}
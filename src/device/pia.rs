@@ -0,0 +1,319 @@
+use super::{Device, StateReader, StateWriter};
+
+///
+/// Motorola 6821 / MOS 6520 peripheral interface adapter. Each side has a
+/// data direction register and a peripheral register sharing one address,
+/// bit 2 of its control register picks which one is visible.
+///
+const PORT_A: u16 = 0x0;
+const CRA: u16 = 0x1;
+const PORT_B: u16 = 0x2;
+const CRB: u16 = 0x3;
+
+// control register layout: IRQ1 IRQ2 C2 control (3) DDR access C1 control (2)
+const CR_C1_IRQ_ENABLE: u8 = 0b00000001;
+const CR_C1_POSITIVE_EDGE: u8 = 0b00000010;
+const CR_PERIPHERAL: u8 = 0b00000100;
+const CR_C2_IRQ_ENABLE: u8 = 0b00001000; // C2 as input
+const CR_C2_POSITIVE_EDGE: u8 = 0b00010000; // C2 as input
+const CR_C2_OUTPUT: u8 = 0b00100000;
+const CR_C2_MANUAL: u8 = 0b00010000; // C2 as output, bit 3 is the level
+const CR_C2_PULSE: u8 = 0b00001000; // C2 as output in handshake mode
+const CR_IRQ2: u8 = 0b01000000;
+const CR_IRQ1: u8 = 0b10000000;
+const CR_WRITABLE: u8 = 0b00111111;
+
+///
+/// one half of the PIA, both sides only differ in when the C2 handshake fires
+///
+#[derive(Default)]
+struct Side {
+    output: u8,
+    ddr: u8,
+    pins_in: u8, // levels driven on the port pins from outside
+    control: u8,
+    c1: bool,
+    c2: bool,
+    c2_pulse: bool,
+}
+
+impl Side {
+    fn pins(&self) -> u8 {
+        self.output & self.ddr | self.pins_in & !self.ddr
+    }
+
+    fn set_c1(&mut self, level: bool) {
+        let positive = self.control & CR_C1_POSITIVE_EDGE != 0;
+        if is_active_edge(self.c1, level, positive) {
+            self.control |= CR_IRQ1;
+            let c2_mode = self.control & (CR_C2_OUTPUT | CR_C2_MANUAL | CR_C2_PULSE);
+            if c2_mode == CR_C2_OUTPUT {
+                // handshake: the peripheral answered, C2 goes back high
+                self.c2 = true;
+            }
+        }
+        self.c1 = level;
+    }
+
+    fn set_c2(&mut self, level: bool) {
+        if self.control & CR_C2_OUTPUT != 0 {
+            return;
+        }
+        let positive = self.control & CR_C2_POSITIVE_EDGE != 0;
+        if is_active_edge(self.c2, level, positive) {
+            self.control |= CR_IRQ2;
+        }
+        self.c2 = level;
+    }
+
+    fn write_control(&mut self, val: u8) {
+        self.control = self.control & !CR_WRITABLE | val & CR_WRITABLE;
+        if val & CR_C2_OUTPUT != 0 {
+            self.control &= !CR_IRQ2;
+            if val & CR_C2_MANUAL != 0 {
+                self.c2 = val & CR_C2_PULSE != 0;
+            }
+        }
+    }
+
+    ///
+    /// the peripheral register was accessed in the direction that drives the
+    /// C2 handshake
+    ///
+    fn handshake(&mut self) {
+        if self.control & (CR_C2_OUTPUT | CR_C2_MANUAL) == CR_C2_OUTPUT {
+            self.c2 = false;
+            self.c2_pulse = self.control & CR_C2_PULSE != 0;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        let irq1 = self.control & CR_IRQ1 != 0 && self.control & CR_C1_IRQ_ENABLE != 0;
+        let irq2 = self.control & CR_IRQ2 != 0
+            && self.control & CR_C2_OUTPUT == 0
+            && self.control & CR_C2_IRQ_ENABLE != 0;
+        irq1 || irq2
+    }
+
+    fn save(&self, state: &mut StateWriter) {
+        state
+            .u8(self.output)
+            .u8(self.ddr)
+            .u8(self.pins_in)
+            .u8(self.control)
+            .bool(self.c1)
+            .bool(self.c2)
+            .bool(self.c2_pulse);
+    }
+
+    fn restore(&mut self, state: &mut StateReader) {
+        self.output = state.u8();
+        self.ddr = state.u8();
+        self.pins_in = state.u8();
+        self.control = state.u8();
+        self.c1 = state.bool();
+        self.c2 = state.bool();
+        self.c2_pulse = state.bool();
+    }
+
+    fn tick(&mut self) {
+        if self.c2_pulse {
+            self.c2 = true;
+            self.c2_pulse = false;
+        }
+    }
+}
+
+fn is_active_edge(old: bool, new: bool, positive: bool) -> bool {
+    if positive {
+        !old && new
+    } else {
+        old && !new
+    }
+}
+
+#[derive(Default)]
+pub struct Pia {
+    a: Side,
+    b: Side,
+}
+
+impl Pia {
+    pub fn port_a(&self) -> u8 {
+        self.a.pins()
+    }
+
+    pub fn port_b(&self) -> u8 {
+        self.b.pins()
+    }
+
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.a.pins_in = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.b.pins_in = pins;
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        self.a.set_c1(level);
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        self.a.set_c2(level);
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        self.b.set_c1(level);
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        self.b.set_c2(level);
+    }
+
+    pub fn ca2(&self) -> bool {
+        self.a.c2
+    }
+
+    pub fn cb2(&self) -> bool {
+        self.b.c2
+    }
+
+    pub fn irq_a(&self) -> bool {
+        self.a.irq()
+    }
+
+    pub fn irq_b(&self) -> bool {
+        self.b.irq()
+    }
+}
+
+impl Device for Pia {
+    fn size(&self) -> u16 {
+        4
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x3 {
+            PORT_A if self.a.control & CR_PERIPHERAL != 0 => {
+                // reading port A clears its flags and drives the CA2 handshake
+                self.a.control &= !(CR_IRQ1 | CR_IRQ2);
+                self.a.handshake();
+                self.a.pins()
+            }
+            PORT_A => self.a.ddr,
+            CRA => self.a.control,
+            PORT_B if self.b.control & CR_PERIPHERAL != 0 => {
+                self.b.control &= !(CR_IRQ1 | CR_IRQ2);
+                // output bits read back from the output register
+                self.b.output & self.b.ddr | self.b.pins_in & !self.b.ddr
+            }
+            PORT_B => self.b.ddr,
+            CRB => self.b.control,
+            _ => unreachable!(),
+        }
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        Some(match offset & 0x3 {
+            PORT_A if self.a.control & CR_PERIPHERAL != 0 => self.a.pins(),
+            PORT_A => self.a.ddr,
+            CRA => self.a.control,
+            PORT_B if self.b.control & CR_PERIPHERAL != 0 => {
+                self.b.output & self.b.ddr | self.b.pins_in & !self.b.ddr
+            }
+            PORT_B => self.b.ddr,
+            _ => self.b.control,
+        })
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        match offset & 0x3 {
+            PORT_A if self.a.control & CR_PERIPHERAL != 0 => self.a.output = val,
+            PORT_A => self.a.ddr = val,
+            CRA => self.a.write_control(val),
+            PORT_B if self.b.control & CR_PERIPHERAL != 0 => {
+                // writing port B drives the CB2 handshake
+                self.b.output = val;
+                self.b.handshake();
+            }
+            PORT_B => self.b.ddr = val,
+            CRB => self.b.write_control(val),
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if cycles > 0 {
+            self.a.tick();
+            self.b.tick();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_a() || self.irq_b()
+    }
+
+    fn state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        self.a.save(&mut state);
+        self.b.save(&mut state);
+        state.into_bytes()
+    }
+
+    fn restore(&mut self, state: &[u8], _version: u8) {
+        let mut state = StateReader::new(state);
+        self.a.restore(&mut state);
+        self.b.restore(&mut state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_bit_2_selects_ddr_or_port() {
+        let mut pia = Pia::default();
+        pia.write(PORT_B, 0x7f);
+        pia.write(CRB, CR_PERIPHERAL);
+        pia.write(PORT_B, 0xaa);
+        pia.set_port_b(0x80);
+        assert_eq!(0xaa, pia.read(PORT_B));
+        pia.write(CRB, 0);
+        assert_eq!(0x7f, pia.read(PORT_B));
+    }
+
+    #[test]
+    fn test_ca1_edge_sets_flag_until_port_is_read() {
+        let mut pia = Pia::default();
+        pia.write(CRA, CR_PERIPHERAL | CR_C1_POSITIVE_EDGE | CR_C1_IRQ_ENABLE);
+        pia.set_port_a(0xc1);
+        pia.set_ca1(true);
+        assert_eq!(CR_IRQ1, pia.read(CRA) & CR_IRQ1);
+        assert!(pia.irq());
+        assert_eq!(0xc1, pia.read(PORT_A));
+        assert_eq!(0, pia.read(CRA) & CR_IRQ1);
+        assert!(!pia.irq());
+    }
+
+    #[test]
+    fn test_cb2_handshake_on_port_b_write() {
+        let mut pia = Pia::default();
+        // CB2 handshake output, CB1 positive edge
+        pia.write(CRB, CR_C2_OUTPUT | CR_PERIPHERAL | CR_C1_POSITIVE_EDGE);
+        pia.write(PORT_B, 0x41);
+        assert!(!pia.cb2());
+        pia.set_cb1(true);
+        assert!(pia.cb2());
+    }
+
+    #[test]
+    fn test_ca2_manual_output() {
+        let mut pia = Pia::default();
+        pia.write(CRA, CR_C2_OUTPUT | CR_C2_MANUAL);
+        assert!(!pia.ca2());
+        pia.write(CRA, CR_C2_OUTPUT | CR_C2_MANUAL | CR_C2_PULSE);
+        assert!(pia.ca2());
+    }
+}
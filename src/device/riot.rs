@@ -0,0 +1,374 @@
+use super::{Device, StateReader, StateWriter};
+use crate::mos6502::scheduler::{EventId, Scheduler};
+
+///
+/// MOS 6532 RAM-I/O-timer. The chip has a separate RAM select pin, here it
+/// is tied to A7: the lower half of the window is the 128 bytes of RAM and
+/// the upper half holds the I/O registers and the interval timer. The timer
+/// is read off the cycle counter and its expiry is a scheduled event, it
+/// stands still until the chip is attached to a cpu.
+///
+pub const RAM_SIZE: u16 = 128;
+
+const IO_SELECT: u16 = 0x80;
+const RAM_MASK: u16 = 0x7f;
+
+// I/O decoding, A2 low selects the ports
+const TIMER_SELECT: u16 = 0b00100;
+const PORT_B_SELECT: u16 = 0b00010;
+const DDR_SELECT: u16 = 0b00001;
+const TIMER_WRITE: u16 = 0b10000; // otherwise the write programs the PA7 edge detection
+const TIMER_IRQ_ENABLE: u16 = 0b01000;
+const PRESCALER_SELECT: u16 = 0b00011;
+const FLAGS_READ: u16 = 0b00001; // otherwise the read returns the timer
+const EDGE_IRQ_ENABLE: u16 = 0b00010;
+const EDGE_POSITIVE: u16 = 0b00001;
+
+const FLAG_TIMER: u8 = 0b10000000;
+const FLAG_PA7: u8 = 0b01000000;
+
+const PA7: u8 = 0b10000000;
+
+// ÷1, ÷8, ÷64 and ÷1024 as shifts
+const PRESCALER_SHIFTS: [u8; 4] = [0, 3, 6, 10];
+
+// scheduled events
+const EXPIRED: u32 = 0; // the timer counted through zero
+
+pub struct Riot {
+    ram: [u8; RAM_SIZE as usize],
+    output_a: u8,
+    ddr_a: u8,
+    pins_a: u8, // levels driven on the port pins from outside
+    output_b: u8,
+    ddr_b: u8,
+    pins_b: u8,
+    timer: u8, // as written, it counts down from there
+    prescaler_shift: u8,
+    first_decrement: u64, // cycle of the first decrement after the write
+    expired: bool,        // counting through zero switches the timer to ÷1
+    timer_irq_enabled: bool,
+    edge_positive: bool,
+    edge_irq_enabled: bool,
+    flags: u8,
+    scheduler: Option<Scheduler>,
+    timer_event: Option<EventId>,
+}
+
+impl Default for Riot {
+    fn default() -> Self {
+        Self {
+            ram: [0; RAM_SIZE as usize],
+            output_a: 0,
+            ddr_a: 0,
+            pins_a: 0xff,
+            output_b: 0,
+            ddr_b: 0,
+            pins_b: 0xff,
+            timer: 0xff,
+            prescaler_shift: PRESCALER_SHIFTS[3],
+            first_decrement: 1 << PRESCALER_SHIFTS[3],
+            expired: false,
+            timer_irq_enabled: false,
+            edge_positive: false,
+            edge_irq_enabled: false,
+            flags: 0,
+            scheduler: None,
+            timer_event: None,
+        }
+    }
+}
+
+impl Riot {
+    pub fn port_a(&self) -> u8 {
+        self.output_a & self.ddr_a | self.pins_a & !self.ddr_a
+    }
+
+    pub fn port_b(&self) -> u8 {
+        self.output_b & self.ddr_b | self.pins_b & !self.ddr_b
+    }
+
+    ///
+    /// drive the port A pins, PA7 is watched for the programmed edge
+    ///
+    pub fn set_port_a(&mut self, pins: u8) {
+        let old = self.port_a() & PA7 != 0;
+        self.pins_a = pins;
+        let new = self.port_a() & PA7 != 0;
+        let active = if self.edge_positive {
+            !old && new
+        } else {
+            old && !new
+        };
+        if active {
+            self.flags |= FLAG_PA7;
+        }
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.pins_b = pins;
+    }
+
+    fn now(&self) -> u64 {
+        self.scheduler.as_ref().map_or(0, Scheduler::now)
+    }
+
+    fn write_timer(&mut self, offset: u16, val: u8) {
+        self.timer = val;
+        self.prescaler_shift = PRESCALER_SHIFTS[(offset & PRESCALER_SELECT) as usize];
+        // the first decrement comes one cycle after the write
+        self.first_decrement = self.now() + 1;
+        self.expired = false;
+        self.timer_irq_enabled = offset & TIMER_IRQ_ENABLE != 0;
+        self.flags &= !FLAG_TIMER;
+        self.schedule_expiry();
+    }
+
+    ///
+    /// the cycle the timer counts through zero
+    ///
+    fn expiry(&self) -> u64 {
+        self.first_decrement + ((self.timer as u64) << self.prescaler_shift)
+    }
+
+    fn read_timer(&self) -> u8 {
+        let now = self.now();
+        if now < self.first_decrement {
+            self.timer
+        } else if now < self.expiry() {
+            let decrements = (now - self.first_decrement) >> self.prescaler_shift;
+            self.timer - 1 - decrements as u8
+        } else {
+            0xff - (now - self.expiry()) as u8
+        }
+    }
+
+    fn schedule_expiry(&mut self) {
+        let Some(scheduler) = self.scheduler.as_ref() else {
+            return;
+        };
+        if let Some(event) = self.timer_event.take() {
+            scheduler.cancel(event);
+        }
+        if !self.expired {
+            self.timer_event = Some(scheduler.schedule(self.expiry(), EXPIRED));
+        }
+    }
+}
+
+impl Device for Riot {
+    fn size(&self) -> u16 {
+        2 * RAM_SIZE
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        if offset & IO_SELECT == 0 {
+            return self.ram[(offset & RAM_MASK) as usize];
+        }
+        if offset & TIMER_SELECT == 0 {
+            return match offset & (PORT_B_SELECT | DDR_SELECT) {
+                0 => self.port_a(),
+                DDR_SELECT => self.ddr_a,
+                PORT_B_SELECT => self.port_b(),
+                _ => self.ddr_b,
+            };
+        }
+        if offset & FLAGS_READ != 0 {
+            let flags = self.flags;
+            self.flags &= !FLAG_PA7;
+            return flags;
+        }
+        self.timer_irq_enabled = offset & TIMER_IRQ_ENABLE != 0;
+        self.flags &= !FLAG_TIMER;
+        self.read_timer()
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        if offset & IO_SELECT == 0 {
+            return Some(self.ram[(offset & RAM_MASK) as usize]);
+        }
+        if offset & TIMER_SELECT == 0 {
+            return Some(match offset & (PORT_B_SELECT | DDR_SELECT) {
+                0 => self.port_a(),
+                DDR_SELECT => self.ddr_a,
+                PORT_B_SELECT => self.port_b(),
+                _ => self.ddr_b,
+            });
+        }
+        if offset & FLAGS_READ != 0 {
+            return Some(self.flags);
+        }
+        Some(self.read_timer())
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        if offset & IO_SELECT == 0 {
+            self.ram[(offset & RAM_MASK) as usize] = val;
+            return;
+        }
+        if offset & TIMER_SELECT == 0 {
+            match offset & (PORT_B_SELECT | DDR_SELECT) {
+                0 => self.output_a = val,
+                DDR_SELECT => self.ddr_a = val,
+                PORT_B_SELECT => self.output_b = val,
+                _ => self.ddr_b = val,
+            }
+        } else if offset & TIMER_WRITE != 0 {
+            self.write_timer(offset, val);
+        } else {
+            self.edge_positive = offset & EDGE_POSITIVE != 0;
+            self.edge_irq_enabled = offset & EDGE_IRQ_ENABLE != 0;
+        }
+    }
+
+    fn connect(&mut self, scheduler: Scheduler) {
+        // until now the chip counted from cycle 0
+        self.first_decrement += scheduler.now();
+        self.scheduler = Some(scheduler);
+        self.schedule_expiry();
+    }
+
+    fn event(&mut self, event: u32) {
+        if event == EXPIRED {
+            self.timer_event = None;
+            self.expired = true;
+            self.flags |= FLAG_TIMER;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.flags & FLAG_TIMER != 0 && self.timer_irq_enabled
+            || self.flags & FLAG_PA7 != 0 && self.edge_irq_enabled
+    }
+
+    fn state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        state
+            .bytes(&self.ram)
+            .u8(self.output_a)
+            .u8(self.ddr_a)
+            .u8(self.pins_a)
+            .u8(self.output_b)
+            .u8(self.ddr_b)
+            .u8(self.pins_b)
+            .u8(self.timer)
+            .u8(self.prescaler_shift)
+            .u64(self.first_decrement)
+            .bool(self.expired)
+            .bool(self.timer_irq_enabled)
+            .bool(self.edge_positive)
+            .bool(self.edge_irq_enabled)
+            .u8(self.flags);
+        state.into_bytes()
+    }
+
+    fn state_version(&self) -> u8 {
+        2 // the cycle of the first decrement rather than the cycles until the next
+    }
+
+    fn restore(&mut self, state: &[u8], version: u8) {
+        let mut state = StateReader::new(state);
+        state.bytes(&mut self.ram);
+        self.output_a = state.u8();
+        self.ddr_a = state.u8();
+        self.pins_a = state.u8();
+        self.output_b = state.u8();
+        self.ddr_b = state.u8();
+        self.pins_b = state.u8();
+        self.timer = state.u8();
+        self.prescaler_shift = state.u8();
+        self.first_decrement = match version {
+            1 => self.now() + state.u32().max(1) as u64,
+            _ => state.u64(),
+        };
+        self.expired = state.bool();
+        if version == 1 && self.expired {
+            // the timer counts down every cycle, it went through zero as
+            // many cycles ago as it stands below $ff
+            let since = (0xff - self.timer) as u64;
+            self.first_decrement = self.now().saturating_sub(since);
+            self.timer = 0;
+        }
+        self.timer_irq_enabled = state.bool();
+        self.edge_positive = state.bool();
+        self.edge_irq_enabled = state.bool();
+        self.flags = state.u8();
+        self.schedule_expiry();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mos6502::Mos6502;
+
+    const TIMER: u16 = IO_SELECT | TIMER_SELECT | TIMER_WRITE;
+    const FLAGS: u16 = IO_SELECT | TIMER_SELECT | FLAGS_READ;
+
+    #[test]
+    fn test_ram_and_ports_share_the_window() {
+        let mut riot = Riot::default();
+        riot.write(0x05, 0x42);
+        riot.write(IO_SELECT | DDR_SELECT, 0x0f);
+        riot.write(IO_SELECT, 0xa5);
+        riot.set_port_a(0x30);
+        assert_eq!(0x42, riot.read(0x05));
+        assert_eq!(0x35, riot.read(IO_SELECT));
+    }
+
+    #[test]
+    fn test_timer_counts_with_prescaler() {
+        let (mut cpu, riot) = Mos6502::nops_with(0x1000, Riot::default());
+        // 3 at ÷8 with the interrupt enabled
+        riot.borrow_mut().write(TIMER | TIMER_IRQ_ENABLE | 0b01, 3);
+        cpu.run_until(2);
+        assert_eq!(2, riot.borrow_mut().read(TIMER | TIMER_IRQ_ENABLE));
+        cpu.run_until(24);
+        assert_eq!(0, riot.borrow_mut().read(TIMER | TIMER_IRQ_ENABLE));
+        assert!(!riot.borrow().irq());
+        cpu.run_until(26);
+        assert!(riot.borrow().irq());
+        assert_eq!(FLAG_TIMER, riot.borrow().flags & FLAG_TIMER);
+        // past zero the timer counts every cycle
+        cpu.run_until(28);
+        assert_eq!(0xfc, riot.borrow_mut().read(TIMER));
+        assert!(!riot.borrow().irq());
+    }
+
+    #[test]
+    fn test_expiry_is_a_single_event() {
+        let (mut cpu, riot) = Mos6502::nops_with(0x1000, Riot::default());
+        riot.borrow_mut().write(TIMER | TIMER_IRQ_ENABLE, 9);
+        assert_eq!(Some(10), cpu.next_event());
+        // a new count replaces the pending expiry
+        riot.borrow_mut().write(TIMER | TIMER_IRQ_ENABLE | 0b11, 1);
+        assert_eq!(Some(1025), cpu.next_event());
+        cpu.run_until(1026);
+        assert!(riot.borrow().irq());
+        assert_eq!(None, cpu.next_event());
+    }
+
+    #[test]
+    fn test_pending_expiry_survives_a_snapshot() {
+        let (mut cpu, riot) = Mos6502::nops_with(0x1000, Riot::default());
+        riot.borrow_mut().write(TIMER | TIMER_IRQ_ENABLE | 0b01, 3);
+        let snapshot = cpu.snapshot();
+        cpu.run_until(30);
+        assert!(riot.borrow().irq());
+        cpu.restore_snapshot(&snapshot).unwrap();
+        assert!(!riot.borrow().irq());
+        assert_eq!(Some(25), cpu.next_event());
+        cpu.run_until(30);
+        assert!(riot.borrow().irq());
+    }
+
+    #[test]
+    fn test_pa7_edge_sets_flag_until_read() {
+        let mut riot = Riot::default();
+        riot.write(IO_SELECT | TIMER_SELECT | EDGE_IRQ_ENABLE, 0);
+        riot.set_port_a(0x7f);
+        assert!(riot.irq());
+        assert_eq!(FLAG_PA7, riot.read(FLAGS));
+        assert!(!riot.irq());
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    rc::Rc,
+};
+
+use super::terminal::Terminal;
+
+///
+/// host end of a serial line, serial devices pull received bytes from it
+/// and push transmitted bytes into it
+///
+pub trait SerialHost {
+    ///
+    /// next byte sent by the host, None when nothing is waiting
+    ///
+    fn poll(&mut self) -> Option<u8>;
+
+    fn send(&mut self, byte: u8);
+}
+
+///
+/// the serial line is the terminal the emulator runs in
+///
+pub struct StdioHost {
+    terminal: Terminal,
+}
+
+impl StdioHost {
+    pub fn new() -> Self {
+        Self {
+            terminal: Terminal::new(),
+        }
+    }
+}
+
+impl Default for StdioHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SerialHost for StdioHost {
+    fn poll(&mut self) -> Option<u8> {
+        self.terminal.poll_key()
+    }
+
+    fn send(&mut self, byte: u8) {
+        self.terminal.put_char(byte);
+    }
+}
+
+///
+/// serial line served on a localhost TCP port, one client at a time.
+/// Bytes sent while nobody is connected are dropped like on a loose cable.
+///
+pub struct TcpHost {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+}
+
+impl TcpHost {
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            client: None,
+        })
+    }
+
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    fn accept(&mut self) {
+        if self.client.is_some() {
+            return;
+        }
+        if let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                let _ = stream.set_nodelay(true);
+                self.client = Some(stream);
+            }
+        }
+    }
+}
+
+impl SerialHost for TcpHost {
+    fn poll(&mut self) -> Option<u8> {
+        self.accept();
+        let client = self.client.as_mut()?;
+        let mut byte = [0u8];
+        match client.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => None,
+            _ => {
+                // the client hung up
+                self.client = None;
+                None
+            }
+        }
+    }
+
+    fn send(&mut self, byte: u8) {
+        self.accept();
+        if let Some(client) = self.client.as_mut() {
+            if client.write_all(&[byte]).is_err() {
+                self.client = None;
+            }
+        }
+    }
+}
+
+///
+/// serial line driven by a program rather than a person: bytes typed into
+/// it wait until a device takes them, and what the devices send is kept.
+/// Clones are the same line.
+///
+#[derive(Clone, Default)]
+pub struct BufferHost {
+    typed: Rc<RefCell<VecDeque<u8>>>,
+    sent: Rc<RefCell<Vec<u8>>>,
+}
+
+impl BufferHost {
+    pub fn type_bytes(&self, bytes: &[u8]) {
+        self.typed.borrow_mut().extend(bytes);
+    }
+
+    ///
+    /// bytes typed that no device took yet
+    ///
+    pub fn pending(&self) -> usize {
+        self.typed.borrow().len()
+    }
+
+    ///
+    /// everything the devices sent so far
+    ///
+    pub fn output(&self) -> Vec<u8> {
+        self.sent.borrow().clone()
+    }
+}
+
+impl SerialHost for BufferHost {
+    fn poll(&mut self) -> Option<u8> {
+        self.typed.borrow_mut().pop_front()
+    }
+
+    fn send(&mut self, byte: u8) {
+        self.sent.borrow_mut().push(byte);
+    }
+}
+
+///
+/// open the host end described as `stdio`, `pty` or `tcp:<port>`. Where to
+/// reach a pty or socket is reported on stderr.
+///
+pub fn open_host(spec: &str) -> io::Result<Box<dyn SerialHost>> {
+    match spec.split_once(':') {
+        None if spec == "stdio" => Ok(Box::new(StdioHost::new())),
+        #[cfg(target_os = "linux")]
+        None if spec == "pty" => {
+            let pty = PtyHost::open()?;
+            eprintln!("serial line attached to {}", pty.path());
+            Ok(Box::new(pty))
+        }
+        Some(("tcp", port)) => {
+            let port = port.parse().map_err(|_| {
+                io::Error::new(ErrorKind::InvalidInput, format!("bad tcp port {}", port))
+            })?;
+            let tcp = TcpHost::bind(port)?;
+            eprintln!("serial line listening on 127.0.0.1:{}", tcp.port()?);
+            Ok(Box::new(tcp))
+        }
+        _ => Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("unknown serial host {}", spec),
+        )),
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use pty::PtyHost;
+
+#[cfg(target_os = "linux")]
+mod pty {
+    use std::{
+        ffi::CStr,
+        fs::{File, OpenOptions},
+        io::{self, Read, Write},
+        os::{
+            fd::{AsRawFd, FromRawFd},
+            unix::fs::OpenOptionsExt,
+        },
+    };
+
+    use super::SerialHost;
+
+    ///
+    /// serial line exposed as a pseudo terminal, a terminal program such as
+    /// minicom attaches to the device `path` names
+    ///
+    pub struct PtyHost {
+        master: File,
+        path: String,
+        // held open so the line survives terminal programs coming and going
+        _slave: File,
+    }
+
+    impl PtyHost {
+        pub fn open() -> io::Result<Self> {
+            let flags = libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK;
+            let fd = unsafe { libc::posix_openpt(flags) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = unsafe { File::from_raw_fd(fd) };
+            if unsafe { libc::grantpt(fd) } != 0 || unsafe { libc::unlockpt(fd) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut name = [0 as libc::c_char; 128];
+            if unsafe { libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let path = unsafe { CStr::from_ptr(name.as_ptr()) }
+                .to_string_lossy()
+                .into_owned();
+
+            let slave = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NOCTTY)
+                .open(&path)?;
+            make_raw(&slave)?;
+            Ok(Self {
+                master,
+                path,
+                _slave: slave,
+            })
+        }
+
+        pub fn path(&self) -> &str {
+            &self.path
+        }
+    }
+
+    ///
+    /// no echo and no line editing until the attached program asks otherwise
+    ///
+    fn make_raw(tty: &File) -> io::Result<()> {
+        let mut termios = std::mem::MaybeUninit::uninit();
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), termios.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut termios = unsafe { termios.assume_init() };
+        unsafe { libc::cfmakeraw(&mut termios) };
+        if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    impl SerialHost for PtyHost {
+        fn poll(&mut self) -> Option<u8> {
+            let mut byte = [0u8];
+            match self.master.read(&mut byte) {
+                Ok(1) => Some(byte[0]),
+                _ => None,
+            }
+        }
+
+        fn send(&mut self, byte: u8) {
+            // a full buffer means nobody reads the line, the byte is dropped
+            let _ = self.master.write(&[byte]);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::Device;
+
+///
+/// RAM several cpus see on their buses, every clone is another view of the
+/// same bytes. Each cpu can map it at an address of its own.
+///
+#[derive(Clone)]
+pub struct SharedRam {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SharedRam {
+    pub fn new(size: u16) -> Self {
+        Self {
+            bytes: Rc::new(RefCell::new(vec![0; size as usize])),
+        }
+    }
+}
+
+impl Device for SharedRam {
+    fn size(&self) -> u16 {
+        self.bytes.borrow().len() as u16
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        self.bytes.borrow()[offset as usize]
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        Some(self.bytes.borrow()[offset as usize])
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        self.bytes.borrow_mut()[offset as usize] = val;
+    }
+
+    fn state(&self) -> Vec<u8> {
+        self.bytes.borrow().clone()
+    }
+
+    fn restore(&mut self, state: &[u8], _version: u8) {
+        let mut bytes = self.bytes.borrow_mut();
+        let len = bytes.len().min(state.len());
+        bytes[..len].copy_from_slice(&state[..len]);
+    }
+}
+
+///
+/// a device attached to another cpu as seen from this one: accesses and the
+/// interrupt output go through, while time, events and the snapshot state
+/// stay with the cpu that owns it
+///
+pub struct Remote<T: Device> {
+    device: Rc<RefCell<T>>,
+    on_bus: bool,
+}
+
+impl<T: Device> Remote<T> {
+    pub fn new(device: Rc<RefCell<T>>) -> Self {
+        Self {
+            device,
+            on_bus: true,
+        }
+    }
+
+    ///
+    /// only the interrupt output, wired to a line of this cpu without any
+    /// addresses on its bus
+    ///
+    pub fn pins(device: Rc<RefCell<T>>) -> Self {
+        Self {
+            device,
+            on_bus: false,
+        }
+    }
+}
+
+impl<T: Device> Device for Remote<T> {
+    fn size(&self) -> u16 {
+        if self.on_bus {
+            self.device.borrow().size()
+        } else {
+            0
+        }
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        self.device.borrow_mut().read(offset)
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        self.device.borrow_mut().write(offset, val)
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        self.device.borrow().peek(offset)
+    }
+
+    fn irq(&self) -> bool {
+        self.device.borrow().irq()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{device::Via, mos6502::Mos6502};
+
+    #[test]
+    fn test_clones_share_the_bytes() {
+        let mut ram = SharedRam::new(0x800);
+        let mut other = ram.clone();
+        ram.write(0x10, 0x42);
+        assert_eq!(0x42, other.read(0x10));
+        let state = other.state();
+        other.write(0x10, 0x00);
+        ram.restore(&state, ram.state_version());
+        assert_eq!(0x42, other.read(0x10));
+    }
+
+    #[test]
+    fn test_remote_leaves_time_to_the_owner() {
+        let (mut owner, via) = Mos6502::nops_with(0x6000, Via::default());
+        let mut other = Mos6502::nops();
+        other.attach(0x6000, Box::new(Remote::new(via.clone())));
+        // timer 1 started from the remote side, T1C-L and T1C-H
+        other.poke(0x6004, 0x10);
+        other.poke(0x6005, 0x00);
+        other.run_until(8);
+        assert_eq!(0x10, other.peek(0x6004));
+        owner.run_until(8);
+        assert_eq!(0x08, other.peek(0x6004));
+        assert_eq!(0, Remote::pins(via).size());
+    }
+}
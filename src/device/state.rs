@@ -0,0 +1,93 @@
+///
+/// byte strings devices keep their snapshot state in. Values are stored in
+/// the order they are written, numbers little endian.
+///
+#[derive(Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, val: u8) -> &mut Self {
+        self.bytes.push(val);
+        self
+    }
+
+    pub fn u16(&mut self, val: u16) -> &mut Self {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, val: u32) -> &mut Self {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, val: u64) -> &mut Self {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+        self
+    }
+
+    pub fn bool(&mut self, val: bool) -> &mut Self {
+        self.u8(val as u8)
+    }
+
+    pub fn bytes(&mut self, val: &[u8]) -> &mut Self {
+        self.bytes.extend_from_slice(val);
+        self
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+///
+/// reads values back in the order they were written. A state cut short
+/// reads as zeros rather than failing, restoring is best effort.
+///
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        self.array::<1>()[0]
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.array())
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.array())
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.array())
+    }
+
+    pub fn bool(&mut self) -> bool {
+        self.u8() != 0
+    }
+
+    ///
+    /// fills `dest` with the next bytes
+    ///
+    pub fn bytes(&mut self, dest: &mut [u8]) {
+        let len = dest.len().min(self.bytes.len());
+        dest[..len].copy_from_slice(&self.bytes[..len]);
+        dest[len..].fill(0);
+        self.bytes = &self.bytes[len..];
+    }
+
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        let mut array = [0; N];
+        self.bytes(&mut array);
+        array
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    io::{self, Read, Write},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use console::{Key, Term};
+
+const CR: u8 = 0x0d;
+const BS: u8 = 0x08;
+const TAB: u8 = 0x09;
+const ESC: u8 = 0x1b;
+
+///
+/// host side of the character devices. Keys are collected by a background
+/// thread so the emulated program can poll for them without blocking, the
+/// terminal stays in raw mode while the thread waits for a key.
+///
+pub struct Terminal {
+    keys: Receiver<u8>,
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            let term = Term::stdout();
+            let interactive = term.is_term();
+            loop {
+                let key = if interactive {
+                    match term.read_key() {
+                        Ok(key) => key_to_byte(key),
+                        Err(_) => return,
+                    }
+                } else {
+                    match read_piped_byte() {
+                        Some(byte) => Some(byte),
+                        None => return,
+                    }
+                };
+                if let Some(byte) = key {
+                    if sender.send(byte).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Self { keys }
+    }
+
+    ///
+    /// next key typed on the host, if there is one waiting
+    ///
+    pub fn poll_key(&self) -> Option<u8> {
+        self.keys.try_recv().ok()
+    }
+
+    ///
+    /// wait for the next key, None once the host input is gone
+    ///
+    pub fn read_key(&self) -> Option<u8> {
+        self.keys.recv().ok()
+    }
+
+    pub fn put_char(&mut self, c: u8) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&[c]);
+        let _ = stdout.flush();
+    }
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn key_to_byte(key: Key) -> Option<u8> {
+    match key {
+        Key::Char(c) if c.is_ascii() => Some(c as u8),
+        Key::Enter => Some(CR),
+        Key::Backspace => Some(BS),
+        Key::Tab => Some(TAB),
+        Key::Escape => Some(ESC),
+        _ => None,
+    }
+}
+
+///
+/// stdin is not a terminal, pass the bytes through but end lines with CR
+/// like a terminal would. None once stdin is exhausted.
+///
+fn read_piped_byte() -> Option<u8> {
+    let mut byte = [0u8];
+    match io::stdin().read(&mut byte) {
+        Ok(1) if byte[0] == b'\n' => Some(CR),
+        Ok(1) => Some(byte[0]),
+        _ => None,
+    }
+}
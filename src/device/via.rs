@@ -0,0 +1,954 @@
+use super::{Device, StateReader, StateWriter};
+use crate::mos6502::scheduler::{EventId, Scheduler};
+
+///
+/// MOS 6522 / W65C22 versatile interface adapter. The counters are read off
+/// the cycle counter and whatever happens when they run out is a scheduled
+/// event, the chip stands still until it is attached to a cpu.
+///
+const ORB: u16 = 0x0;
+const ORA: u16 = 0x1;
+const DDRB: u16 = 0x2;
+const DDRA: u16 = 0x3;
+const T1C_L: u16 = 0x4;
+const T1C_H: u16 = 0x5;
+const T1L_L: u16 = 0x6;
+const T1L_H: u16 = 0x7;
+const T2C_L: u16 = 0x8;
+const T2C_H: u16 = 0x9;
+const SR: u16 = 0xa;
+const ACR: u16 = 0xb;
+const PCR: u16 = 0xc;
+const IFR: u16 = 0xd;
+const IER: u16 = 0xe;
+const ORA_NO_HANDSHAKE: u16 = 0xf;
+
+// IFR / IER layout: IRQ T1 T2 CB1 CB2 SR CA1 CA2
+const IRQ_CA2: u8 = 0b00000001;
+const IRQ_CA1: u8 = 0b00000010;
+const IRQ_SR: u8 = 0b00000100;
+const IRQ_CB2: u8 = 0b00001000;
+const IRQ_CB1: u8 = 0b00010000;
+const IRQ_T2: u8 = 0b00100000;
+const IRQ_T1: u8 = 0b01000000;
+const IRQ_ANY: u8 = 0b10000000;
+
+// ACR layout: T1 control (2) T2 control (1) SR control (3) PB latch PA latch
+const ACR_PA_LATCH: u8 = 0b00000001;
+const ACR_PB_LATCH: u8 = 0b00000010;
+const ACR_T2_PULSE_COUNT: u8 = 0b00100000;
+const ACR_T1_FREE_RUN: u8 = 0b01000000;
+const ACR_T1_PB7: u8 = 0b10000000;
+
+// shift register modes, ACR bits 4-2
+const SR_DISABLED: u8 = 0b000;
+const SR_IN_PHI2: u8 = 0b010;
+const SR_IN_CB1: u8 = 0b011;
+const SR_OUT_FREE_RUN: u8 = 0b100;
+const SR_OUT_PHI2: u8 = 0b110;
+const SR_OUT_CB1: u8 = 0b111;
+const SR_OUT: u8 = 0b100;
+
+// CA2 / CB2 control modes, three bits of the PCR each
+const C2_INDEPENDENT: u8 = 0b001;
+const C2_POSITIVE_EDGE: u8 = 0b010;
+const C2_OUTPUT: u8 = 0b100;
+const C2_HANDSHAKE: u8 = 0b100;
+const C2_PULSE: u8 = 0b101;
+const C2_LOW: u8 = 0b110;
+const C2_HIGH: u8 = 0b111;
+
+const PB6: u8 = 0b01000000;
+const PB7: u8 = 0b10000000;
+
+// scheduled events
+const T1_UNDERFLOW: u32 = 0;
+const T2_UNDERFLOW: u32 = 1;
+const SHIFT: u32 = 2; // the next shift clock when clocked internally
+const PULSE_END: u32 = 3; // CA2 / CB2 pulses last one cycle
+
+const NO_DEADLINE: u64 = u64::MAX;
+
+pub struct Via {
+    ora: u8,
+    orb: u8,
+    ddra: u8,
+    ddrb: u8,
+    port_a_in: u8, // levels driven on the port pins from outside
+    port_b_in: u8,
+    ira_latch: u8,
+    irb_latch: u8,
+    t1_counter: u16, // as loaded, it counts down from there
+    t1_loaded: u64,  // cycle the counter was loaded at
+    t1_latch: u16,
+    t1_armed: bool,
+    t1_pb7: bool,
+    t2_counter: u16,
+    t2_loaded: u64, // pulse counting leaves the counter as it is
+    t2_latch_lsb: u8,
+    t2_armed: bool,
+    sr: u8,
+    sr_bits_left: u8,
+    shift_at: u64, // cycle of the next shift clock when clocked internally
+    acr: u8,
+    pcr: u8,
+    ifr: u8,
+    ier: u8,
+    ca1: bool,
+    ca2: bool,
+    cb1: bool,
+    cb2: bool,
+    ca2_pulse: bool,
+    cb2_pulse: bool,
+    scheduler: Option<Scheduler>,
+    t1_event: Option<EventId>,
+    t2_event: Option<EventId>,
+    shift_event: Option<EventId>,
+    pulse_event: Option<EventId>,
+}
+
+impl Default for Via {
+    fn default() -> Self {
+        Self {
+            ora: 0,
+            orb: 0,
+            ddra: 0,
+            ddrb: 0,
+            port_a_in: 0xff,
+            port_b_in: 0xff,
+            ira_latch: 0,
+            irb_latch: 0,
+            t1_counter: 0xffff,
+            t1_loaded: 0,
+            t1_latch: 0xffff,
+            t1_armed: false,
+            t1_pb7: true,
+            t2_counter: 0xffff,
+            t2_loaded: 0,
+            t2_latch_lsb: 0xff,
+            t2_armed: false,
+            sr: 0,
+            sr_bits_left: 0,
+            shift_at: 0,
+            acr: 0,
+            pcr: 0,
+            ifr: 0,
+            ier: 0,
+            ca1: true,
+            ca2: true,
+            cb1: true,
+            cb2: true,
+            ca2_pulse: false,
+            cb2_pulse: false,
+            scheduler: None,
+            t1_event: None,
+            t2_event: None,
+            shift_event: None,
+            pulse_event: None,
+        }
+    }
+}
+
+impl Via {
+    ///
+    /// levels on the port A pins, outputs come from ORA and inputs from outside
+    ///
+    pub fn port_a(&self) -> u8 {
+        self.ora & self.ddra | self.port_a_in & !self.ddra
+    }
+
+    pub fn port_b(&self) -> u8 {
+        let pins = self.orb & self.ddrb | self.port_b_in & !self.ddrb;
+        if self.acr & ACR_T1_PB7 != 0 {
+            pins & !PB7 | if self.t1_pb7 { PB7 } else { 0 }
+        } else {
+            pins
+        }
+    }
+
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.port_a_in = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        let falling_pb6 = self.port_b_in & PB6 != 0 && pins & PB6 == 0;
+        self.port_b_in = pins;
+        if falling_pb6 && self.acr & ACR_T2_PULSE_COUNT != 0 {
+            self.count_t2();
+        }
+    }
+
+    pub fn ca2(&self) -> bool {
+        self.ca2
+    }
+
+    pub fn cb2(&self) -> bool {
+        self.cb2
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        let active = is_active_edge(self.ca1, level, self.pcr & 0b1 != 0);
+        self.ca1 = level;
+        if !active {
+            return;
+        }
+        self.set_flag(IRQ_CA1);
+        if self.acr & ACR_PA_LATCH != 0 {
+            self.ira_latch = self.port_a();
+        }
+        if self.ca2_control() == C2_HANDSHAKE {
+            self.ca2 = true;
+        }
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        let control = self.ca2_control();
+        if control & C2_OUTPUT != 0 {
+            return;
+        }
+        if is_active_edge(self.ca2, level, control & C2_POSITIVE_EDGE != 0) {
+            self.set_flag(IRQ_CA2);
+        }
+        self.ca2 = level;
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        let rising = !self.cb1 && level;
+        let active = is_active_edge(self.cb1, level, (self.pcr >> 4) & 0b1 != 0);
+        self.cb1 = level;
+        if active {
+            self.set_flag(IRQ_CB1);
+            if self.acr & ACR_PB_LATCH != 0 {
+                self.irb_latch = self.port_b();
+            }
+            if self.cb2_control() == C2_HANDSHAKE {
+                self.cb2 = true;
+            }
+        }
+        // with an external shift clock the data moves on CB1 rising edges
+        let mode = self.sr_mode();
+        if rising && (mode == SR_IN_CB1 || mode == SR_OUT_CB1) && self.sr_bits_left > 0 {
+            self.shift();
+        }
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        let control = self.cb2_control();
+        if control & C2_OUTPUT != 0 {
+            return;
+        }
+        if is_active_edge(self.cb2, level, control & C2_POSITIVE_EDGE != 0) {
+            self.set_flag(IRQ_CB2);
+        }
+        self.cb2 = level;
+    }
+
+    fn ca2_control(&self) -> u8 {
+        (self.pcr >> 1) & 0b111
+    }
+
+    fn cb2_control(&self) -> u8 {
+        (self.pcr >> 5) & 0b111
+    }
+
+    fn sr_mode(&self) -> u8 {
+        (self.acr >> 2) & 0b111
+    }
+
+    fn set_flag(&mut self, flag: u8) {
+        self.ifr |= flag;
+    }
+
+    fn clear_flag(&mut self, flag: u8) {
+        self.ifr &= !flag;
+    }
+
+    ///
+    /// touching ORA clears the CA flags and drives the CA2 handshake
+    ///
+    fn port_a_handshake(&mut self) {
+        let control = self.ca2_control();
+        self.clear_flag(IRQ_CA1);
+        if control & C2_OUTPUT != 0 || control & C2_INDEPENDENT == 0 {
+            self.clear_flag(IRQ_CA2);
+        }
+        match control {
+            C2_HANDSHAKE => self.ca2 = false,
+            C2_PULSE => {
+                self.ca2 = false;
+                self.ca2_pulse = true;
+                self.schedule_pulse_end();
+            }
+            _ => {}
+        }
+    }
+
+    ///
+    /// touching ORB clears the CB flags, only writes drive the CB2 handshake
+    ///
+    fn port_b_handshake(&mut self, is_write: bool) {
+        let control = self.cb2_control();
+        self.clear_flag(IRQ_CB1);
+        if control & C2_OUTPUT != 0 || control & C2_INDEPENDENT == 0 {
+            self.clear_flag(IRQ_CB2);
+        }
+        match control {
+            C2_HANDSHAKE if is_write => self.cb2 = false,
+            C2_PULSE if is_write => {
+                self.cb2 = false;
+                self.cb2_pulse = true;
+                self.schedule_pulse_end();
+            }
+            _ => {}
+        }
+    }
+
+    fn update_manual_outputs(&mut self) {
+        match self.ca2_control() {
+            C2_LOW => self.ca2 = false,
+            C2_HIGH => self.ca2 = true,
+            _ => {}
+        }
+        match self.cb2_control() {
+            C2_LOW => self.cb2 = false,
+            C2_HIGH => self.cb2 = true,
+            _ => {}
+        }
+    }
+
+    fn read_ira(&self) -> u8 {
+        if self.acr & ACR_PA_LATCH != 0 {
+            self.ira_latch
+        } else {
+            self.port_a()
+        }
+    }
+
+    fn read_irb(&self) -> u8 {
+        // output bits always read back from ORB, whatever loads the pins
+        let pins = if self.acr & ACR_PB_LATCH != 0 {
+            self.irb_latch
+        } else {
+            self.port_b()
+        };
+        self.orb & self.ddrb | pins & !self.ddrb
+    }
+
+    fn now(&self) -> u64 {
+        self.scheduler.as_ref().map_or(0, Scheduler::now)
+    }
+
+    ///
+    /// replace a pending event of the chip, `None` leaves none behind
+    ///
+    fn reschedule(&self, pending: Option<EventId>, at: Option<u64>, event: u32) -> Option<EventId> {
+        let scheduler = self.scheduler.as_ref()?;
+        if let Some(pending) = pending {
+            scheduler.cancel(pending);
+        }
+        at.map(|at| scheduler.schedule(at, event))
+    }
+
+    fn deadline(&self, event: Option<EventId>) -> u64 {
+        match (self.scheduler.as_ref(), event) {
+            (Some(scheduler), Some(id)) => scheduler.deadline(id).unwrap_or(NO_DEADLINE),
+            _ => NO_DEADLINE,
+        }
+    }
+
+    fn schedule_pulse_end(&mut self) {
+        if self.pulse_event.is_none() {
+            let at = self.now() + 1;
+            self.pulse_event = self.reschedule(None, Some(at), PULSE_END);
+        }
+    }
+
+    fn end_pulses(&mut self) {
+        self.pulse_event = None;
+        if self.ca2_pulse {
+            self.ca2 = true;
+            self.ca2_pulse = false;
+        }
+        if self.cb2_pulse {
+            self.cb2 = true;
+            self.cb2_pulse = false;
+        }
+    }
+
+    fn start_shift(&mut self) {
+        self.clear_flag(IRQ_SR);
+        if self.sr_mode() != SR_DISABLED {
+            self.sr_bits_left = 8;
+            self.shift_at = self.now() + self.shift_period();
+            self.schedule_shift();
+        }
+    }
+
+    fn shift_period(&self) -> u64 {
+        match self.sr_mode() {
+            SR_IN_PHI2 | SR_OUT_PHI2 => 2,
+            _ => self.t2_latch_lsb as u64 + 2,
+        }
+    }
+
+    fn is_clocked_internally(&self) -> bool {
+        !matches!(self.sr_mode(), SR_DISABLED | SR_IN_CB1 | SR_OUT_CB1)
+    }
+
+    fn schedule_shift(&mut self) {
+        let running = self.is_clocked_internally() && self.sr_bits_left > 0;
+        let at = running.then_some(self.shift_at);
+        self.shift_event = self.reschedule(self.shift_event, at, SHIFT);
+    }
+
+    fn shift(&mut self) {
+        let mode = self.sr_mode();
+        if mode & SR_OUT != 0 {
+            // shifting out recirculates bit 7 into bit 0
+            let bit = self.sr >> 7;
+            self.sr = self.sr << 1 | bit;
+            self.cb2 = bit == 1;
+        } else {
+            self.sr = self.sr << 1 | self.cb2 as u8;
+        }
+        self.sr_bits_left -= 1;
+        if self.sr_bits_left == 0 {
+            if mode == SR_OUT_FREE_RUN {
+                self.sr_bits_left = 8;
+            } else {
+                self.set_flag(IRQ_SR);
+            }
+        }
+    }
+
+    fn shift_clock(&mut self) {
+        self.shift_event = None;
+        if !self.is_clocked_internally() || self.sr_bits_left == 0 {
+            return;
+        }
+        self.shift();
+        // from the deadline, a late dispatch does not drift
+        self.shift_at += self.shift_period();
+        self.schedule_shift();
+    }
+
+    ///
+    /// timer 1 at `now`, it reads all ones in the cycle between running out
+    /// and reloading from the latch
+    ///
+    fn t1_value(&self, now: u64) -> u16 {
+        match now.checked_sub(self.t1_loaded) {
+            Some(elapsed) => self.t1_counter.wrapping_sub(elapsed as u16),
+            None => 0xffff,
+        }
+    }
+
+    fn load_t1(&mut self, counter: u16, at: u64) {
+        self.t1_counter = counter;
+        self.t1_loaded = at;
+        self.schedule_t1();
+    }
+
+    ///
+    /// only an armed or free running timer 1 does anything when it runs out
+    ///
+    fn schedule_t1(&mut self) {
+        let now = self.now();
+        if now > self.t1_loaded {
+            // counted from now, the next underflow is the one after the load
+            self.t1_counter = self.t1_value(now);
+            self.t1_loaded = now;
+        }
+        let wanted = self.t1_armed || self.acr & ACR_T1_FREE_RUN != 0;
+        let at = wanted.then_some(self.t1_underflow_at());
+        self.t1_event = self.reschedule(self.t1_event, at, T1_UNDERFLOW);
+    }
+
+    fn t1_underflow_at(&self) -> u64 {
+        self.t1_loaded + self.t1_counter as u64 + 1
+    }
+
+    fn t1_underflow(&mut self) {
+        self.t1_event = None;
+        let underflow = self.t1_underflow_at();
+        if self.acr & ACR_T1_FREE_RUN != 0 {
+            self.set_flag(IRQ_T1);
+            self.t1_pb7 = !self.t1_pb7;
+            // the cycle after running out the counter reloads
+            self.load_t1(self.t1_latch, underflow + 1);
+        } else {
+            if self.t1_armed {
+                self.set_flag(IRQ_T1);
+                self.t1_pb7 = true;
+                self.t1_armed = false;
+            }
+            self.load_t1(0xffff, underflow);
+        }
+    }
+
+    fn t2_value(&self, now: u64) -> u16 {
+        if self.acr & ACR_T2_PULSE_COUNT != 0 {
+            return self.t2_counter;
+        }
+        let elapsed = now.saturating_sub(self.t2_loaded);
+        self.t2_counter.wrapping_sub(elapsed as u16)
+    }
+
+    ///
+    /// only an armed timer 2 counting cycles does anything when it runs out
+    ///
+    fn schedule_t2(&mut self) {
+        let wanted = self.t2_armed && self.acr & ACR_T2_PULSE_COUNT == 0;
+        let underflow = self.t2_loaded + self.t2_counter as u64 + 1;
+        let at = wanted.then_some(underflow);
+        self.t2_event = self.reschedule(self.t2_event, at, T2_UNDERFLOW);
+    }
+
+    fn t2_underflow(&mut self) {
+        self.t2_event = None;
+        if self.t2_armed {
+            self.set_flag(IRQ_T2);
+            self.t2_armed = false;
+        }
+    }
+
+    ///
+    /// a PB6 pulse counted down, the timer runs out when it reaches zero
+    ///
+    fn count_t2(&mut self) {
+        self.t2_counter = self.t2_counter.wrapping_sub(1);
+        if self.t2_counter == 0 && self.t2_armed {
+            self.set_flag(IRQ_T2);
+            self.t2_armed = false;
+        }
+    }
+
+    fn write_acr(&mut self, val: u8) {
+        // the counters go on from where they stand under the old mode
+        let now = self.now();
+        self.t2_counter = self.t2_value(now);
+        self.t2_loaded = now;
+        let shift_mode = self.sr_mode();
+        self.acr = val;
+        if self.sr_mode() != shift_mode {
+            self.shift_at = now + self.shift_period();
+        }
+        self.schedule_t1();
+        self.schedule_t2();
+        self.schedule_shift();
+    }
+}
+
+fn is_active_edge(old: bool, new: bool, positive: bool) -> bool {
+    if positive {
+        !old && new
+    } else {
+        old && !new
+    }
+}
+
+impl Device for Via {
+    fn size(&self) -> u16 {
+        16
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0xf {
+            ORB => {
+                self.port_b_handshake(false);
+                self.read_irb()
+            }
+            ORA => {
+                self.port_a_handshake();
+                self.read_ira()
+            }
+            DDRB => self.ddrb,
+            DDRA => self.ddra,
+            T1C_L => {
+                self.clear_flag(IRQ_T1);
+                self.t1_value(self.now()) as u8
+            }
+            T1C_H => (self.t1_value(self.now()) >> 8) as u8,
+            T1L_L => self.t1_latch as u8,
+            T1L_H => (self.t1_latch >> 8) as u8,
+            T2C_L => {
+                self.clear_flag(IRQ_T2);
+                self.t2_value(self.now()) as u8
+            }
+            T2C_H => (self.t2_value(self.now()) >> 8) as u8,
+            SR => {
+                self.start_shift();
+                self.sr
+            }
+            ACR => self.acr,
+            PCR => self.pcr,
+            IFR => {
+                if self.irq() {
+                    self.ifr | IRQ_ANY
+                } else {
+                    self.ifr
+                }
+            }
+            IER => self.ier | IRQ_ANY,
+            ORA_NO_HANDSHAKE => self.read_ira(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        Some(match offset & 0xf {
+            ORB => self.read_irb(),
+            ORA | ORA_NO_HANDSHAKE => self.read_ira(),
+            T1C_L => self.t1_value(self.now()) as u8,
+            T2C_L => self.t2_value(self.now()) as u8,
+            SR => self.sr,
+            IFR if self.irq() => self.ifr | IRQ_ANY,
+            IFR => self.ifr,
+            _ => return None,
+        })
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        match offset & 0xf {
+            ORB => {
+                self.orb = val;
+                self.port_b_handshake(true);
+            }
+            ORA => {
+                self.ora = val;
+                self.port_a_handshake();
+            }
+            DDRB => self.ddrb = val,
+            DDRA => self.ddra = val,
+            T1C_L | T1L_L => self.t1_latch = self.t1_latch & 0xff00 | val as u16,
+            T1C_H => {
+                self.t1_latch = (val as u16) << 8 | self.t1_latch & 0x00ff;
+                self.t1_armed = true;
+                self.t1_pb7 = false;
+                self.clear_flag(IRQ_T1);
+                self.load_t1(self.t1_latch, self.now());
+            }
+            T1L_H => {
+                self.t1_latch = (val as u16) << 8 | self.t1_latch & 0x00ff;
+                self.clear_flag(IRQ_T1);
+            }
+            T2C_L => self.t2_latch_lsb = val,
+            T2C_H => {
+                self.t2_counter = (val as u16) << 8 | self.t2_latch_lsb as u16;
+                self.t2_loaded = self.now();
+                self.t2_armed = true;
+                self.clear_flag(IRQ_T2);
+                self.schedule_t2();
+            }
+            SR => {
+                self.sr = val;
+                self.start_shift();
+            }
+            ACR => self.write_acr(val),
+            PCR => {
+                self.pcr = val;
+                self.update_manual_outputs();
+            }
+            IFR => self.ifr &= !(val & !IRQ_ANY),
+            IER => {
+                if val & IRQ_ANY != 0 {
+                    self.ier |= val & !IRQ_ANY;
+                } else {
+                    self.ier &= !val;
+                }
+            }
+            ORA_NO_HANDSHAKE => self.ora = val,
+            _ => unreachable!(),
+        }
+    }
+
+    fn connect(&mut self, scheduler: Scheduler) {
+        // until now the chip counted from cycle 0
+        let now = scheduler.now();
+        self.t1_loaded += now;
+        self.t2_loaded += now;
+        self.shift_at += now;
+        self.scheduler = Some(scheduler);
+        self.schedule_t1();
+        self.schedule_t2();
+        self.schedule_shift();
+    }
+
+    fn event(&mut self, event: u32) {
+        match event {
+            T1_UNDERFLOW => self.t1_underflow(),
+            T2_UNDERFLOW => self.t2_underflow(),
+            SHIFT => self.shift_clock(),
+            PULSE_END => self.end_pulses(),
+            _ => {}
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.ifr & self.ier & !IRQ_ANY != 0
+    }
+
+    fn state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        state
+            .u8(self.ora)
+            .u8(self.orb)
+            .u8(self.ddra)
+            .u8(self.ddrb)
+            .u8(self.port_a_in)
+            .u8(self.port_b_in)
+            .u8(self.ira_latch)
+            .u8(self.irb_latch)
+            .u16(self.t1_counter)
+            .u64(self.t1_loaded)
+            .u16(self.t1_latch)
+            .bool(self.t1_armed)
+            .bool(self.t1_pb7)
+            .u16(self.t2_counter)
+            .u64(self.t2_loaded)
+            .u8(self.t2_latch_lsb)
+            .bool(self.t2_armed)
+            .u8(self.sr)
+            .u8(self.sr_bits_left)
+            .u64(self.shift_at)
+            .u8(self.acr)
+            .u8(self.pcr)
+            .u8(self.ifr)
+            .u8(self.ier)
+            .bool(self.ca1)
+            .bool(self.ca2)
+            .bool(self.cb1)
+            .bool(self.cb2)
+            .bool(self.ca2_pulse)
+            .bool(self.cb2_pulse)
+            .u64(self.deadline(self.pulse_event));
+        state.into_bytes()
+    }
+
+    fn state_version(&self) -> u8 {
+        2 // the cycles the counters were loaded at rather than countdowns
+    }
+
+    fn restore(&mut self, state: &[u8], version: u8) {
+        let mut state = StateReader::new(state);
+        // version 1 counted down from the restored cycle counter
+        let now = self.now();
+        let countdowns = version == 1;
+        self.ora = state.u8();
+        self.orb = state.u8();
+        self.ddra = state.u8();
+        self.ddrb = state.u8();
+        self.port_a_in = state.u8();
+        self.port_b_in = state.u8();
+        self.ira_latch = state.u8();
+        self.irb_latch = state.u8();
+        self.t1_counter = state.u16();
+        self.t1_loaded = if countdowns { now } else { state.u64() };
+        self.t1_latch = state.u16();
+        self.t1_armed = state.bool();
+        if countdowns && state.bool() {
+            // ran out, the latch is loaded on the next cycle
+            self.t1_counter = self.t1_latch;
+            self.t1_loaded = now + 1;
+        }
+        self.t1_pb7 = state.bool();
+        self.t2_counter = state.u16();
+        self.t2_loaded = if countdowns { now } else { state.u64() };
+        self.t2_latch_lsb = state.u8();
+        self.t2_armed = state.bool();
+        self.sr = state.u8();
+        self.sr_bits_left = state.u8();
+        self.shift_at = match countdowns {
+            true => now + state.u16() as u64,
+            false => state.u64(),
+        };
+        self.acr = state.u8();
+        self.pcr = state.u8();
+        self.ifr = state.u8();
+        self.ier = state.u8();
+        self.ca1 = state.bool();
+        self.ca2 = state.bool();
+        self.cb1 = state.bool();
+        self.cb2 = state.bool();
+        self.ca2_pulse = state.bool();
+        self.cb2_pulse = state.bool();
+        let pulse_end = match countdowns {
+            // pulses ended on the next cycle
+            true if self.ca2_pulse || self.cb2_pulse => now + 1,
+            true => NO_DEADLINE,
+            false => state.u64(),
+        };
+        // whatever was pending is replaced by the restored deadlines
+        self.schedule_t1();
+        self.schedule_t2();
+        self.schedule_shift();
+        let at = (pulse_end != NO_DEADLINE).then_some(pulse_end);
+        self.pulse_event = self.reschedule(self.pulse_event, at, PULSE_END);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mos6502::Mos6502;
+
+    #[test]
+    fn test_timer1_one_shot_fires_once() {
+        let (mut cpu, via) = Mos6502::nops_with(0x6000, Via::default());
+        via.borrow_mut().write(IER, IRQ_ANY | IRQ_T1);
+        via.borrow_mut().write(T1C_L, 10);
+        via.borrow_mut().write(T1C_H, 0);
+        cpu.run_until(10);
+        assert!(!via.borrow().irq());
+        assert_eq!(0, via.borrow_mut().read(T1C_H));
+        cpu.run_until(12);
+        assert!(via.borrow().irq());
+        assert_eq!(IRQ_ANY | IRQ_T1, via.borrow_mut().read(IFR));
+        // past zero the counter goes on from all ones
+        assert_eq!(0xfffe, via.borrow().t1_value(12));
+
+        via.borrow_mut().read(T1C_L);
+        assert!(!via.borrow().irq());
+        assert_eq!(None, cpu.next_event());
+        cpu.run_until(0x20000);
+        assert!(!via.borrow().irq());
+    }
+
+    #[test]
+    fn test_timer1_free_run_toggles_pb7() {
+        let (mut cpu, via) = Mos6502::nops_with(0x6000, Via::default());
+        via.borrow_mut().write(ACR, ACR_T1_FREE_RUN | ACR_T1_PB7);
+        via.borrow_mut().write(T1C_L, 4);
+        via.borrow_mut().write(T1C_H, 0);
+        assert_eq!(0, via.borrow().port_b() & PB7);
+        // free running timers reload from the latch, one period is N + 2
+        // cycles: it runs out at 5, 11, 17 and 23, seen after the NOP
+        let mut toggles = Vec::new();
+        let mut pb7 = 0;
+        while cpu.cycles() < 24 {
+            cpu.step();
+            if via.borrow().port_b() & PB7 != pb7 {
+                pb7 ^= PB7;
+                toggles.push(cpu.cycles());
+            }
+        }
+        assert_eq!(vec![6, 12, 18, 24], toggles);
+    }
+
+    #[test]
+    fn test_timer1_runs_out_on_its_deadlines() {
+        let (mut cpu, via) = Mos6502::nops_with(0x6000, Via::default());
+        via.borrow_mut().write(ACR, ACR_T1_FREE_RUN);
+        via.borrow_mut().write(T1C_L, 5);
+        via.borrow_mut().write(T1C_H, 0);
+        assert_eq!(Some(6), cpu.next_event());
+        cpu.run_until(6);
+        assert_eq!(Some(13), cpu.next_event());
+        // the new latch is loaded with the next reload, counted from 13
+        // rather than from the end of the NOP that saw it
+        via.borrow_mut().write(T1L_L, 1);
+        cpu.run_until(14);
+        assert_eq!(Some(16), cpu.next_event());
+    }
+
+    #[test]
+    fn test_timer2_counts_pb6_pulses() {
+        let (mut cpu, via) = Mos6502::nops_with(0x6000, Via::default());
+        via.borrow_mut().write(ACR, ACR_T2_PULSE_COUNT);
+        via.borrow_mut().write(IER, IRQ_ANY | IRQ_T2);
+        via.borrow_mut().write(T2C_L, 3);
+        via.borrow_mut().write(T2C_H, 0);
+        cpu.run_until(100);
+        assert_eq!(3, via.borrow_mut().read(T2C_L));
+        let mut via = via.borrow_mut();
+        for _ in 0..2 {
+            via.set_port_b(!PB6);
+            via.set_port_b(0xff);
+        }
+        assert!(!via.irq());
+        via.set_port_b(!PB6);
+        assert!(via.irq());
+    }
+
+    #[test]
+    fn test_ier_masks_ifr() {
+        let (mut cpu, via) = Mos6502::nops_with(0x6000, Via::default());
+        via.borrow_mut().write(T2C_L, 1);
+        via.borrow_mut().write(T2C_H, 0);
+        cpu.run_until(2);
+        let mut via = via.borrow_mut();
+        assert_eq!(IRQ_T2, via.read(IFR));
+        assert!(!via.irq());
+        via.write(IER, IRQ_ANY | IRQ_T2);
+        assert_eq!(IRQ_ANY | IRQ_T2, via.read(IER));
+        assert!(via.irq());
+        via.write(IER, IRQ_T2);
+        assert!(!via.irq());
+    }
+
+    #[test]
+    fn test_ports_mix_outputs_and_inputs() {
+        let mut via = Via::default();
+        via.write(DDRA, 0x0f);
+        via.write(ORA, 0xa5);
+        via.set_port_a(0x30);
+        assert_eq!(0x35, via.read(ORA));
+        assert_eq!(0x35, via.port_a());
+    }
+
+    #[test]
+    fn test_ca1_edge_and_ca2_handshake() {
+        let mut via = Via::default();
+        // CA1 positive edge, CA2 handshake output
+        via.write(PCR, 0b00001001);
+        via.write(ORA, 0x42);
+        assert!(!via.ca2());
+        via.set_ca1(false);
+        via.set_ca1(true);
+        assert!(via.ca2());
+        assert_eq!(IRQ_CA1, via.read(IFR) & IRQ_CA1);
+        via.read(ORA);
+        assert_eq!(0, via.read(IFR) & IRQ_CA1);
+    }
+
+    #[test]
+    fn test_ca2_pulse_ends_a_cycle_later() {
+        let (mut cpu, via) = Mos6502::nops_with(0x6000, Via::default());
+        via.borrow_mut().write(PCR, C2_PULSE << 1);
+        via.borrow_mut().write(ORA, 0x42);
+        assert!(!via.borrow().ca2());
+        cpu.step();
+        assert!(via.borrow().ca2());
+    }
+
+    #[test]
+    fn test_shift_out_under_phi2() {
+        let (mut cpu, via) = Mos6502::nops_with(0x6000, Via::default());
+        via.borrow_mut().write(ACR, SR_OUT_PHI2 << 2);
+        via.borrow_mut().write(SR, 0b10000001);
+        cpu.run_until(2);
+        assert!(via.borrow().cb2());
+        cpu.run_until(14);
+        assert_eq!(0, via.borrow_mut().read(IFR) & IRQ_SR);
+        cpu.run_until(16);
+        assert_eq!(IRQ_SR, via.borrow_mut().read(IFR) & IRQ_SR);
+        assert_eq!(0b10000001, via.borrow().sr);
+        assert_eq!(None, cpu.next_event());
+    }
+
+    #[test]
+    fn test_pending_timers_survive_a_snapshot() {
+        let (mut cpu, via) = Mos6502::nops_with(0x6000, Via::default());
+        via.borrow_mut().write(IER, IRQ_ANY | IRQ_T1);
+        via.borrow_mut().write(T1C_L, 20);
+        via.borrow_mut().write(T1C_H, 0);
+        cpu.run_until(10);
+        let snapshot = cpu.snapshot();
+        cpu.run_until(30);
+        assert!(via.borrow().irq());
+        cpu.restore_snapshot(&snapshot).unwrap();
+        assert!(!via.borrow().irq());
+        assert_eq!(10, via.borrow_mut().read(T1C_L));
+        assert_eq!(Some(21), cpu.next_event());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod debugger;
+pub mod device;
+pub mod machine;
+pub mod mos6502;
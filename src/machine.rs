@@ -0,0 +1,8 @@
+///
+/// ready made computers, a profile builds a cpu with the memory layout and
+/// the devices of a real machine
+///
+pub mod apple1;
+pub mod description;
+pub mod kim1;
+pub mod system;
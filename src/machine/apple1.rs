@@ -0,0 +1,221 @@
+use std::io;
+
+use crate::{
+    device::{Device, Pia, Terminal},
+    mos6502::{scheduler::Scheduler, Mos6502, Region, RegionKind},
+};
+
+pub const PIA_ADDRESS: u16 = 0xd010;
+
+pub const WOZ_MONITOR_ADDRESS: u16 = 0xff00;
+
+pub const WOZ_MONITOR_SIZE: usize = 256;
+
+///
+/// the stock 4K at the bottom plus the 4K Integer BASIC gets loaded into
+///
+pub const RAM: [(u16, u16); 2] = [(0x0000, 0x0fff), (0xe000, 0xefff)];
+
+const KBDCR: u16 = 0x1;
+
+const KEY_AVAILABLE: u8 = 0b10000000;
+const DISPLAY_COLUMNS: u8 = 40;
+const CR: u8 = 0x0d;
+const RUBOUT: u8 = b'_'; // the Woz Monitor takes an underscore as backspace
+
+///
+/// the PIA with the keyboard on port A and the terminal section on port B
+///
+struct Apple1Io {
+    pia: Pia,
+    terminal: Terminal,
+    column: u8,
+    scheduler: Option<Scheduler>, // keys go through it to be recorded
+}
+
+impl Apple1Io {
+    ///
+    /// the keyboard only knows upper case, sets bit 7 on every key and
+    /// strobes CA1 when a key is pressed
+    ///
+    fn poll_keyboard(&mut self) {
+        if self.pia.read(KBDCR) & KEY_AVAILABLE != 0 {
+            return;
+        }
+        let terminal = &self.terminal;
+        let key = match self.scheduler.as_ref() {
+            Some(scheduler) => scheduler.input(|| terminal.poll_key()),
+            None => terminal.poll_key(),
+        };
+        let Some(key) = key else {
+            return;
+        };
+        let key = match key {
+            b'\n' => CR,
+            0x08 | 0x7f => RUBOUT,
+            _ => key.to_ascii_uppercase(),
+        };
+        self.pia.set_port_a(key | 0x80);
+        self.pia.set_ca1(true);
+        self.pia.set_ca1(false);
+    }
+
+    ///
+    /// the terminal section pulls CB2 low when a character is written to
+    /// port B, shows it and answers on CB1. It has no lower case and wraps
+    /// after 40 columns.
+    ///
+    fn update_display(&mut self) {
+        if self.pia.cb2() {
+            return;
+        }
+        match self.pia.port_b() & 0x7f {
+            CR => self.new_line(),
+            c @ 0x20..=0x7f => {
+                let c = if c >= 0x60 { c - 0x20 } else { c };
+                self.terminal.put_char(c);
+                self.column += 1;
+                if self.column == DISPLAY_COLUMNS {
+                    self.new_line();
+                }
+            }
+            _ => {}
+        }
+        self.pia.set_cb1(false);
+        self.pia.set_cb1(true);
+    }
+
+    fn new_line(&mut self) {
+        self.terminal.put_char(b'\r');
+        self.terminal.put_char(b'\n');
+        self.column = 0;
+    }
+}
+
+impl Device for Apple1Io {
+    fn size(&self) -> u16 {
+        4
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        self.pia.read(offset)
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        self.pia.peek(offset)
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        self.pia.write(offset, val);
+        self.update_display();
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.pia.tick(cycles);
+        self.poll_keyboard();
+    }
+
+    fn connect(&mut self, scheduler: Scheduler) {
+        self.scheduler = Some(scheduler);
+    }
+
+    // the PIA IRQ outputs are not connected on the Apple-1
+
+    fn state(&self) -> Vec<u8> {
+        let mut state = self.pia.state();
+        state.push(self.column);
+        state
+    }
+
+    fn state_version(&self) -> u8 {
+        self.pia.state_version()
+    }
+
+    fn restore(&mut self, state: &[u8], version: u8) {
+        if let Some((&column, pia)) = state.split_last() {
+            self.pia.restore(pia, version);
+            self.column = column;
+        }
+    }
+}
+
+///
+/// an Apple-1 running the user supplied Woz Monitor, reset and ready to run
+///
+pub fn build(woz_monitor: &[u8]) -> io::Result<Mos6502> {
+    if woz_monitor.len() != WOZ_MONITOR_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the Woz Monitor rom has to be 256 bytes",
+        ));
+    }
+    let mut cpu = Mos6502::default();
+    map_memory(&mut cpu);
+    cpu.load(WOZ_MONITOR_ADDRESS, woz_monitor);
+    cpu.attach(
+        PIA_ADDRESS,
+        Box::new(Apple1Io {
+            pia: Pia::default(),
+            terminal: Terminal::new(),
+            column: 0,
+            scheduler: None,
+        }),
+    );
+    cpu.reset();
+    Ok(cpu)
+}
+
+///
+/// the RAM, the PIA and the monitor rom, nothing else answers on the bus
+///
+fn map_memory(cpu: &mut Mos6502) {
+    cpu.map(Region::new(0x0000, 0xffff, RegionKind::Unmapped));
+    for (start, end) in RAM {
+        cpu.map(Region::new(start, end, RegionKind::Ram));
+    }
+    cpu.map(Region::new(PIA_ADDRESS, PIA_ADDRESS + 3, RegionKind::Io));
+    cpu.map(Region::new(WOZ_MONITOR_ADDRESS, 0xffff, RegionKind::Rom));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// stands in for the Woz Monitor: sets up the PIA the way it does, then
+    /// writes the RAM, unmapped space and its own rom before looping
+    ///
+    fn monitor() -> Vec<u8> {
+        let mut rom = vec![
+            0xd8, 0x58, 0xa0, 0x7f, 0x8c, 0x12, 0xd0, // cld, cli, sty DSP
+            0xa9, 0xa7, 0x8d, 0x11, 0xd0, 0x8d, 0x13, 0xd0, // lda #$a7, sta KBDCR, sta DSPCR
+            0x8d, 0x00, 0x03, 0x8d, 0x00, 0xe0, // sta $0300, sta $e000
+            0x8d, 0x00, 0x20, 0x8d, 0x00, 0xff, // sta $2000, sta $ff00
+            0x4c, 0x1b, 0xff, // jmp *
+        ];
+        rom.resize(WOZ_MONITOR_SIZE, 0);
+        rom[0xfc] = 0x00;
+        rom[0xfd] = 0xff;
+        rom
+    }
+
+    #[test]
+    fn test_boots_into_the_monitor() {
+        let mut cpu = build(&monitor()).unwrap();
+        assert_eq!(WOZ_MONITOR_ADDRESS, cpu.registers().pc);
+        for _ in 0..12 {
+            cpu.step();
+        }
+        assert_eq!(0xff1b, cpu.registers().pc);
+        assert_eq!(None, cpu.halt_reason());
+        assert_eq!(0xa7, cpu.inspect(0x0300));
+        assert_eq!(0xa7, cpu.inspect(0xe000));
+        assert_eq!(0x00, cpu.inspect(0x2000));
+        assert_eq!(0xd8, cpu.inspect(0xff00));
+    }
+
+    #[test]
+    fn test_rejects_a_rom_of_the_wrong_size() {
+        assert!(build(&[0xea; 255]).is_err());
+    }
+}
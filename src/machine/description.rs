@@ -0,0 +1,505 @@
+///
+/// boards described in a TOML file instead of Rust. A description lists the
+/// memory map and the devices on the bus:
+///
+/// ```toml
+/// cpu = "6502"
+/// clock_hz = 1_000_000
+/// realtime = true         # paced to clock_hz instead of running flat out
+/// strict = true           # halt on writes to rom and touching unmapped space
+/// cycle_accurate = true   # dummy accesses, devices ticked every bus cycle
+///
+/// [[ram]]
+/// start = 0x0000
+/// end = 0x7fff
+/// executable = false      # any region can refuse execution
+///
+/// [[unmapped]]
+/// start = 0xc000
+/// end = 0xdfff
+///
+/// [[rom]]
+/// start = 0xe000
+/// image = "monitor.bin"   # relative to the description file
+///
+/// [[mirror]]
+/// start = 0x8000
+/// end = 0xbfff
+/// target = 0x0000
+///
+/// [[device]]
+/// type = "acia"           # console, via, acia, pia, riot or timer
+/// address = 0xd000
+/// irq = "nmi"             # irq (the default), nmi, rdy, so, reset or none
+/// host = "tcp:6551"       # acia only: stdio (the default), pty or tcp:<port>
+///
+/// [[bank]]
+/// mapper = "uxrom"        # uxrom, cnrom or mmc1 program rom at $8000
+/// image = "game.prg"
+///
+/// [[bank]]
+/// mapper = "latch"        # banked ram, the bank is written to `register`
+/// start = 0x4000
+/// window_size = 0x4000
+/// size = 0x80000
+/// register = 0xdf00
+/// ```
+///
+/// A timer is a 6532 RIOT used for its interval timer, its ports and RAM
+/// come along in the window. Device windows are I/O regions. Space no region
+/// covers acts as RAM.
+///
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    rc::Rc,
+};
+
+use toml::{Table, Value};
+
+use crate::{
+    device::{
+        open_host, Acia, BankedMemory, CnRom, Console, Device, Latch, Mapper, MapperPort, Mmc1,
+        Pia, Riot, SerialHost, UxRom, Via,
+    },
+    mos6502::{InterruptLine, Mos6502, Region, RegionKind},
+};
+
+pub const DEFAULT_CLOCK_HZ: u32 = 1_000_000;
+
+const CARTRIDGE_ADDRESS: u16 = 0x8000;
+const CARTRIDGE_WINDOW: usize = 0x4000;
+
+const SUPPORTED_CPUS: [&str; 2] = ["6502", "nmos6502"];
+
+///
+/// read a description and build the machine it describes, reset and ready to run
+///
+pub fn load(path: &Path) -> io::Result<Mos6502> {
+    let text = fs::read_to_string(path)?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    build(&text, base)
+}
+
+///
+/// build a machine from the text of a description, image paths are relative
+/// to `base`
+///
+pub fn build(text: &str, base: &Path) -> io::Result<Mos6502> {
+    build_with_hosts(text, base, &mut open_host)
+}
+
+///
+/// build with the character devices talking to the hosts `open` makes out
+/// of their `host` key, consoles ask for `stdio`
+///
+pub fn build_with_hosts(
+    text: &str,
+    base: &Path,
+    open: &mut dyn FnMut(&str) -> io::Result<Box<dyn SerialHost>>,
+) -> io::Result<Mos6502> {
+    let description: Table = text
+        .parse()
+        .map_err(|err: toml::de::Error| invalid(err.message().to_string()))?;
+    check_keys(
+        &description,
+        "machine",
+        &[
+            "cpu",
+            "clock_hz",
+            "realtime",
+            "strict",
+            "cycle_accurate",
+            "ram",
+            "rom",
+            "unmapped",
+            "mirror",
+            "bank",
+            "device",
+        ],
+    )?;
+
+    let cpu_variant = optional_str(&description, "cpu")?.unwrap_or("6502");
+    if !SUPPORTED_CPUS.contains(&cpu_variant) {
+        return Err(invalid(format!("unsupported cpu {}", cpu_variant)));
+    }
+    let clock_hz = match description.get("clock_hz") {
+        None => DEFAULT_CLOCK_HZ,
+        Some(Value::Integer(hz)) if *hz > 0 && *hz <= u32::MAX as i64 => *hz as u32,
+        Some(_) => return Err(invalid("clock_hz has to be a positive number")),
+    };
+
+    let mut cpu = Mos6502::default();
+    match description.get("strict") {
+        None => {}
+        Some(Value::Boolean(strict)) => cpu.set_strict(*strict),
+        Some(_) => return Err(invalid("strict has to be true or false")),
+    }
+    match description.get("cycle_accurate") {
+        None => {}
+        Some(Value::Boolean(cycle_accurate)) => cpu.set_cycle_accurate(*cycle_accurate),
+        Some(_) => return Err(invalid("cycle_accurate has to be true or false")),
+    }
+    match description.get("realtime") {
+        None | Some(Value::Boolean(false)) => {}
+        Some(Value::Boolean(true)) => cpu.throttle(clock_hz),
+        Some(_) => return Err(invalid("realtime has to be true or false")),
+    }
+    for ram in tables(&description, "ram")? {
+        check_keys(ram, "ram", &["start", "end", "image", "executable"])?;
+        let start = address(ram, "start")?;
+        let end = address(ram, "end")?;
+        if let Some(image) = optional_str(ram, "image")? {
+            let image = fs::read(base.join(image))?;
+            cpu.load(start, &image[..image.len().min(size_of(start, end))]);
+        }
+        cpu.map(region(ram, start, end, RegionKind::Ram)?);
+    }
+    for unmapped in tables(&description, "unmapped")? {
+        check_keys(unmapped, "unmapped", &["start", "end"])?;
+        let start = address(unmapped, "start")?;
+        let end = address(unmapped, "end")?;
+        cpu.map(Region::new(start, end, RegionKind::Unmapped));
+    }
+    for rom in tables(&description, "rom")? {
+        check_keys(rom, "rom", &["start", "end", "image", "executable"])?;
+        let start = address(rom, "start")?;
+        let image = required_str(rom, "image")?;
+        let image = fs::read(base.join(image))?;
+        if image.is_empty() || start as usize + image.len() > 0x10000 {
+            return Err(invalid(format!("rom at {:04x} does not fit", start)));
+        }
+        let end = match rom.get("end") {
+            Some(_) => address(rom, "end")?,
+            None => start + (image.len() - 1) as u16,
+        };
+        cpu.load(start, &image[..image.len().min(size_of(start, end))]);
+        cpu.map(region(rom, start, end, RegionKind::Rom)?);
+    }
+    for mirror in tables(&description, "mirror")? {
+        check_keys(mirror, "mirror", &["start", "end", "target"])?;
+        cpu.mirror(
+            address(mirror, "start")?,
+            address(mirror, "end")?,
+            address(mirror, "target")?,
+        );
+    }
+    for bank in tables(&description, "bank")? {
+        attach_bank(&mut cpu, bank, base)?;
+    }
+    for device in tables(&description, "device")? {
+        attach_device(&mut cpu, device, clock_hz, open)?;
+    }
+    cpu.reset();
+    Ok(cpu)
+}
+
+fn attach_device(
+    cpu: &mut Mos6502,
+    device: &Table,
+    clock_hz: u32,
+    open: &mut dyn FnMut(&str) -> io::Result<Box<dyn SerialHost>>,
+) -> io::Result<()> {
+    let kind = required_str(device, "type")?;
+    let keys: &[&str] = match kind {
+        "acia" => &["type", "address", "irq", "host"],
+        _ => &["type", "address", "irq"],
+    };
+    check_keys(device, kind, keys)?;
+    let start = address(device, "address")?;
+    let line = match optional_str(device, "irq")?.unwrap_or("irq") {
+        "irq" => InterruptLine::Irq,
+        "nmi" => InterruptLine::Nmi,
+        "rdy" => InterruptLine::Ready,
+        "so" => InterruptLine::Overflow,
+        "reset" => InterruptLine::Reset,
+        "none" => InterruptLine::Disconnected,
+        other => return Err(invalid(format!("unknown interrupt line {}", other))),
+    };
+    let device: Box<dyn Device> = match kind {
+        "console" => Box::new(Console::with_host(open("stdio")?)),
+        "via" => Box::new(Via::default()),
+        "acia" => {
+            let host = open(optional_str(device, "host")?.unwrap_or("stdio"))?;
+            Box::new(Acia::new(host, clock_hz))
+        }
+        "pia" => Box::new(Pia::default()),
+        "riot" | "timer" => Box::new(Riot::default()),
+        other => return Err(invalid(format!("unknown device type {}", other))),
+    };
+    let end = start.saturating_add(device.size().saturating_sub(1));
+    cpu.map(Region::new(start, end, RegionKind::Io));
+    cpu.attach_wired(start, device, line);
+    Ok(())
+}
+
+///
+/// cartridge mappers bank their program rom through two 16K windows at
+/// $8000, the latch banks ram behind a register of its own
+///
+fn attach_bank(cpu: &mut Mos6502, bank: &Table, base: &Path) -> io::Result<()> {
+    let mapper = required_str(bank, "mapper")?;
+    if mapper == "latch" {
+        check_keys(
+            bank,
+            mapper,
+            &["mapper", "start", "window_size", "size", "register"],
+        )?;
+        let start = address(bank, "start")?;
+        let register = address(bank, "register")?;
+        let window_size = size(bank, "window_size")?;
+        if start as usize + window_size > 0x10000 {
+            return Err(invalid(format!(
+                "bank window at {:04x} does not fit",
+                start
+            )));
+        }
+        let memory = vec![0; size(bank, "size")?];
+        let latch: Rc<RefCell<dyn Mapper>> = Rc::new(RefCell::new(Latch::default()));
+        let ram = BankedMemory::ram(memory, window_size, 1, latch.clone())?;
+        let end = start.saturating_add(ram.size().saturating_sub(1));
+        cpu.map(Region::new(start, end, RegionKind::Ram));
+        cpu.attach(start, Box::new(ram));
+        cpu.map(Region::new(register, register, RegionKind::Io));
+        cpu.attach(register, Box::new(MapperPort::new(latch, 1)));
+        return Ok(());
+    }
+
+    check_keys(bank, mapper, &["mapper", "image"])?;
+    let image = fs::read(base.join(required_str(bank, "image")?))?;
+    if image.is_empty() || image.len() % CARTRIDGE_WINDOW != 0 {
+        return Err(invalid(format!(
+            "{} rom has to be made of 16K banks",
+            mapper
+        )));
+    }
+    let banks = image.len() / CARTRIDGE_WINDOW;
+    let mapper: Rc<RefCell<dyn Mapper>> = match mapper {
+        "uxrom" => Rc::new(RefCell::new(UxRom::new(banks))),
+        "cnrom" => Rc::new(RefCell::new(CnRom::default())),
+        "mmc1" => Rc::new(RefCell::new(Mmc1::new(banks))),
+        other => return Err(invalid(format!("unknown mapper {}", other))),
+    };
+    cpu.map(Region::new(CARTRIDGE_ADDRESS, 0xffff, RegionKind::Rom));
+    cpu.attach(
+        CARTRIDGE_ADDRESS,
+        Box::new(BankedMemory::rom(image, CARTRIDGE_WINDOW, 2, mapper)?),
+    );
+    Ok(())
+}
+
+fn size(table: &Table, key: &str) -> io::Result<usize> {
+    match table.get(key) {
+        Some(Value::Integer(size)) if *size > 0 && *size <= 0x100_0000 => Ok(*size as usize),
+        Some(_) => Err(invalid(format!("{} has to be a size up to 16M", key))),
+        None => Err(invalid(format!("missing {}", key))),
+    }
+}
+
+fn region(table: &Table, start: u16, end: u16, kind: RegionKind) -> io::Result<Region> {
+    let region = Region::new(start, end, kind);
+    match table.get("executable") {
+        None | Some(Value::Boolean(true)) => Ok(region),
+        Some(Value::Boolean(false)) => Ok(region.no_execute()),
+        Some(_) => Err(invalid("executable has to be true or false")),
+    }
+}
+
+fn size_of(start: u16, end: u16) -> usize {
+    end.saturating_sub(start) as usize + 1
+}
+
+fn tables<'a>(table: &'a Table, key: &str) -> io::Result<Vec<&'a Table>> {
+    match table.get(key) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(entries)) => entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .as_table()
+                    .ok_or_else(|| invalid(format!("every {} entry has to be a table", key)))
+            })
+            .collect(),
+        Some(_) => Err(invalid(format!("{} has to be written as [[{}]]", key, key))),
+    }
+}
+
+fn address(table: &Table, key: &str) -> io::Result<u16> {
+    match table.get(key) {
+        Some(Value::Integer(address)) if (0..=0xffff).contains(address) => Ok(*address as u16),
+        Some(_) => Err(invalid(format!(
+            "{} has to be an address from 0 to 0xffff",
+            key
+        ))),
+        None => Err(invalid(format!("missing {}", key))),
+    }
+}
+
+fn optional_str<'a>(table: &'a Table, key: &str) -> io::Result<Option<&'a str>> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(text)) => Ok(Some(text)),
+        Some(_) => Err(invalid(format!("{} has to be a string", key))),
+    }
+}
+
+fn required_str<'a>(table: &'a Table, key: &str) -> io::Result<&'a str> {
+    optional_str(table, key)?.ok_or_else(|| invalid(format!("missing {}", key)))
+}
+
+///
+/// a misspelled key would otherwise be silently ignored
+///
+fn check_keys(table: &Table, section: &str, known: &[&str]) -> io::Result<()> {
+    match table.keys().find(|key| !known.contains(&key.as_str())) {
+        Some(key) => Err(invalid(format!("unknown key {} in {}", key, section))),
+        None => Ok(()),
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::mos6502::{AccessViolation, HaltReason, ViolationKind};
+
+    #[test]
+    fn test_rom_is_loaded_and_write_protected() {
+        let dir = env::temp_dir().join(format!("martian6502-description-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut rom = vec![0xea; 0x100];
+        rom[0xfc] = 0x00; // reset vector
+        rom[0xfd] = 0xff;
+        fs::write(dir.join("rom.bin"), &rom).unwrap();
+
+        let mut cpu = build("[[rom]]\nstart = 0xff00\nimage = \"rom.bin\"\n", &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(0xff00, cpu.registers().pc);
+        cpu.poke(0xff10, 0x00);
+        assert_eq!(0xea, cpu.peek(0xff10));
+    }
+
+    #[test]
+    fn test_mirror_and_devices() {
+        let text = "
+            [[mirror]]
+            start = 0x0800
+            end = 0x0fff
+            target = 0x0000
+
+            [[device]]
+            type = \"via\"
+            address = 0x6000
+            irq = \"nmi\"
+        ";
+        let mut cpu = build(text, Path::new(".")).unwrap();
+        cpu.poke(0x0810, 0x42);
+        assert_eq!(0x42, cpu.peek(0x0010));
+        // VIA DDRB reads back through the bus
+        cpu.poke(0x6002, 0x5a);
+        assert_eq!(0x5a, cpu.peek(0x6002));
+    }
+
+    #[test]
+    fn test_timer_interrupts() {
+        let text = "[[device]]\ntype = \"timer\"\naddress = 0x7000\n";
+        let mut cpu = build(text, Path::new(".")).unwrap();
+        cpu.load(0x0200, &[0xea; 0x10]);
+        cpu.poke(0xfffe, 0x00); // IRQ vector
+        cpu.poke(0xffff, 0x03);
+        let mut registers = cpu.registers();
+        (registers.pc, registers.sp, registers.sr) = (0x0200, 0xff, 0);
+        cpu.set_registers(registers);
+        // 6532 interval timer: 4 cycles at ÷1, IRQ enabled
+        cpu.poke(0x709c, 4);
+        for _ in 0..5 {
+            cpu.step();
+        }
+        assert_eq!(0x0300, cpu.registers().pc);
+    }
+
+    #[test]
+    fn test_strict_mode_halts_on_unmapped_read() {
+        let dir = env::temp_dir().join(format!("martian6502-strict-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut rom = vec![0xea; 0x100];
+        rom[0x00..0x03].copy_from_slice(&[0xad, 0x00, 0xc0]); // lda $c000
+        rom[0xfc] = 0x00;
+        rom[0xfd] = 0xff;
+        fs::write(dir.join("rom.bin"), &rom).unwrap();
+        let text = "
+            strict = true
+
+            [[unmapped]]
+            start = 0xc000
+            end = 0xcfff
+
+            [[rom]]
+            start = 0xff00
+            image = \"rom.bin\"
+        ";
+        let mut cpu = build(text, &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        cpu.run();
+        let violation = AccessViolation {
+            kind: ViolationKind::UnmappedRead,
+            address: 0xc000,
+            pc: 0xff00,
+        };
+        assert_eq!(
+            Some(HaltReason::AccessViolation(violation)),
+            cpu.halt_reason()
+        );
+    }
+
+    #[test]
+    fn test_latch_banks_ram() {
+        let text = "
+            [[bank]]
+            mapper = \"latch\"
+            start = 0x4000
+            window_size = 0x4000
+            size = 0x10000
+            register = 0xdf00
+        ";
+        let mut cpu = build(text, Path::new(".")).unwrap();
+        cpu.poke(0xdf00, 3);
+        cpu.poke(0x4000, 0x33);
+        cpu.poke(0xdf00, 1);
+        assert_eq!(0x00, cpu.peek(0x4000));
+        cpu.poke(0xdf00, 3);
+        assert_eq!(0x33, cpu.peek(0x4000));
+    }
+
+    #[test]
+    fn test_misspelled_key_is_rejected() {
+        let text = "[[device]]\ntype = \"via\"\nadress = 0x6000\n";
+        let err = build(text, Path::new(".")).err().unwrap();
+        assert_eq!("unknown key adress in via", err.to_string());
+    }
+
+    #[test]
+    fn test_realtime_paces_to_the_clock() {
+        let cpu = build("clock_hz = 1_789_773\nrealtime = true\n", Path::new(".")).unwrap();
+        let throttle = cpu.throttled().unwrap();
+        assert_eq!(
+            (1_789_773, false),
+            (throttle.clock_hz(), throttle.is_turbo())
+        );
+        let default_clock = build("realtime = true\n", Path::new(".")).unwrap();
+        assert_eq!(
+            Some(DEFAULT_CLOCK_HZ),
+            default_clock
+                .throttled()
+                .map(|throttle| throttle.clock_hz())
+        );
+        assert!(build("", Path::new(".")).unwrap().throttled().is_none());
+    }
+}
@@ -0,0 +1,270 @@
+use std::{cell::RefCell, io, rc::Rc};
+
+use crate::{
+    device::{Device, Riot, Terminal},
+    mos6502::{scheduler::Scheduler, Mos6502, Region, RegionKind},
+};
+
+pub const RAM: (u16, u16) = (0x0000, 0x03ff);
+
+///
+/// I/O and RAM of both RIOTs share the page at $1700
+///
+pub const RIOT_PAGE: u16 = 0x1700;
+
+pub const ROM_003_ADDRESS: u16 = 0x1800; // audio tape
+pub const ROM_002_ADDRESS: u16 = 0x1c00; // monitor, keypad and TTY
+
+pub const ROM_SIZE: usize = 1024;
+
+// only 13 address lines are decoded, the vectors at the top are read from the 002 rom
+const ROM_002_MIRROR: u16 = 0xfc00;
+
+const DETCPS: u16 = 0x1c2a; // measures the TTY bit rate
+const START: u16 = 0x1c4f;
+const GETCH: u16 = 0x1e5a;
+const OUTCH: u16 = 0x1ea0;
+const CNTL30: u16 = 0x17f2;
+const CNTH30: u16 = 0x17f3;
+
+// PA0 jumpered to ground selects the TTY, PA7 is the idle serial input
+const TTY_MODE_PINS: u8 = 0b11111110;
+
+const RIOT_RAM: u16 = 0x80;
+const RIOT_IO_MASK: u16 = 0x1f;
+const RIOT_RAM_MASK: u16 = 0x3f;
+
+// events of the 003 are numbered from here, those of the 002 from 0
+const RIOT_003_EVENTS: u32 = 0x100;
+
+///
+/// both RIOTs decoded the way the KIM-1 decodes its 6530s: I/O of the 003
+/// at $1700, I/O of the 002 at $1740 and 64 bytes of RAM each from $1780
+///
+struct Kim1Io {
+    riot_002: Riot,
+    riot_003: Riot,
+}
+
+impl Kim1Io {
+    ///
+    /// whether the 002 answers at `offset` rather than the 003, and the
+    /// offset the RIOT sees
+    ///
+    fn decode(offset: u16) -> (bool, u16) {
+        match offset >> 6 {
+            0 => (false, RIOT_RAM | offset & RIOT_IO_MASK),
+            1 => (true, RIOT_RAM | offset & RIOT_IO_MASK),
+            2 => (false, offset & RIOT_RAM_MASK),
+            _ => (true, offset & RIOT_RAM_MASK),
+        }
+    }
+
+    fn riot(&self, offset: u16) -> (&Riot, u16) {
+        match Self::decode(offset) {
+            (true, offset) => (&self.riot_002, offset),
+            (false, offset) => (&self.riot_003, offset),
+        }
+    }
+
+    fn riot_mut(&mut self, offset: u16) -> (&mut Riot, u16) {
+        match Self::decode(offset) {
+            (true, offset) => (&mut self.riot_002, offset),
+            (false, offset) => (&mut self.riot_003, offset),
+        }
+    }
+}
+
+impl Device for Kim1Io {
+    fn size(&self) -> u16 {
+        0x100
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        let (riot, offset) = self.riot_mut(offset);
+        riot.read(offset)
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        let (riot, offset) = self.riot_mut(offset);
+        riot.write(offset, val)
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        let (riot, offset) = self.riot(offset);
+        riot.peek(offset)
+    }
+
+    fn connect(&mut self, scheduler: Scheduler) {
+        self.riot_002.connect(scheduler.clone());
+        self.riot_003.connect(scheduler.offset(RIOT_003_EVENTS));
+    }
+
+    fn event(&mut self, event: u32) {
+        match event.checked_sub(RIOT_003_EVENTS) {
+            Some(event) => self.riot_003.event(event),
+            None => self.riot_002.event(event),
+        }
+    }
+
+    // the IRQ outputs are left to the user's own jumpers
+
+    fn state(&self) -> Vec<u8> {
+        let mut state = self.riot_002.state();
+        state.extend(self.riot_003.state());
+        state
+    }
+
+    fn state_version(&self) -> u8 {
+        self.riot_002.state_version()
+    }
+
+    fn restore(&mut self, state: &[u8], version: u8) {
+        // both halves have the same length
+        let (riot_002, riot_003) = state.split_at(state.len() / 2);
+        self.riot_002.restore(riot_002, version);
+        self.riot_003.restore(riot_003, version);
+    }
+}
+
+///
+/// a KIM-1 running the user supplied 6530-002 and 6530-003 roms, reset and
+/// ready to run. The monitor's bit banged TTY routines are replaced by
+/// traps talking to the host terminal.
+///
+pub fn build(rom_002: &[u8], rom_003: &[u8]) -> io::Result<Mos6502> {
+    if rom_002.len() != ROM_SIZE || rom_003.len() != ROM_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the KIM-1 roms have to be 1024 bytes each",
+        ));
+    }
+    let mut cpu = Mos6502::default();
+    map_memory(&mut cpu);
+    cpu.load(ROM_003_ADDRESS, rom_003);
+    cpu.load(ROM_002_ADDRESS, rom_002);
+    cpu.load(ROM_002_MIRROR, rom_002);
+
+    let mut riot_002 = Riot::default();
+    riot_002.set_port_a(TTY_MODE_PINS);
+    cpu.attach(
+        RIOT_PAGE,
+        Box::new(Kim1Io {
+            riot_002,
+            riot_003: Riot::default(),
+        }),
+    );
+    attach_tty(&mut cpu);
+    cpu.reset();
+    Ok(cpu)
+}
+
+///
+/// the RAM, the RIOT page and both roms with the mirror of the 002 at the
+/// top, nothing else answers on the bus
+///
+fn map_memory(cpu: &mut Mos6502) {
+    cpu.map(Region::new(0x0000, 0xffff, RegionKind::Unmapped));
+    cpu.map(Region::new(RAM.0, RAM.1, RegionKind::Ram));
+    cpu.map(Region::new(RIOT_PAGE, RIOT_PAGE + 0xff, RegionKind::Io));
+    for start in [ROM_003_ADDRESS, ROM_002_ADDRESS, ROM_002_MIRROR] {
+        let end = start + ROM_SIZE as u16 - 1;
+        cpu.map(Region::new(start, end, RegionKind::Rom));
+    }
+}
+
+fn attach_tty(cpu: &mut Mos6502) {
+    let terminal = Rc::new(RefCell::new(Terminal::new()));
+
+    cpu.trap(
+        DETCPS,
+        Box::new(|cpu| {
+            // the delay loops only pace the bit banging the traps replace
+            cpu.poke(CNTL30, 0x01);
+            cpu.poke(CNTH30, 0x00);
+            let mut registers = cpu.registers();
+            registers.pc = START;
+            cpu.set_registers(registers);
+        }),
+    );
+
+    let input = terminal.clone();
+    cpu.trap(
+        GETCH,
+        Box::new(move |cpu| {
+            let Some(key) = cpu.host_input(GETCH, || input.borrow().read_key()) else {
+                cpu.stop();
+                return;
+            };
+            // the teletype is upper case only and echoes what is typed
+            let key = key.to_ascii_uppercase() & 0x7f;
+            input.borrow_mut().put_char(key);
+            let mut registers = cpu.registers();
+            registers.ac = key;
+            cpu.set_registers(registers);
+            cpu.return_from_subroutine();
+        }),
+    );
+
+    cpu.trap(
+        OUTCH,
+        Box::new(move |cpu| {
+            terminal.borrow_mut().put_char(cpu.registers().ac & 0x7f);
+            cpu.return_from_subroutine();
+        }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// stands in for the monitor: writes the RAM, the RIOT RAM, both roms
+    /// and unmapped space before looping
+    ///
+    fn rom_002() -> Vec<u8> {
+        let mut rom = vec![
+            0xa9, 0x5a, 0x8d, 0x00, 0x03, 0x8d, 0x80, 0x17, // lda #$5a, sta $0300, sta $1780
+            0x8d, 0x00, 0x1c, 0x8d, 0x00, 0x18, // sta $1c00, sta $1800
+            0x8d, 0x00, 0x20, // sta $2000
+            0x4c, 0x11, 0x1c, // jmp *
+        ];
+        rom.resize(ROM_SIZE, 0);
+        // the reset vector, read through the mirror
+        rom[0x3fc] = 0x00;
+        rom[0x3fd] = 0x1c;
+        rom
+    }
+
+    #[test]
+    fn test_boots_into_the_monitor() {
+        let mut cpu = build(&rom_002(), &[0xea; ROM_SIZE]).unwrap();
+        assert_eq!(ROM_002_ADDRESS, cpu.registers().pc);
+        for _ in 0..7 {
+            cpu.step();
+        }
+        assert_eq!(0x1c11, cpu.registers().pc);
+        assert_eq!(None, cpu.halt_reason());
+        assert_eq!(0x5a, cpu.inspect(0x0300));
+        assert_eq!(0x5a, cpu.inspect(0x1780));
+        assert_eq!(0xa9, cpu.inspect(ROM_002_ADDRESS));
+        assert_eq!(0xea, cpu.inspect(ROM_003_ADDRESS));
+        assert_eq!(0x00, cpu.inspect(0x2000));
+    }
+
+    #[test]
+    fn test_each_riot_keeps_its_own_timer() {
+        let mut cpu = build(&rom_002(), &[0xea; ROM_SIZE]).unwrap();
+        cpu.step();
+        let start = cpu.cycles();
+        // ÷1 counts, the 003 runs out after 4 cycles and the 002 after 40
+        cpu.poke(0x1714, 3);
+        cpu.poke(0x1754, 39);
+        cpu.run_until(start + 10);
+        assert_eq!(0x80, cpu.inspect(0x1705) & 0x80);
+        assert_eq!(0x00, cpu.inspect(0x1745) & 0x80);
+        cpu.run_until(start + 50);
+        assert_eq!(0x80, cpu.inspect(0x1745) & 0x80);
+    }
+}
@@ -0,0 +1,174 @@
+///
+/// boards with more than one 6502, like a computer and the cpu of its disk
+/// drive. Every core keeps its own bus and clock, they meet through a
+/// `SharedRam` or through devices one core owns and the others reach with a
+/// `Remote`. The core furthest behind in time always runs the next
+/// instruction, so the interleaving only depends on the cycle counts.
+///
+use std::cmp::Ordering;
+
+use crate::mos6502::Mos6502;
+
+pub struct Core {
+    pub name: String,
+    pub cpu: Mos6502,
+    clock_hz: u32,
+}
+
+impl Core {
+    pub fn clock_hz(&self) -> u32 {
+        self.clock_hz
+    }
+
+    ///
+    /// compare how far two cores got in time, cycles at their own clocks
+    ///
+    fn time_cmp(&self, other: &Core) -> Ordering {
+        let this = self.cpu.cycles() as u128 * other.clock_hz as u128;
+        let that = other.cpu.cycles() as u128 * self.clock_hz as u128;
+        this.cmp(&that)
+    }
+}
+
+#[derive(Default)]
+pub struct System {
+    cores: Vec<Core>,
+}
+
+impl System {
+    ///
+    /// add a core running at `clock_hz`, its index names it from now on
+    ///
+    pub fn add(&mut self, name: &str, cpu: Mos6502, clock_hz: u32) -> usize {
+        self.cores.push(Core {
+            name: name.to_string(),
+            cpu,
+            clock_hz: clock_hz.max(1),
+        });
+        self.cores.len() - 1
+    }
+
+    pub fn cores(&self) -> &[Core] {
+        &self.cores
+    }
+
+    pub fn cpu(&self, core: usize) -> &Mos6502 {
+        &self.cores[core].cpu
+    }
+
+    pub fn cpu_mut(&mut self, core: usize) -> &mut Mos6502 {
+        &mut self.cores[core].cpu
+    }
+
+    ///
+    /// run one instruction on the core furthest behind, the first one added
+    /// on a tie. Returns the core that ran.
+    ///
+    pub fn step(&mut self) -> Option<usize> {
+        let core = (0..self.cores.len()).min_by(|&a, &b| self.cores[a].time_cmp(&self.cores[b]))?;
+        self.cores[core].cpu.step();
+        Some(core)
+    }
+
+    ///
+    /// run until `core` reaches `cycles` of its own clock or any core halts
+    ///
+    pub fn run_until(&mut self, core: usize, cycles: u64) {
+        for other in self.cores.iter_mut() {
+            other.cpu.resume();
+        }
+        while self.cores[core].cpu.cycles() < cycles && self.halted().is_none() {
+            self.step();
+        }
+    }
+
+    ///
+    /// run until a core halts
+    ///
+    pub fn run(&mut self) {
+        self.run_until(0, u64::MAX);
+    }
+
+    ///
+    /// the first core that stopped
+    ///
+    pub fn halted(&self) -> Option<usize> {
+        self.cores
+            .iter()
+            .position(|core| core.cpu.halt_reason().is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        device::{Device, Remote, SharedRam, Via},
+        mos6502::{InterruptLine, Registers},
+    };
+
+    fn core(program: &[u8]) -> Mos6502 {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, program);
+        cpu.set_registers(Registers {
+            pc: 0x0200,
+            sp: 0xff,
+            sr: 0,
+            ..Registers::default()
+        });
+        cpu
+    }
+
+    #[test]
+    fn test_cores_interleave_by_their_clocks() {
+        let mut system = System::default();
+        system.add("fast", core(&[0xea; 0x100]), 2_000_000);
+        system.add("slow", core(&[0xea; 0x100]), 1_000_000);
+        let order: Vec<usize> = (0..6).filter_map(|_| system.step()).collect();
+        assert_eq!(vec![0, 1, 0, 0, 1, 0], order);
+        // the fast core is never more than an instruction ahead
+        system.run_until(1, 20);
+        assert_eq!((38, 20), (system.cpu(0).cycles(), system.cpu(1).cycles()));
+    }
+
+    #[test]
+    fn test_shared_ram_carries_a_mailbox() {
+        let mailbox = SharedRam::new(0x100);
+        // lda #$42, sta $00
+        let mut sender = core(&[0xa9, 0x42, 0x85, 0x00]);
+        sender.attach(0x0000, Box::new(mailbox.clone()));
+        // loop: lda $80, beq loop, sta $10
+        let mut receiver = core(&[0xa5, 0x80, 0xf0, 0xfc, 0x85, 0x10]);
+        receiver.attach(0x0080, Box::new(mailbox));
+        let mut system = System::default();
+        system.add("sender", sender, 1_000_000);
+        system.add("receiver", receiver, 1_000_000);
+        system.run_until(1, 40);
+        assert_eq!(0x42, system.cpu_mut(1).peek(0x0010));
+    }
+
+    #[test]
+    fn test_device_interrupts_the_other_core() {
+        // the drive's VIA raises IRQ on the main cpu, not on its own
+        let via = Rc::new(RefCell::new(Via::default()));
+        let mut drive = core(&[0xea; 0x100]);
+        drive.attach_wired(0x1800, Box::new(via.clone()), InterruptLine::Disconnected);
+        let mut main = core(&[0xea; 0x100]);
+        main.attach(0x0000, Box::new(Remote::pins(via.clone())));
+        main.load(0xfffe, &[0x00, 0x03]);
+        main.load(0x0300, &[0xea; 0x10]);
+        // timer 1 with its interrupt enabled: IER, T1C-L, T1C-H
+        via.borrow_mut().write(0xe, 0xc0);
+        via.borrow_mut().write(0x4, 0x08);
+        via.borrow_mut().write(0x5, 0x00);
+        let mut system = System::default();
+        system.add("main", main, 1_000_000);
+        system.add("drive", drive, 1_000_000);
+        system.run_until(0, 30);
+        assert_eq!(0x03, system.cpu(0).registers().pc >> 8);
+        assert_eq!(0x00, system.cpu_mut(0).peek(0x0000), "no bus window");
+        assert_eq!(0x02, system.cpu(1).registers().pc >> 8);
+    }
+}
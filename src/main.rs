@@ -1,8 +1,825 @@
-use mos6502::Mos6502;
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    net::{Ipv4Addr, TcpListener},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-mod mos6502;
+use martian6502::{
+    debugger::{dap, dbginfo::DebugInfo, gdb, script, vice, Debugger, Stop, WatchKind, Watchpoint},
+    device::{open_host, Acia, Console},
+    machine::{apple1, description, kim1},
+    mos6502::{
+        history::{DEFAULT_BUDGET, DEFAULT_SNAPSHOT_INTERVAL},
+        journal::{Recording, DEFAULT_CHECKPOINT_INTERVAL},
+        HaltReason, Mos6502, Region, RegionKind,
+    },
+};
+
+// sim65 reports its own failures with this exit code
+const SIM65_ERROR: i32 = 0x7f;
+
+// py65 puts its character output at the same address
+const DEFAULT_CONSOLE_ADDRESS: u16 = 0xf001;
+
+const DEFAULT_CLOCK_HZ: u32 = 1_000_000;
+
+// routines listed in the flat profile
+const PROFILE_LINES: usize = 20;
+
+// bus accesses the access log keeps, the most recent ones
+const ACCESS_LOG_ENTRIES: usize = 1_000_000;
+
+// how often a paced machine looks at the signals it was sent
+const SIGNAL_POLL_CYCLES: u64 = 100_000;
+
+// set from signal handlers, applied by the running machine
+static TURBO: AtomicBool = AtomicBool::new(false);
+static SPEED_REQUESTED: AtomicBool = AtomicBool::new(false);
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
     let mut cpu = Mos6502::default();
-    cpu.debug();
+    match args.first().map(String::as_str) {
+        Some("sim65") => process::exit(run_sim65(&mut cpu, &args[1..])),
+        Some("run") => process::exit(run_image(&mut cpu, &args[1..])),
+        Some("apple1") => process::exit(run_apple1(&args[1..])),
+        Some("kim1") => process::exit(run_kim1(&args[1..])),
+        Some("machine") => process::exit(run_machine(&args[1..])),
+        Some("debug") => process::exit(run_debugger(&args[1..])),
+        Some("gdb") => process::exit(run_gdb(&args[1..])),
+        Some("vice") => process::exit(run_vice(&args[1..])),
+        Some("dap") => process::exit(run_dap(&args[1..])),
+        Some("test") => process::exit(run_scripts(&args[1..])),
+        _ => cpu.debug(),
+    }
+}
+
+///
+/// run a raw image with devices mapped on the bus. Without any device option
+/// the console sits at py65's address.
+///
+fn run_image(cpu: &mut Mos6502, args: &[String]) -> i32 {
+    let usage = "usage: martian6502 run <image> <hex load address> \
+                 [--console <hex address>] [--acia <hex address>[,stdio|pty|tcp:<port>]]";
+    let (Some(image), Some(load_address)) = (args.first(), args.get(1).map(|a| parse_hex(a)))
+    else {
+        eprintln!("{}", usage);
+        return 1;
+    };
+    let Some(load_address) = load_address else {
+        eprintln!("{}", usage);
+        return 1;
+    };
+
+    let mut options = args[2..].iter();
+    let mut has_devices = false;
+    while let Some(option) = options.next() {
+        let attached = match (option.as_str(), options.next()) {
+            ("--console", Some(address)) => match parse_hex(address) {
+                Some(address) => {
+                    cpu.attach(address, Box::new(Console::new()));
+                    Ok(())
+                }
+                None => Err(usage.to_string()),
+            },
+            ("--acia", Some(spec)) => attach_acia(cpu, spec),
+            _ => Err(usage.to_string()),
+        };
+        if let Err(err) = attached {
+            eprintln!("{}", err);
+            return 1;
+        }
+        has_devices = true;
+    }
+    if !has_devices {
+        cpu.attach(DEFAULT_CONSOLE_ADDRESS, Box::new(Console::new()));
+    }
+
+    match fs::read(image) {
+        Ok(image) => cpu.load(load_address, &image),
+        Err(err) => {
+            eprintln!("cannot load {}: {}", image, err);
+            return 1;
+        }
+    }
+    cpu.run();
+    0
+}
+
+///
+/// boot an Apple-1 into the Woz Monitor, the rom is not shipped with us
+///
+fn run_apple1(args: &[String]) -> i32 {
+    let Some(rom) = args.first() else {
+        eprintln!("usage: martian6502 apple1 <woz monitor rom>");
+        return 1;
+    };
+    match fs::read(rom).and_then(|rom| apple1::build(&rom)) {
+        Ok(mut cpu) => {
+            cpu.run();
+            0
+        }
+        Err(err) => {
+            eprintln!("cannot load {}: {}", rom, err);
+            1
+        }
+    }
+}
+
+///
+/// boot a KIM-1 into its monitor, or straight into a program loaded on top
+///
+fn run_kim1(args: &[String]) -> i32 {
+    let usage =
+        "usage: martian6502 kim1 <6530-002 rom> <6530-003 rom> [<image> <hex load address>]";
+    let (Some(rom_002), Some(rom_003)) = (args.first(), args.get(1)) else {
+        eprintln!("{}", usage);
+        return 1;
+    };
+    let built = fs::read(rom_002).and_then(|rom_002| {
+        let rom_003 = fs::read(rom_003)?;
+        kim1::build(&rom_002, &rom_003)
+    });
+    let mut cpu = match built {
+        Ok(cpu) => cpu,
+        Err(err) => {
+            eprintln!("cannot load the KIM-1 roms: {}", err);
+            return 1;
+        }
+    };
+    if let Some(image) = args.get(2) {
+        let Some(load_address) = args.get(3).and_then(|a| parse_hex(a)) else {
+            eprintln!("{}", usage);
+            return 1;
+        };
+        match fs::read(image) {
+            Ok(image) if !image.is_empty() => {
+                // expansion RAM wherever the program goes past the stock 1K
+                let end = load_address.saturating_add(image.len() as u16 - 1);
+                cpu.map(Region::new(load_address, end, RegionKind::Ram));
+                cpu.load(load_address, &image)
+            }
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("cannot load {}: {}", image, err);
+                return 1;
+            }
+        }
+    }
+    cpu.run();
+    0
+}
+
+///
+/// build a board from its TOML description and run it from reset, or from a
+/// snapshot taken on the same board. A realtime board toggles turbo on
+/// SIGUSR1 and prints its speed on SIGUSR2.
+///
+fn run_machine(args: &[String]) -> i32 {
+    let usage = "usage: martian6502 machine <description.toml> \
+                 [--restore <snapshot>] [--save <snapshot> <cycles>] \
+                 [--profile <folded stacks> <cycles>] [--coverage <report prefix> <cycles>] \
+                 [--heatmap <report prefix> <cycles>] [--access-log <csv> <cycles>] \
+                 [--record <inputs>] [--replay <inputs>] [--dbgfile <ld65 debug info>]";
+    let Some(path) = args.first() else {
+        eprintln!("{}", usage);
+        return 1;
+    };
+    let mut cpu = match description::load(Path::new(path)) {
+        Ok(cpu) => cpu,
+        Err(err) => {
+            eprintln!("cannot build {}: {}", path, err);
+            return 1;
+        }
+    };
+
+    let mut save = None;
+    let mut profile = None;
+    let mut coverage = None;
+    let mut heatmap = None;
+    let mut access_log = None;
+    let mut record = None;
+    let mut replay = None;
+    let mut debug_info = None;
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        let after_cycles = match option.as_str() {
+            "--save" => Some(&mut save),
+            "--profile" => Some(&mut profile),
+            "--coverage" => Some(&mut coverage),
+            "--heatmap" => Some(&mut heatmap),
+            "--access-log" => Some(&mut access_log),
+            _ => None,
+        };
+        if let Some(after_cycles) = after_cycles {
+            let Some(target) = path_and_cycles(&mut options) else {
+                eprintln!("{}", usage);
+                return 1;
+            };
+            *after_cycles = Some(target);
+            continue;
+        }
+        match (option.as_str(), options.next()) {
+            ("--restore", Some(snapshot)) => {
+                let restored = fs::read(snapshot).and_then(|bytes| cpu.restore_snapshot(&bytes));
+                if let Err(err) = restored {
+                    eprintln!("cannot restore {}: {}", snapshot, err);
+                    return 1;
+                }
+            }
+            ("--record", Some(inputs)) => match fs::File::create(inputs) {
+                Ok(file) => record = Some(file),
+                Err(err) => {
+                    eprintln!("cannot create {}: {}", inputs, err);
+                    return 1;
+                }
+            },
+            ("--replay", Some(inputs)) => {
+                match fs::File::open(inputs)
+                    .and_then(|file| Recording::read(io::BufReader::new(file)))
+                {
+                    Ok(recording) => replay = Some(recording),
+                    Err(err) => {
+                        eprintln!("cannot read {}: {}", inputs, err);
+                        return 1;
+                    }
+                }
+            }
+            ("--dbgfile", Some(dbgfile)) => match DebugInfo::load(Path::new(dbgfile)) {
+                Ok(info) => debug_info = Some(info),
+                Err(err) => {
+                    eprintln!("cannot load {}: {}", dbgfile, err);
+                    return 1;
+                }
+            },
+            _ => {
+                eprintln!("{}", usage);
+                return 1;
+            }
+        }
+    }
+
+    if cpu.throttled().is_some() {
+        watch_throttle_signals(&mut cpu);
+    }
+    // the recorded run goes to its end, the machine is live again after it
+    if let Some(recording) = replay {
+        let (end, checkpoints) = (recording.end(), recording.checkpoints.len());
+        cpu.replay(recording);
+        cpu.run_until(end + 1);
+        cpu.finish_journal();
+        match cpu.halt_reason() {
+            Some(HaltReason::Diverged(cycle)) => {
+                eprintln!("replay diverged from the recording at cycle {}", cycle);
+                return 1;
+            }
+            None => eprintln!(
+                "replayed to cycle {}, {} checkpoints matched",
+                end, checkpoints
+            ),
+            Some(_) => {}
+        }
+    }
+    if let Some(file) = record {
+        cpu.record(DEFAULT_CHECKPOINT_INTERVAL, Some(Box::new(file)));
+    }
+    if let Some((snapshot, cycles)) = save {
+        cpu.run_until(cycles);
+        if let Err(err) = fs::write(snapshot, cpu.snapshot()) {
+            eprintln!("cannot save {}: {}", snapshot, err);
+            return 1;
+        }
+    }
+    let measured = [profile, coverage, heatmap, access_log]
+        .into_iter()
+        .flatten()
+        .map(|(_, cycles)| cycles)
+        .max();
+    if let Some(cycles) = measured {
+        if profile.is_some() {
+            cpu.profile();
+        }
+        if coverage.is_some() {
+            cpu.cover();
+        }
+        if heatmap.is_some() {
+            cpu.count_accesses();
+        }
+        if access_log.is_some() {
+            cpu.log_accesses(ACCESS_LOG_ENTRIES);
+        }
+        cpu.run_until(cpu.cycles() + cycles);
+        if let Some((folded, _)) = profile {
+            if let Err(err) = report_profile(&cpu, folded, debug_info.as_ref()) {
+                eprintln!("cannot write {}: {}", folded, err);
+                return 1;
+            }
+        }
+        if let Some((prefix, _)) = coverage {
+            if let Err(err) = report_coverage(&mut cpu, prefix, debug_info.as_ref()) {
+                eprintln!("cannot write the {} coverage report: {}", prefix, err);
+                return 1;
+            }
+        }
+        if let (Some((prefix, _)), Some(counters)) = (heatmap, cpu.heatmap()) {
+            let written = fs::File::create(format!("{}.csv", prefix))
+                .and_then(|mut csv| counters.write_csv(&mut csv))
+                .and_then(|_| fs::File::create(format!("{}.png", prefix)))
+                .and_then(|mut png| counters.write_png(&mut png));
+            if let Err(err) = written {
+                eprintln!("cannot write the {} heatmap: {}", prefix, err);
+                return 1;
+            }
+        }
+        if let (Some((csv, _)), Some(log)) = (access_log, cpu.access_log()) {
+            if log.dropped() > 0 {
+                eprintln!(
+                    "access log: the first {} accesses did not fit",
+                    log.dropped()
+                );
+            }
+            if let Err(err) = fs::File::create(csv).and_then(|mut file| log.write_csv(&mut file)) {
+                eprintln!("cannot write {}: {}", csv, err);
+                return 1;
+            }
+        }
+    } else if cpu.halt_reason().is_none() {
+        cpu.run();
+    }
+    match cpu.halt_reason() {
+        Some(HaltReason::AccessViolation(violation)) => {
+            eprintln!("halted: {}", violation);
+            1
+        }
+        _ => match cpu.finish_journal() {
+            Some(Err(err)) => {
+                eprintln!("cannot write the recording: {}", err);
+                1
+            }
+            _ => 0,
+        },
+    }
+}
+
+///
+/// let signals reach a paced machine, it looks for them every
+/// `SIGNAL_POLL_CYCLES`
+///
+fn watch_throttle_signals(cpu: &mut Mos6502) {
+    #[cfg(unix)]
+    {
+        extern "C" fn on_signal(signal: libc::c_int) {
+            if signal == libc::SIGUSR1 {
+                TURBO.fetch_xor(true, Ordering::Relaxed);
+            } else {
+                SPEED_REQUESTED.store(true, Ordering::Relaxed);
+            }
+        }
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe {
+            libc::signal(libc::SIGUSR1, handler);
+            libc::signal(libc::SIGUSR2, handler);
+        }
+    }
+    fn poll(cpu: &mut Mos6502) {
+        cpu.set_turbo(TURBO.load(Ordering::Relaxed));
+        if SPEED_REQUESTED.swap(false, Ordering::Relaxed) {
+            report_speed(cpu);
+        }
+        let at = cpu.cycles() + SIGNAL_POLL_CYCLES;
+        cpu.schedule(at, Box::new(poll));
+    }
+    poll(cpu);
+}
+
+fn report_speed(cpu: &Mos6502) {
+    let Some(throttle) = cpu.throttled() else {
+        return;
+    };
+    let clock = throttle.clock_hz() as f64 / 1e6;
+    let turbo = if throttle.is_turbo() { ", turbo" } else { "" };
+    match throttle.speed() {
+        Some(speed) => eprintln!(
+            "running at {:.3} MHz ({:.3} MHz{})",
+            speed / 1e6,
+            clock,
+            turbo
+        ),
+        None => eprintln!("speed not measured yet ({:.3} MHz{})", clock, turbo),
+    }
+}
+
+///
+/// the `<path> <cycles>` of an option acting once the machine ran that long
+///
+fn path_and_cycles<'a>(options: &mut impl Iterator<Item = &'a String>) -> Option<(&'a str, u64)> {
+    let path = options.next()?;
+    let cycles = options.next()?.parse().ok()?;
+    Some((path, cycles))
+}
+
+///
+/// print the routines and the call tree with their cycles, and save the
+/// folded stacks for flame graph tools
+///
+fn report_profile(cpu: &Mos6502, folded: &str, debug_info: Option<&DebugInfo>) -> io::Result<()> {
+    let Some(profiler) = cpu.profiler() else {
+        return Ok(());
+    };
+    let name = |address: u16| match debug_info.and_then(|info| info.label_before(address)) {
+        Some((label, 0)) => label.to_string(),
+        _ => format!("${:04x}", address),
+    };
+    let total = profiler.total_cycles().max(1);
+    eprintln!(
+        "{:>12} {:>12} {:>10}  routine",
+        "inclusive", "exclusive", "calls"
+    );
+    for routine in profiler.routines().iter().take(PROFILE_LINES) {
+        eprintln!(
+            "{:>12} {:>12} {:>10}  {} ({:.1}%)",
+            routine.inclusive,
+            routine.exclusive,
+            routine.calls,
+            name(routine.address),
+            routine.inclusive as f64 * 100.0 / total as f64
+        );
+    }
+    eprintln!();
+    for call in profiler.call_tree() {
+        eprintln!(
+            "{:>12} {:>12} {:>10}  {}{}",
+            call.inclusive,
+            call.exclusive,
+            call.calls,
+            "  ".repeat(call.depth),
+            name(call.address)
+        );
+    }
+    let mut file = fs::File::create(folded)?;
+    profiler.write_folded(&mut file, &name)
+}
+
+///
+/// save the annotated disassembly of the code that ran, and of the debug
+/// info's address range, as `<prefix>.lst`, with debug info the per line
+/// coverage as `<prefix>.info`, and list the routines that never ran
+///
+fn report_coverage(
+    cpu: &mut Mos6502,
+    prefix: &str,
+    debug_info: Option<&DebugInfo>,
+) -> io::Result<()> {
+    let ranges = [
+        cpu.coverage().and_then(|coverage| coverage.code_range()),
+        debug_info.and_then(|info| info.address_range()),
+    ];
+    let Some((start, end)) = ranges
+        .into_iter()
+        .flatten()
+        .reduce(|(start, end), (first, last)| (start.min(first), end.max(last)))
+    else {
+        eprintln!("no code ran");
+        return Ok(());
+    };
+    let bytes: Vec<u8> = (start..=end).map(|address| cpu.inspect(address)).collect();
+    let Some(coverage) = cpu.coverage() else {
+        return Ok(());
+    };
+    let mut listing = fs::File::create(format!("{}.lst", prefix))?;
+    coverage.write_listing(&mut listing, start, &bytes)?;
+    if let Some(info) = debug_info {
+        let mut lcov = fs::File::create(format!("{}.info", prefix))?;
+        let (found, hit) = info.write_lcov(&mut lcov, coverage)?;
+        eprintln!(
+            "lines: {} of {} ({:.1}%)",
+            hit,
+            found,
+            hit as f64 * 100.0 / found.max(1) as f64
+        );
+    }
+    for routine in coverage.unexecuted_routines(start, &bytes) {
+        match debug_info.and_then(|info| info.label_before(routine)) {
+            Some((label, 0)) => eprintln!("never executed: {} (${:04x})", label, routine),
+            _ => eprintln!("never executed: ${:04x}", routine),
+        }
+    }
+    Ok(())
+}
+
+///
+/// line oriented debugger on a board built from its description, with an
+/// undo history of `--history` bytes to step backwards through
+///
+fn run_debugger(args: &[String]) -> i32 {
+    let usage = "usage: martian6502 debug <description.toml> [--history <bytes>]";
+    let Some(path) = args.first() else {
+        eprintln!("{}", usage);
+        return 1;
+    };
+    let budget = match args.get(1..) {
+        Some([option, bytes]) if option == "--history" => match bytes.parse() {
+            Ok(budget) => budget,
+            Err(_) => {
+                eprintln!("{}", usage);
+                return 1;
+            }
+        },
+        Some([]) => DEFAULT_BUDGET,
+        _ => {
+            eprintln!("{}", usage);
+            return 1;
+        }
+    };
+    let Some(mut debugger) = load_debugger(path, budget) else {
+        return 1;
+    };
+
+    let help = "commands: s(tep), c(ontinue), rs (reverse-step), rc (reverse-continue), \
+                b <addr>, d <addr>, w <addr> [r|w|a], who <addr>, m <addr> [len], r(egisters), q(uit)";
+    print_registers(&debugger);
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return 0;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let address = words.get(1).and_then(|word| parse_hex(word));
+        match (words.first().copied(), address) {
+            (None, _) => continue,
+            (Some("q" | "quit"), _) => return 0,
+            (Some("s" | "step"), _) => {
+                let stop = debugger.step();
+                report_stop(&debugger, stop);
+            }
+            (Some("c" | "continue"), _) => {
+                let stop = debugger.resume();
+                report_stop(&debugger, stop);
+            }
+            (Some("rs" | "reverse-step"), _) => {
+                let stop = debugger.reverse_step();
+                report_stop(&debugger, stop);
+            }
+            (Some("rc" | "reverse-continue"), _) => {
+                let stop = debugger.reverse_resume();
+                report_stop(&debugger, stop);
+            }
+            (Some("r" | "registers"), _) => print_registers(&debugger),
+            (Some("b" | "break"), Some(address)) => debugger.add_breakpoint(address),
+            (Some("d" | "delete"), Some(address)) => {
+                debugger.remove_breakpoint(address);
+            }
+            (Some("w" | "watch"), Some(address)) => {
+                let kind = match words.get(2).copied() {
+                    Some("r") => WatchKind::Read,
+                    Some("a") => WatchKind::Access,
+                    _ => WatchKind::Write,
+                };
+                debugger.add_watchpoint(Watchpoint {
+                    start: address,
+                    end: address,
+                    kind,
+                });
+            }
+            (Some("who"), Some(address)) => match debugger.last_write(address) {
+                Some(write) => println!(
+                    "${:04x} = ${:02x}, written by the instruction at ${:04x} (#{}, cycle {})",
+                    address, write.val, write.pc, write.instruction, write.cycles
+                ),
+                None => println!("no write to ${:04x} in the history", address),
+            },
+            (Some("m" | "memory"), Some(address)) => {
+                let len = words
+                    .get(2)
+                    .and_then(|len| len.parse().ok())
+                    .unwrap_or(16u16);
+                let bytes: Vec<String> = (0..len)
+                    .map(|offset| {
+                        format!(
+                            "{:02x}",
+                            debugger.cpu_mut().inspect(address.wrapping_add(offset))
+                        )
+                    })
+                    .collect();
+                println!("${:04x}: {}", address, bytes.join(" "));
+            }
+            _ => println!("{}", help),
+        }
+    }
+}
+
+///
+/// serve a board built from its description to GDB, which connects with
+/// `target remote localhost:<port>`
+///
+fn run_gdb(args: &[String]) -> i32 {
+    let usage = "usage: martian6502 gdb <description.toml> [--port <port>] [--history <bytes>]";
+    let Some(path) = args.first() else {
+        eprintln!("{}", usage);
+        return 1;
+    };
+    let mut port = gdb::DEFAULT_PORT;
+    let mut budget = DEFAULT_BUDGET;
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        let parsed = match (option.as_str(), options.next()) {
+            ("--port", Some(val)) => val.parse().map(|val| port = val).is_ok(),
+            ("--history", Some(val)) => val.parse().map(|val| budget = val).is_ok(),
+            _ => false,
+        };
+        if !parsed {
+            eprintln!("{}", usage);
+            return 1;
+        }
+    }
+    let Some(mut debugger) = load_debugger(path, budget) else {
+        return 1;
+    };
+    eprintln!("waiting for gdb on localhost:{}", port);
+    if let Err(err) = gdb::serve(&mut debugger, port) {
+        eprintln!("gdb connection failed: {}", err);
+        return 1;
+    }
+    0
+}
+
+///
+/// serve a board built from its description over the VICE binary monitor
+/// protocol, for tools that connect to `x64sc -binarymonitor`
+///
+fn run_vice(args: &[String]) -> i32 {
+    let usage = "usage: martian6502 vice <description.toml> [--port <port>]";
+    let Some(path) = args.first() else {
+        eprintln!("{}", usage);
+        return 1;
+    };
+    let mut port = vice::DEFAULT_PORT;
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        let parsed = match (option.as_str(), options.next()) {
+            ("--port", Some(val)) => val.parse().map(|val| port = val).is_ok(),
+            _ => false,
+        };
+        if !parsed {
+            eprintln!("{}", usage);
+            return 1;
+        }
+    }
+    // the protocol has no way to run backwards
+    let Some(mut debugger) = load_debugger(path, 0) else {
+        return 1;
+    };
+    eprintln!("waiting for a binary monitor client on localhost:{}", port);
+    if let Err(err) = vice::serve(&mut debugger, port) {
+        eprintln!("binary monitor connection failed: {}", err);
+        return 1;
+    }
+    0
+}
+
+///
+/// Debug Adapter Protocol server for editors, on stdio unless a localhost
+/// port is given. The description is what launch requests naming no
+/// machine get.
+///
+fn run_dap(args: &[String]) -> i32 {
+    let usage = "usage: martian6502 dap [--port <port>] [<description.toml>]";
+    let mut port = None;
+    let mut machine = None;
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        if option == "--port" {
+            match options.next().map(|val| val.parse()) {
+                Some(Ok(val)) => port = Some(val),
+                _ => {
+                    eprintln!("{}", usage);
+                    return 1;
+                }
+            }
+        } else if machine.is_none() {
+            machine = Some(PathBuf::from(option));
+        } else {
+            eprintln!("{}", usage);
+            return 1;
+        }
+    }
+    let served = match port {
+        None => dap::serve(io::stdin(), io::stdout(), machine),
+        Some(port) => TcpListener::bind((Ipv4Addr::LOCALHOST, port)).and_then(|listener| {
+            eprintln!("waiting for a debug adapter client on localhost:{}", port);
+            let (stream, _) = listener.accept()?;
+            dap::serve(stream.try_clone()?, stream, machine)
+        }),
+    };
+    if let Err(err) = served {
+        eprintln!("debug adapter failed: {}", err);
+        return 1;
+    }
+    0
+}
+
+///
+/// run test scripts, and every script of the directories given
+///
+fn run_scripts(args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("usage: martian6502 test <script or directory>...");
+        return 1;
+    }
+    let paths: Vec<PathBuf> = args.iter().map(PathBuf::from).collect();
+    match script::run_all(&paths, &mut io::stdout()) {
+        Ok(passed) => !passed as i32,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+///
+/// a board from its description recording a history of `budget` bytes
+///
+fn load_debugger(path: &str, budget: usize) -> Option<Debugger> {
+    match description::load(Path::new(path)) {
+        Ok(mut cpu) => {
+            cpu.record_history(budget, DEFAULT_SNAPSHOT_INTERVAL);
+            Some(Debugger::new(cpu))
+        }
+        Err(err) => {
+            eprintln!("cannot build {}: {}", path, err);
+            None
+        }
+    }
+}
+
+fn report_stop(debugger: &Debugger, stop: Stop) {
+    match stop {
+        Stop::Stepped | Stop::Limit => {}
+        Stop::Breakpoint(address) => println!("breakpoint at ${:04x}", address),
+        Stop::Watchpoint(_, access) => println!(
+            "watchpoint: {:?} ${:02x} at ${:04x}",
+            access.kind, access.val, access.address
+        ),
+        Stop::Halted(HaltReason::AccessViolation(violation)) => println!("halted: {}", violation),
+        Stop::Halted(reason) => println!("halted: {:?}", reason),
+        Stop::HistoryStart => println!("reached the start of the history"),
+    }
+    print_registers(debugger);
+}
+
+fn print_registers(debugger: &Debugger) {
+    let registers = debugger.cpu().registers();
+    println!(
+        "pc ${:04x}  a ${:02x}  x ${:02x}  y ${:02x}  sp ${:02x}  p ${:02x}  cycles {}",
+        registers.pc,
+        registers.ac,
+        registers.xr,
+        registers.yr,
+        registers.sp,
+        registers.sr,
+        debugger.cpu().cycles()
+    );
+}
+
+///
+/// map a 6551 described as `<hex address>[,stdio|pty|tcp:<port>]`
+///
+fn attach_acia(cpu: &mut Mos6502, spec: &str) -> Result<(), String> {
+    let (address, host) = spec.split_once(',').unwrap_or((spec, "stdio"));
+    let address = parse_hex(address).ok_or(format!("bad acia address {}", address))?;
+    let host = open_host(host).map_err(|err| format!("cannot open the acia host: {}", err))?;
+    cpu.attach(address, Box::new(Acia::new(host, DEFAULT_CLOCK_HZ)));
+    Ok(())
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+///
+/// run a `cl65 --target sim6502` program headless, the program's arguments
+/// follow its path and its exit code becomes ours
+///
+fn run_sim65(cpu: &mut Mos6502, args: &[String]) -> i32 {
+    let Some(program) = args.first() else {
+        eprintln!("usage: martian6502 sim65 <program> [args...]");
+        return SIM65_ERROR;
+    };
+    let loaded = fs::read(program).and_then(|image| cpu.load_sim65(&image, args.to_vec()));
+    if let Err(err) = loaded {
+        eprintln!("cannot load {}: {}", program, err);
+        return SIM65_ERROR;
+    }
+    cpu.run();
+    match cpu.halt_reason() {
+        Some(HaltReason::Exit(code)) => code as i32,
+        _ => SIM65_ERROR,
+    }
 }
@@ -1,11 +1,132 @@
+pub mod access_log;
 mod address_mode;
 mod constant;
+pub mod coverage;
+pub mod disasm;
+pub mod heatmap;
+pub mod history;
 mod insset;
+pub mod journal;
+pub mod paravirt;
+pub mod profile;
+mod region;
+pub mod scheduler;
+mod snapshot;
+pub mod throttle;
 
+use std::{cell::RefCell, rc::Rc};
+
+use crate::device::Device;
+use access_log::AccessLog;
 use console::Term;
-use constant::BIT_0_MASK;
+use constant::{
+    BIT_0_MASK, BREAK_ON_MASK, INTERRUPT_ON_MASK, IRQ_VECTOR, NMI_VECTOR, OVERFLOW_ON_MASK,
+    RESET_VECTOR, STACK_PAGE, UNUSED_ON_MASK,
+};
+use coverage::Coverage;
+use heatmap::Heatmap;
+use history::History;
 use insset::parser::parse;
 use insset::{InsAttr, Mos6502Ins};
+use journal::{Input, Journal, Recording, Source};
+use paravirt::Paravirt;
+use profile::Profiler;
+pub use region::{AccessViolation, Region, RegionKind, ViolationKind};
+use scheduler::{Callback, EventId, Queue, Scheduler, Target};
+use throttle::Throttle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltReason {
+    Stopped,
+    Exit(u8), // the program asked the host to exit with this code
+    AccessViolation(AccessViolation), // only in strict mode
+    Diverged(u64), // a replay's state differed from the recording at this cycle
+}
+
+///
+/// snapshot of the programmer visible registers
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Registers {
+    pub pc: u16,
+    pub sp: u8,
+    pub ac: u8,
+    pub xr: u8,
+    pub yr: u8,
+    pub sr: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Fetch, // opcode read
+    Read,
+    Write,
+}
+
+///
+/// one bus cycle as the cpu issued it, before mirrors are resolved
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: u16,
+    pub val: u8,
+    pub old: Option<u8>, // RAM contents a write replaced
+}
+
+///
+/// host code standing in for the routine at a fixed address, it runs instead
+/// of the instruction found there
+///
+pub type Trap = Box<dyn FnMut(&mut Mos6502)>;
+
+///
+/// cpu input a device's interrupt output is wired to, an asserted output
+/// pulls the input low
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptLine {
+    Irq,
+    Nmi,
+    Ready,    // RDY, stalls read cycles
+    Overflow, // SO, sets V when pulled low
+    Reset,
+    Disconnected,
+}
+
+///
+/// interrupts the cpu recognized when it looked at its inputs
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Poll {
+    irq: bool, // asserted and not masked
+    nmi: bool, // an edge not served yet
+}
+
+const RTI: u8 = 0x40;
+
+struct MappedDevice {
+    start: u16,
+    end: Option<u16>, // last address decoded by the device
+    device: Box<dyn Device>,
+    line: InterruptLine,
+}
+
+impl MappedDevice {
+    fn decodes(&self, address: u16) -> bool {
+        self.end
+            .is_some_and(|end| self.start <= address && address <= end)
+    }
+}
+
+///
+/// addresses `start..=end` decode to the same cells as the range from `target`
+///
+struct Mirror {
+    start: u16,
+    end: u16,
+    target: u16,
+}
 
 pub struct Mos6502 {
     pc: u16,
@@ -15,43 +136,1056 @@ pub struct Mos6502 {
     yr: u8,
     sr: u8, // Processing status layout: NV-BDIZC
     mem: [u8; 64 * 1024],
+    cycles: u64, // cpu cycles elapsed since power on
+    irq: bool,   // level of the IRQ input driven from outside the bus
+    nmi: bool,   // level of the NMI input driven from outside the bus
+    nmi_seen: bool, // NMI is edge triggered, the level when last sampled
+    nmi_latched: bool, // an NMI edge was seen and not served yet
+    samples: [Poll; 3], // the inputs at the end of the last cycles, newest last
+    poll: Option<Poll>, // taken by the next step, None samples the inputs then
+    early_poll: bool, // the instruction looks at its inputs a cycle early
+    ticked: u32, // cycles of the instruction the devices already saw
+    not_ready: bool, // RDY held low from outside the bus
+    so_low: bool,   // SO held low from outside the bus
+    so_seen: bool,  // SO is edge triggered, whether it was low when last sampled
+    reset_held: bool, // RESET held low from outside the bus
+    reset_seen: bool, // the reset sequence runs once RESET is released
     power_on: bool,
+    halt_reason: Option<HaltReason>,
+    paravirt: Option<Paravirt>,
+    devices: Vec<MappedDevice>,
+    mirrors: Vec<Mirror>,
+    regions: Vec<Region>, // later regions take precedence
+    strict: bool,
+    cycle_accurate: bool, // every bus access is one cycle
+    instruction_pc: u16, // start of the instruction being executed
+    traps: Vec<(u16, Trap)>,
+    events: Rc<RefCell<Queue>>, // shared with the attached devices
+    journal: Rc<RefCell<Journal>>, // inputs from the host, shared as well
+    throttle: Option<(Throttle, EventId)>, // and its next pacing event
+    tracing: bool,
+    accesses: Vec<Access>, // made by the last step when tracing
+    history: Option<History>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    heatmap: Option<Heatmap>,
+    access_log: Option<AccessLog>,
 }
 
 impl Mos6502 {
-    // pub fn start(self: &mut Self) {
-    //     self.power_on = true;
-    //     while self.power_on {
-    //         let ins: Box<dyn Mos6502Ins> = parse(self.mem[self.pc as usize] as u8);
-    //         ins.execute(self);
-    //     }
-    // }
+    pub fn run(&mut self) {
+        self.resume();
+        while self.power_on {
+            self.step();
+        }
+    }
+
+    ///
+    /// run until the cycle counter reaches `cycles` or the cpu halts
+    ///
+    pub fn run_until(&mut self, cycles: u64) {
+        self.resume();
+        while self.power_on && self.cycles < cycles {
+            self.step();
+        }
+    }
 
     pub fn debug(self: &mut Self) {
         self.power_on = true;
         let stdout = Term::stdout();
         while self.power_on {
-            let ins: Box<dyn Mos6502Ins> = parse(self.mem[self.pc as usize] as u8);
             println!(
                 "pc: {}\nsp: {}\nac: {}\nxr: {}\nyr: {}\nsr: {}\nins_opcode: {}\n",
                 self.pc, self.sp, self.ac, self.xr, self.yr, self.sr, self.mem[self.pc as usize]
             );
             if let Ok(_) = stdout.read_char() {}
-            ins.execute(self);
+            self.step();
         }
     }
 
     pub fn stop(self: &mut Self) {
-        self.power_on = false
+        self.halt(HaltReason::Stopped)
+    }
+
+    pub fn halt(&mut self, reason: HaltReason) {
+        self.power_on = false;
+        self.halt_reason = Some(reason);
+    }
+
+    ///
+    /// forget why the cpu halted, for callers stepping it themselves
+    ///
+    pub fn resume(&mut self) {
+        self.power_on = true;
+        self.halt_reason = None;
+    }
+
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        self.halt_reason
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    ///
+    /// capture registers, memory, interrupt inputs and device state
+    ///
+    pub fn snapshot(&self) -> Vec<u8> {
+        snapshot::save(self)
+    }
+
+    ///
+    /// go back to a snapshot taken on a machine built the same way
+    ///
+    pub fn restore_snapshot(&mut self, snapshot: &[u8]) -> std::io::Result<()> {
+        snapshot::restore(self, snapshot)?;
+        // the pacing event was due at the old cycle count
+        if let Some((_, event)) = self.throttle.as_ref() {
+            let event = *event;
+            self.cancel(event);
+            self.pace_in(0);
+        }
+        Ok(())
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            pc: self.pc,
+            sp: self.sp,
+            ac: self.ac,
+            xr: self.xr,
+            yr: self.yr,
+            sr: self.sr,
+        }
+    }
+
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.pc = registers.pc;
+        self.sp = registers.sp;
+        self.ac = registers.ac;
+        self.xr = registers.xr;
+        self.yr = registers.yr;
+        self.sr = registers.sr;
+    }
+
+    ///
+    /// read a byte the way the cpu would, devices see the access
+    ///
+    pub fn peek(&mut self, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    pub fn poke(&mut self, address: u16, val: u8) {
+        self.write(address, val)
+    }
+
+    ///
+    /// read on behalf of a debugger: devices still see the read, but the
+    /// memory map is not enforced and nothing is traced
+    ///
+    pub fn inspect(&mut self, address: u16) -> u8 {
+        let address = self.resolve(address);
+        if let Some((offset, device)) = self.device_at(address) {
+            return device.read(offset);
+        }
+        self.mem[address as usize]
+    }
+
+    ///
+    /// read on behalf of a debugger without disturbing the machine, device
+    /// registers that cannot be read quietly read as open bus
+    ///
+    pub fn peek_quiet(&self, address: u16) -> u8 {
+        let address = self.resolve(address);
+        match self.devices.iter().find(|mapped| mapped.decodes(address)) {
+            Some(mapped) => mapped
+                .device
+                .peek(address - mapped.start)
+                .unwrap_or((address >> 8) as u8),
+            None => self.mem[address as usize],
+        }
+    }
+
+    ///
+    /// whether a device answers at `address` rather than memory
+    ///
+    pub fn device_mapped(&self, address: u16) -> bool {
+        let address = self.resolve(address);
+        self.devices.iter().any(|mapped| mapped.decodes(address))
+    }
+
+    ///
+    /// write on behalf of a debugger, ROM can be patched this way
+    ///
+    pub fn deposit(&mut self, address: u16, val: u8) {
+        let address = self.resolve(address);
+        if let Some((offset, device)) = self.device_at(address) {
+            return device.write(offset, val);
+        }
+        self.mem[address as usize] = val;
+    }
+
+    ///
+    /// execute bus cycle by bus cycle: the dummy reads and writes the real
+    /// cpu makes are made, page crossings and taken branches cost their
+    /// extra cycle, and devices are ticked after every cycle instead of
+    /// after every instruction
+    ///
+    pub fn set_cycle_accurate(&mut self, cycle_accurate: bool) {
+        self.cycle_accurate = cycle_accurate;
+    }
+
+    ///
+    /// run `handler` whenever the cpu is about to execute `address`
+    ///
+    pub fn trap(&mut self, address: u16, handler: Trap) {
+        self.traps.push((address, handler));
+    }
+
+    ///
+    /// run `callback` once the cycle counter reaches `at`, after the
+    /// instruction that gets it there
+    ///
+    pub fn schedule(&mut self, at: u64, callback: Callback) -> EventId {
+        self.events
+            .borrow_mut()
+            .schedule(at, Target::Host(callback))
+    }
+
+    ///
+    /// false if the event already fired or was cancelled
+    ///
+    pub fn cancel(&mut self, id: EventId) -> bool {
+        self.events.borrow_mut().cancel(id)
+    }
+
+    ///
+    /// deadline of the earliest pending event
+    ///
+    pub fn next_event(&self) -> Option<u64> {
+        self.events.borrow().next_deadline()
+    }
+
+    ///
+    /// pace execution to `clock_hz` cycles per second of wall clock time
+    ///
+    pub fn throttle(&mut self, clock_hz: u32) {
+        self.unthrottle();
+        self.throttle = Some((Throttle::new(clock_hz, self.cycles), 0));
+        self.pace_in(0);
+    }
+
+    pub fn unthrottle(&mut self) {
+        if let Some((_, event)) = self.throttle.take() {
+            self.cancel(event);
+        }
+    }
+
+    ///
+    /// run a throttled cpu as fast as the host allows, or paced again
+    ///
+    pub fn set_turbo(&mut self, turbo: bool) {
+        if let Some((throttle, _)) = self.throttle.as_mut() {
+            throttle.set_turbo(turbo);
+        }
+    }
+
+    pub fn throttled(&self) -> Option<&Throttle> {
+        self.throttle.as_ref().map(|(throttle, _)| throttle)
+    }
+
+    fn pace_in(&mut self, cycles: u64) {
+        let at = self.cycles + cycles;
+        let event = self.schedule(at, Box::new(Self::pace));
+        if let Some((_, pending)) = self.throttle.as_mut() {
+            *pending = event;
+        }
+    }
+
+    fn pace(&mut self) {
+        let Some((throttle, _)) = self.throttle.as_mut() else {
+            return;
+        };
+        throttle.pace(self.cycles);
+        let slice = throttle.slice();
+        self.pace_in(slice);
+    }
+
+    ///
+    /// leave the subroutine a trap replaced the way RTS would
+    ///
+    pub fn return_from_subroutine(&mut self) {
+        let return_lsb = self.pull() as u16;
+        let return_msb = self.pull() as u16;
+        self.pc = (return_msb << 8 | return_lsb).wrapping_add(1);
+    }
+
+    ///
+    /// drive the IRQ input, the line is level triggered and shared with the
+    /// devices on the bus
+    ///
+    pub fn set_irq(&mut self, level: bool) {
+        let mut journal = self.journal.borrow_mut();
+        if journal.is_replaying() {
+            return;
+        }
+        if level != self.irq {
+            journal.log(self.cycles, Input::Irq(level));
+        }
+        self.irq = level;
+    }
+
+    ///
+    /// drive the NMI input, an interrupt is taken on every rising edge
+    ///
+    pub fn set_nmi(&mut self, level: bool) {
+        let mut journal = self.journal.borrow_mut();
+        if journal.is_replaying() {
+            return;
+        }
+        if level != self.nmi {
+            journal.log(self.cycles, Input::Nmi(level));
+        }
+        self.nmi = level;
+    }
+
+    ///
+    /// drive RDY, while it is low the cpu waits at its next read cycle. Only
+    /// a cycle accurate cpu stops in the middle of an instruction.
+    ///
+    pub fn set_rdy(&mut self, ready: bool) {
+        self.not_ready = !ready;
+    }
+
+    ///
+    /// drive SO, a falling edge sets the overflow flag
+    ///
+    pub fn set_so(&mut self, level: bool) {
+        self.so_low = !level;
+        self.sample_so();
+    }
+
+    ///
+    /// hold RESET low, or release it: the cpu idles while it is held and
+    /// runs the reset sequence once released
+    ///
+    pub fn set_reset(&mut self, held: bool) {
+        self.reset_held = held;
+    }
+
+    ///
+    /// map a device on the bus starting at `start`, it shadows the memory
+    /// underneath and its interrupt output drives IRQ
+    ///
+    pub fn attach(&mut self, start: u16, device: Box<dyn Device>) {
+        self.attach_wired(start, device, InterruptLine::Irq);
+    }
+
+    pub fn attach_wired(&mut self, start: u16, mut device: Box<dyn Device>, line: InterruptLine) {
+        let end = match device.size() {
+            0 => None,
+            size => Some(start.saturating_add(size - 1)),
+        };
+        self.events.borrow_mut().set_now(self.cycles);
+        device.connect(Scheduler::new(
+            self.devices.len(),
+            self.events.clone(),
+            self.journal.clone(),
+        ));
+        self.devices.push(MappedDevice {
+            start,
+            end,
+            device,
+            line,
+        });
+    }
+
+    ///
+    /// make `start..=end` an alias of the range beginning at `target`, devices
+    /// included. Partial address decoding on real boards does the same.
+    ///
+    pub fn mirror(&mut self, start: u16, end: u16, target: u16) {
+        self.mirrors.push(Mirror { start, end, target });
+    }
+
+    ///
+    /// give a range of the address space its attributes, it overrides the
+    /// regions mapped before
+    ///
+    pub fn map(&mut self, region: Region) {
+        self.regions.push(region);
+    }
+
+    ///
+    /// halt on the first access the memory map does not allow instead of
+    /// ignoring writes and reading open bus
+    ///
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    ///
+    /// copy a raw image into memory and start executing from its first byte
+    ///
+    pub fn load(&mut self, address: u16, image: &[u8]) {
+        let start = address as usize;
+        let end = (start + image.len()).min(self.mem.len());
+        self.mem[start..end].copy_from_slice(&image[..end - start]);
+        self.pc = address;
+    }
+
+    ///
+    /// reset sequence: continue at the address the reset vector points to
+    /// with interrupts masked
+    ///
+    pub fn reset(&mut self) {
+        // an interrupt sequence whose stack writes come out as reads, the
+        // stack pointer still moves past the three bytes it did not push
+        self.dummy_read(self.pc);
+        self.dummy_read(self.pc);
+        for pushed in 0..3u8 {
+            self.dummy_read(STACK_PAGE | self.sp.wrapping_sub(pushed) as u16);
+        }
+        let lsb = self.read(RESET_VECTOR) as u16;
+        let msb = self.read(RESET_VECTOR + 1) as u16;
+        self.pc = msb << 8 | lsb;
+        self.sp = self.sp.wrapping_sub(3);
+        self.sr |= INTERRUPT_ON_MASK;
+        self.elapse(7);
+    }
+
+    ///
+    /// load a sim65 image and let it reach the host through the paravirtualization hooks
+    ///
+    pub fn load_sim65(&mut self, image: &[u8], args: Vec<String>) -> std::io::Result<()> {
+        paravirt::load(self, image, args)
+    }
+
+    ///
+    /// execute one instruction, or take one interrupt
+    ///
+    pub fn step(&mut self) {
+        self.accesses.clear();
+        let (pc, cycles) = (self.pc, self.cycles);
+        let mut history = self.history.take();
+        let mut profiler = self.profiler.take();
+        if let Some(history) = history.as_mut() {
+            history.begin(self);
+        }
+        if let Some(profiler) = profiler.as_mut() {
+            profiler.begin(self);
+        }
+        self.replay_lines();
+        self.execute();
+        self.dispatch_events();
+        self.checkpoint();
+        if let Some(profiler) = profiler.as_mut() {
+            profiler.end(self);
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.end(&self.accesses);
+        }
+        if let Some(heatmap) = self.heatmap.as_mut() {
+            heatmap.end(&self.accesses);
+        }
+        if let Some(log) = self.access_log.as_mut() {
+            log.end(&self.accesses, pc, cycles);
+        }
+        if let Some(history) = history.as_mut() {
+            history.end(self);
+        }
+        self.history = history;
+        self.profiler = profiler;
+    }
+
+    ///
+    /// write down every input from the host from now on, see `journal`.
+    /// Lines go to `sink` as they happen, so a run that never ends still
+    /// leaves its recording behind.
+    ///
+    pub fn record(&mut self, checkpoint_interval: u64, sink: Option<Box<dyn std::io::Write>>) {
+        self.journal
+            .borrow_mut()
+            .record(checkpoint_interval, self.cycles, sink);
+    }
+
+    ///
+    /// take the inputs from a recording instead of the host, the machine has
+    /// to be where it was when the recording started. A checkpoint that does
+    /// not match halts the cpu.
+    ///
+    pub fn replay(&mut self, recording: Recording) {
+        self.journal.borrow_mut().replay(recording, self.cycles);
+    }
+
+    ///
+    /// stop recording or replaying, what was recorded if anything
+    ///
+    pub fn finish_journal(&mut self) -> Option<std::io::Result<Recording>> {
+        self.journal.borrow_mut().finish()
+    }
+
+    ///
+    /// a byte the host hands a trap, recorded and replayed like the ones
+    /// devices get. The trap's address tells the sources apart.
+    ///
+    pub fn host_input(&mut self, trap: u16, poll: impl FnOnce() -> Option<u8>) -> Option<u8> {
+        self.journal
+            .borrow_mut()
+            .byte(Source::Trap(trap), self.cycles, poll)
+    }
+
+    ///
+    /// record the accesses each step makes, see `accesses`
+    ///
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.tracing = tracing;
+        self.accesses.clear();
+    }
+
+    pub fn accesses(&self) -> &[Access] {
+        &self.accesses
+    }
+
+    ///
+    /// keep an undo history of up to `budget` bytes so execution can be
+    /// stepped backwards, this turns tracing on
+    ///
+    pub fn record_history(&mut self, budget: usize, snapshot_interval: u64) {
+        self.history = Some(History::new(budget, snapshot_interval));
+        self.tracing = true;
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    ///
+    /// start profiling where the cpu stands, this turns tracing on
+    ///
+    pub fn profile(&mut self) {
+        self.profiler = Some(Profiler::new());
+        self.tracing = true;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    ///
+    /// start recording coverage, this turns tracing on
+    ///
+    pub fn cover(&mut self) {
+        self.coverage = Some(Coverage::new());
+        self.tracing = true;
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    ///
+    /// start counting the accesses to every address, this turns tracing on
+    ///
+    pub fn count_accesses(&mut self) {
+        self.heatmap = Some(Heatmap::new());
+        self.tracing = true;
+    }
+
+    pub fn heatmap(&self) -> Option<&Heatmap> {
+        self.heatmap.as_ref()
+    }
+
+    ///
+    /// start logging the last `capacity` bus accesses, this turns tracing on.
+    /// The accesses are timed one by one if the cpu is cycle accurate by now.
+    ///
+    pub fn log_accesses(&mut self, capacity: usize) {
+        self.access_log = Some(AccessLog::new(capacity, self.cycle_accurate));
+        self.tracing = true;
+    }
+
+    pub fn access_log(&self) -> Option<&AccessLog> {
+        self.access_log.as_ref()
+    }
+
+    ///
+    /// undo the last recorded step, returns the accesses it had made or
+    /// `None` at the start of the history. Devices are only rewound when a
+    /// snapshot was taken before that step.
+    ///
+    pub fn reverse_step(&mut self) -> Option<Vec<Access>> {
+        let mut history = self.history.take()?;
+        let record = history.pop();
+        if let Some(record) = &record {
+            let snapshot = history.snapshot_before(record.instruction);
+            if snapshot.is_none_or(|snapshot| self.restore_snapshot(snapshot).is_err()) {
+                for access in record.accesses.iter().rev() {
+                    if let (AccessKind::Write, Some(old)) = (access.kind, access.old) {
+                        self.mem[self.resolve(access.address) as usize] = old;
+                    }
+                }
+                self.set_registers(record.registers);
+                self.cycles = record.cycles;
+            }
+            self.halt_reason = record.halt_reason;
+        }
+        self.history = Some(history);
+        record.map(|record| record.accesses)
+    }
+
+    fn execute(&mut self) {
+        if self.hold() {
+            return;
+        }
+        self.sample_so();
+        if let Some(index) = self.traps.iter().position(|(address, _)| *address == self.pc) {
+            let (address, mut handler) = self.traps.swap_remove(index);
+            handler(self);
+            self.traps.push((address, handler));
+            self.poll = None;
+            return;
+        }
+        if self.paravirt.is_some() && paravirt::is_hook(self.pc) {
+            paravirt::call(self);
+            self.poll = None;
+            return;
+        }
+        let start = self.cycles;
+        self.events.borrow_mut().set_now(start);
+        self.instruction_pc = self.pc;
+        self.ticked = 0;
+        self.early_poll = false;
+        let masked = self.sr & INTERRUPT_ON_MASK != 0;
+        let poll = match self.poll.take() {
+            Some(poll) => poll,
+            None => {
+                self.sample_interrupts(masked);
+                self.samples[2]
+            }
+        };
+        let mut opcode = None;
+        if poll.nmi {
+            self.nmi_latched = false;
+            self.interrupt(NMI_VECTOR);
+        } else if poll.irq {
+            self.interrupt(IRQ_VECTOR);
+        } else {
+            if !self.region_at(self.resolve(self.pc)).executable {
+                self.violation(ViolationKind::Execute, self.pc);
+                if self.strict {
+                    return;
+                }
+            }
+            let fetched = self.fetch();
+            // one byte instructions read the next byte while decoding
+            if disasm::length(fetched) == Some(1) {
+                self.dummy_read(self.pc.wrapping_add(1));
+            }
+            let ins: Box<dyn Mos6502Ins> = parse(fetched);
+            ins.execute(self);
+            opcode = Some(fetched);
+        }
+        // interrupt sequences leave a poll behind, the handler's first
+        // instruction always runs
+        let polled = self.poll.is_some();
+        let elapsed = (self.cycles - start) as u32;
+        let poll_cycle = elapsed.saturating_sub(1 + self.early_poll as u32);
+        if self.cycle_accurate {
+            if !polled {
+                let sample = if self.early_poll { 0 } else { 1 };
+                self.poll = Some(self.samples[sample]);
+            }
+        } else {
+            if !polled {
+                // CLI, SEI and PLP change the flag after the inputs were looked at
+                let masked = match opcode {
+                    Some(RTI) => self.sr & INTERRUPT_ON_MASK != 0,
+                    _ => masked,
+                };
+                self.settle(poll_cycle, masked);
+                self.poll = Some(self.samples[2]);
+            }
+            self.tick_devices(elapsed.saturating_sub(self.ticked));
+            self.ticked = elapsed;
+            self.sample_interrupts(self.sr & INTERRUPT_ON_MASK != 0);
+        }
+    }
+
+    ///
+    /// look at the interrupt inputs as they are at the end of a cycle, an
+    /// NMI edge stays latched until it is served
+    ///
+    fn sample_interrupts(&mut self, masked: bool) {
+        let nmi = self.line_asserted(InterruptLine::Nmi, self.nmi);
+        if nmi && !self.nmi_seen {
+            self.nmi_latched = true;
+        }
+        self.nmi_seen = nmi;
+        let sample = Poll {
+            irq: !masked && self.line_asserted(InterruptLine::Irq, self.irq),
+            nmi: self.nmi_latched,
+        };
+        self.samples = [self.samples[1], self.samples[2], sample];
+    }
+
+    ///
+    /// let the devices catch up with the first `cycles` of the instruction
+    /// and sample the inputs there, a cycle accurate cpu does it every cycle
+    ///
+    fn settle(&mut self, cycles: u32, masked: bool) {
+        if !self.cycle_accurate {
+            self.tick_devices(cycles.saturating_sub(self.ticked));
+            self.ticked = self.ticked.max(cycles);
+            self.sample_interrupts(masked);
+        }
+    }
+
+    ///
+    /// the vector a BRK or IRQ sequence fetches: an NMI seen by its fourth
+    /// cycle takes it over, the status already on its way to the stack
+    ///
+    fn sequence_vector(&mut self, vector: u16) -> u16 {
+        self.poll = Some(Poll::default());
+        self.settle(4, true);
+        if vector != NMI_VECTOR && self.nmi_latched {
+            self.nmi_latched = false;
+            return NMI_VECTOR;
+        }
+        vector
+    }
+
+    ///
+    /// taken branches that stay in their page look at the interrupt
+    /// inputs before their last cycle rather than during it
+    ///
+    fn poll_early(&mut self) {
+        self.early_poll = true;
+    }
+
+    ///
+    /// RESET and RDY keep the cpu from running the step, true if they did
+    ///
+    fn hold(&mut self) -> bool {
+        if self.line_asserted(InterruptLine::Reset, self.reset_held) {
+            self.reset_seen = true;
+            self.clock_cycle();
+            return true;
+        }
+        if self.reset_seen {
+            self.reset_seen = false;
+            let start = self.cycles;
+            self.reset();
+            if !self.cycle_accurate {
+                self.tick_devices((self.cycles - start) as u32);
+            }
+            self.poll = Some(Poll::default());
+            return true;
+        }
+        if !self.is_ready() {
+            self.clock_cycle();
+            return true;
+        }
+        false
+    }
+
+    fn is_ready(&self) -> bool {
+        !self.line_asserted(InterruptLine::Ready, self.not_ready)
+    }
+
+    fn sample_so(&mut self) {
+        let low = self.line_asserted(InterruptLine::Overflow, self.so_low);
+        if low && !self.so_seen {
+            self.sr |= OVERFLOW_ON_MASK;
+        }
+        self.so_seen = low;
+    }
+
+    ///
+    /// the interrupt levels a replay drives, in place of the host
+    ///
+    fn replay_lines(&mut self) {
+        loop {
+            let due = self.journal.borrow_mut().line_due(self.cycles);
+            match due {
+                Some(Input::Irq(level)) => self.irq = level,
+                Some(Input::Nmi(level)) => self.nmi = level,
+                _ => break,
+            }
+        }
+    }
+
+    fn checkpoint(&mut self) {
+        if !self.journal.borrow().checkpoint_due(self.cycles) {
+            return;
+        }
+        let hash = journal::state_hash(&self.snapshot());
+        if !self.journal.borrow_mut().checkpoint(self.cycles, hash) {
+            self.halt(HaltReason::Diverged(self.cycles));
+        }
+    }
+
+    ///
+    /// deliver the events due by now, those they schedule for now included
+    ///
+    fn dispatch_events(&mut self) {
+        loop {
+            let due = {
+                let mut events = self.events.borrow_mut();
+                events.set_now(self.cycles);
+                events.pop_due(self.cycles)
+            };
+            match due {
+                Some(Target::Device(index, event)) => self.devices[index].device.event(event),
+                Some(Target::Host(mut callback)) => callback(self),
+                None => break,
+            }
+        }
+    }
+
+    ///
+    /// inputs are wired-or, `external` is the level driven from outside the bus
+    ///
+    fn line_asserted(&self, line: InterruptLine, external: bool) -> bool {
+        external
+            || self
+                .devices
+                .iter()
+                .any(|mapped| mapped.line == line && mapped.device.irq())
+    }
+
+    ///
+    /// hardware interrupt sequence: save pc and status, mask further IRQs
+    /// and continue at the handler the vector points to
+    ///
+    fn interrupt(&mut self, vector: u16) {
+        self.dummy_read(self.pc);
+        self.dummy_read(self.pc);
+        self.push((self.pc >> 8) as u8);
+        self.push(self.pc as u8);
+        let vector = self.sequence_vector(vector);
+        self.push(self.sr & !BREAK_ON_MASK | UNUSED_ON_MASK);
+        self.sr |= INTERRUPT_ON_MASK;
+        let handler_lsb = self.read(vector) as u16;
+        let handler_msb = self.read(vector + 1) as u16;
+        self.pc = handler_msb << 8 | handler_lsb;
+        self.elapse(7);
+    }
+
+    fn fetch(&mut self) -> u8 {
+        let opcode = self.read(self.pc);
+        if let Some(access) = self.accesses.last_mut().filter(|_| self.tracing) {
+            access.kind = AccessKind::Fetch;
+        }
+        opcode
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        // RDY stops read cycles only, writes go ahead
+        while self.cycle_accurate && !self.is_ready() {
+            self.clock_cycle();
+        }
+        let val = self.read_bus(address);
+        if self.tracing {
+            self.accesses.push(Access {
+                kind: AccessKind::Read,
+                address,
+                val,
+                old: None,
+            });
+        }
+        self.bus_cycle();
+        val
+    }
+
+    ///
+    /// read the real cpu makes and throws away, only made when cycle accurate
+    ///
+    fn dummy_read(&mut self, address: u16) {
+        if self.cycle_accurate {
+            self.read(address);
+        }
+    }
+
+    ///
+    /// the cycle a taken branch or a page crossing adds to an instruction:
+    /// a dummy read when cycle accurate, counted on top of the instruction
+    /// otherwise
+    ///
+    fn extra_cycle(&mut self, address: u16) {
+        if self.cycle_accurate {
+            self.read(address);
+        } else {
+            self.cycles += 1;
+        }
+    }
+
+    ///
+    /// write of the unmodified value read-modify-write instructions make
+    /// before the real one, only made when cycle accurate
+    ///
+    fn dummy_write(&mut self, address: u16, val: u8) {
+        if self.cycle_accurate {
+            self.write(address, val);
+        }
+    }
+
+    ///
+    /// the bus cycle of an access just made, devices see time pass
+    ///
+    fn bus_cycle(&mut self) {
+        if self.cycle_accurate {
+            self.clock_cycle();
+            self.sample_interrupts(self.sr & INTERRUPT_ON_MASK != 0);
+        }
+    }
+
+    ///
+    /// one cycle of the clock, with or without the cpu on the bus
+    ///
+    fn clock_cycle(&mut self) {
+        self.cycles += 1;
+        self.events.borrow_mut().set_now(self.cycles);
+        self.tick_devices(1);
+    }
+
+    fn tick_devices(&mut self, cycles: u32) {
+        for mapped in self.devices.iter_mut() {
+            mapped.device.tick(cycles);
+        }
+    }
+
+    ///
+    /// the cycles an instruction takes when bus cycles are not counted
+    ///
+    fn elapse(&mut self, cycles: u8) {
+        if !self.cycle_accurate {
+            self.cycles += cycles as u64;
+        }
+    }
+
+    fn read_bus(&mut self, address: u16) -> u8 {
+        let address = self.resolve(address);
+        if let Some((offset, device)) = self.device_at(address) {
+            return device.read(offset);
+        }
+        match self.region_at(address).kind {
+            RegionKind::Ram | RegionKind::Rom => self.mem[address as usize],
+            RegionKind::Io | RegionKind::Unmapped => {
+                self.violation(ViolationKind::UnmappedRead, address);
+                // nothing drives the data bus, the high byte of the address lingers
+                (address >> 8) as u8
+            }
+        }
+    }
+
+    fn write(&mut self, address: u16, val: u8) {
+        let old = self.write_bus(address, val);
+        if self.tracing {
+            self.accesses.push(Access {
+                kind: AccessKind::Write,
+                address,
+                val,
+                old,
+            });
+        }
+        self.bus_cycle();
+    }
+
+    ///
+    /// returns the RAM contents the write replaced
+    ///
+    fn write_bus(&mut self, address: u16, val: u8) -> Option<u8> {
+        let address = self.resolve(address);
+        if let Some((offset, device)) = self.device_at(address) {
+            device.write(offset, val);
+            return None;
+        }
+        match self.region_at(address).kind {
+            RegionKind::Ram => {
+                let old = self.mem[address as usize];
+                self.mem[address as usize] = val;
+                return Some(old);
+            }
+            RegionKind::Rom => self.violation(ViolationKind::RomWrite, address),
+            RegionKind::Io | RegionKind::Unmapped => {
+                self.violation(ViolationKind::UnmappedWrite, address)
+            }
+        }
+        None
+    }
+
+    fn region_at(&self, address: u16) -> Region {
+        self.regions
+            .iter()
+            .rev()
+            .find(|region| region.contains(address))
+            .copied()
+            .unwrap_or(Region::new(0x0000, 0xffff, RegionKind::Ram))
+    }
+
+    ///
+    /// only strict mode cares, the first violation is the one reported
+    ///
+    fn violation(&mut self, kind: ViolationKind, address: u16) {
+        if !self.strict || self.halt_reason.is_some() {
+            return;
+        }
+        self.halt(HaltReason::AccessViolation(AccessViolation {
+            kind,
+            address,
+            pc: self.instruction_pc,
+        }));
+    }
+
+    fn resolve(&self, address: u16) -> u16 {
+        self.mirrors
+            .iter()
+            .find(|mirror| mirror.start <= address && address <= mirror.end)
+            .map_or(address, |mirror| mirror.target.wrapping_add(address - mirror.start))
+    }
+
+    fn device_at(&mut self, address: u16) -> Option<(u16, &mut (dyn Device + 'static))> {
+        self.devices
+            .iter_mut()
+            .find(|mapped| mapped.decodes(address))
+            .map(|mapped| (address - mapped.start, mapped.device.as_mut()))
     }
 
     fn next_instruction(self: &mut Self, attr: &InsAttr) {
         self.pc += attr.len() as u16;
+        self.elapse(attr.cyc());
+    }
+
+    ///
+    /// continue at `address` instead of the next instruction
+    ///
+    fn jump(&mut self, attr: &InsAttr, address: u16) {
+        self.pc = address;
+        self.elapse(attr.cyc());
     }
 
     fn is_carried(self: &Self) -> u8 {
         self.sr & BIT_0_MASK
     }
+
+    fn push(&mut self, val: u8) {
+        self.write(STACK_PAGE | self.sp as u16, val);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    ///
+    /// the read of the stack top instructions pulling from it start with
+    ///
+    fn stack_dummy_read(&mut self) {
+        self.dummy_read(STACK_PAGE | self.sp as u16);
+    }
+
+    fn pull(&mut self) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        self.read(STACK_PAGE | self.sp as u16)
+    }
 }
 
 impl Default for Mos6502 {
@@ -64,7 +1198,677 @@ impl Default for Mos6502 {
             yr: 0,
             sr: 0,
             mem: [0; 64 * 1024],
+            cycles: 0,
+            irq: false,
+            nmi: false,
+            nmi_seen: false,
+            nmi_latched: false,
+            samples: [Poll::default(); 3],
+            poll: None,
+            early_poll: false,
+            ticked: 0,
+            not_ready: false,
+            so_low: false,
+            so_seen: false,
+            reset_held: false,
+            reset_seen: false,
             power_on: false,
+            halt_reason: None,
+            paravirt: None,
+            devices: Vec::new(),
+            mirrors: Vec::new(),
+            regions: Vec::new(),
+            strict: false,
+            cycle_accurate: false,
+            instruction_pc: 0,
+            traps: Vec::new(),
+            events: Rc::default(),
+            journal: Rc::default(),
+            throttle: None,
+            tracing: false,
+            accesses: Vec::new(),
+            history: None,
+            profiler: None,
+            coverage: None,
+            heatmap: None,
+            access_log: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Mos6502 {
+    ///
+    /// a cpu running NOPs through all of memory from $0000, time passes
+    /// with `run_until`
+    ///
+    pub(crate) fn nops() -> Self {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0000, &[0xea; 0x10000]);
+        cpu.set_registers(Registers::default());
+        cpu
+    }
+
+    ///
+    /// `nops` with `device` at `start`, its IRQ output left unconnected
+    ///
+    pub(crate) fn nops_with<T: Device + 'static>(start: u16, device: T) -> (Self, Rc<RefCell<T>>) {
+        let device = Rc::new(RefCell::new(device));
+        let mut cpu = Self::nops();
+        cpu.attach_wired(start, Box::new(device.clone()), InterruptLine::Disconnected);
+        (cpu, device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    use super::*;
+    use crate::device::Via;
+
+    #[test]
+    fn test_device_irq_vectors_to_handler() {
+        let mut cpu = Mos6502::default();
+        cpu.mem[0x0200..0x0300].fill(0xea); // nop
+        cpu.mem[IRQ_VECTOR as usize] = 0x00;
+        cpu.mem[IRQ_VECTOR as usize + 1] = 0x03;
+        cpu.pc = 0x0200;
+        cpu.sp = 0xff;
+
+        let via = Rc::new(RefCell::new(Via::default()));
+        cpu.attach(0x9000, Box::new(via.clone()));
+        // IER: enable T1, then start T1 with 9 cycles
+        cpu.write(0x900e, 0xc0);
+        cpu.write(0x9004, 9);
+        cpu.write(0x9005, 0);
+
+        // T1 fires in the last cycle of the fifth NOP, too late for it to
+        // see, the sixth still runs
+        for _ in 0..6 {
+            cpu.step();
+        }
+        assert_eq!(0x0206, cpu.pc);
+        cpu.step();
+        assert_eq!(0x0300, cpu.pc);
+        assert_eq!(INTERRUPT_ON_MASK, cpu.sr & INTERRUPT_ON_MASK);
+        assert_eq!(0x02, cpu.mem[0x01ff]);
+        assert_eq!(0x06, cpu.mem[0x01fe]);
+        assert_eq!(19, cpu.cycles());
+    }
+
+    ///
+    /// counts the cycles it sees and the writes made to it
+    ///
+    #[derive(Default)]
+    struct Probe {
+        ticks: u32,
+        writes: Vec<(u32, u8)>,
+    }
+
+    impl Device for Probe {
+        fn size(&self) -> u16 {
+            1
+        }
+
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.ticks as u8
+        }
+
+        fn write(&mut self, _offset: u16, val: u8) {
+            self.writes.push((self.ticks, val));
+        }
+
+        fn tick(&mut self, cycles: u32) {
+            self.ticks += cycles;
+        }
+    }
+
+    fn cycle_accurate(program: &[u8]) -> Mos6502 {
+        let mut cpu = Mos6502::default();
+        cpu.mem[0x0000..0x0300].fill(0x01);
+        cpu.load(0x0200, program);
+        cpu.pc = 0x0200;
+        cpu.sp = 0xff;
+        cpu.xr = 0x01;
+        cpu.yr = 0x01;
+        cpu.set_cycle_accurate(true);
+        cpu.set_tracing(true);
+        cpu
+    }
+
+    #[test]
+    fn test_every_cycle_is_a_bus_access() {
+        for opcode in 0..=0xffu8 {
+            // branches depend on the flags, undocumented opcodes are not modelled
+            if opcode & 0x1f == 0x10 || disasm::length(opcode).is_none() {
+                continue;
+            }
+            let program = [opcode, 0x10, 0x02];
+            let mut accurate = cycle_accurate(&program);
+            accurate.step();
+            let mut timed = cycle_accurate(&program);
+            timed.set_cycle_accurate(false);
+            timed.step();
+            assert_eq!(
+                (timed.cycles(), accurate.cycles()),
+                (accurate.accesses().len() as u64, timed.cycles()),
+                "opcode {:02x}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn test_page_crossings_and_taken_branches_cost_a_cycle() {
+        // lda $02ff,x reads $0200 before $0300
+        let mut cpu = cycle_accurate(&[0xbd, 0xff, 0x02]);
+        cpu.step();
+        let addresses: Vec<u16> = cpu.accesses().iter().map(|access| access.address).collect();
+        assert_eq!(vec![0x0200, 0x0201, 0x0202, 0x0200, 0x0300], addresses);
+        // bne back across the page start
+        let mut cpu = cycle_accurate(&[0xd0, 0xfc]);
+        cpu.step();
+        assert_eq!((0x01fe, 4), (cpu.pc, cpu.cycles()));
+    }
+
+    #[test]
+    fn test_devices_see_double_writes_between_cycles() {
+        // inc $9000, lda $9000
+        let mut cpu = cycle_accurate(&[0xee, 0x00, 0x90, 0xad, 0x00, 0x90]);
+        let probe = Rc::new(RefCell::new(Probe::default()));
+        cpu.attach(0x9000, Box::new(probe.clone()));
+        cpu.step();
+        // read in cycle 4 after three ticks, written back in cycles 5 and 6
+        assert_eq!(vec![(4, 3), (5, 4)], probe.borrow().writes);
+        cpu.step();
+        assert_eq!(6 + 3, cpu.ac);
+    }
+
+    #[test]
+    fn test_masked_irq_is_ignored() {
+        let mut cpu = Mos6502::default();
+        cpu.mem[0x0200] = 0xea;
+        cpu.pc = 0x0200;
+        cpu.sr = INTERRUPT_ON_MASK;
+        cpu.set_irq(true);
+        cpu.step();
+        assert_eq!(0x0201, cpu.pc);
+    }
+
+    ///
+    /// pulls RDY low for three cycles, as many cycles after it is written
+    /// as the value written
+    ///
+    #[derive(Default)]
+    struct Dma {
+        countdown: u32,
+        held: u32,
+    }
+
+    impl Device for Dma {
+        fn size(&self) -> u16 {
+            1
+        }
+
+        fn read(&mut self, _offset: u16) -> u8 {
+            0
+        }
+
+        fn write(&mut self, _offset: u16, val: u8) {
+            self.countdown = val as u32;
+        }
+
+        fn tick(&mut self, cycles: u32) {
+            for _ in 0..cycles {
+                if self.held > 0 {
+                    self.held -= 1;
+                } else if self.countdown > 0 {
+                    self.countdown -= 1;
+                    self.held = if self.countdown == 0 { 3 } else { 0 };
+                }
+            }
+        }
+
+        fn irq(&self) -> bool {
+            self.held > 0
+        }
+    }
+
+    #[test]
+    fn test_rdy_stalls_read_cycles() {
+        // sta $c000, lda $0300
+        let mut cpu = cycle_accurate(&[0x8d, 0x00, 0xc0, 0xad, 0x00, 0x03]);
+        cpu.attach_wired(0xc000, Box::<Dma>::default(), InterruptLine::Ready);
+        cpu.ac = 2;
+        cpu.mem[0x0300] = 0x42;
+        cpu.set_rdy(false);
+        cpu.step();
+        cpu.step();
+        assert_eq!((0x0200, 2), (cpu.pc, cpu.cycles()));
+        cpu.set_rdy(true);
+        cpu.step();
+        cpu.step();
+        // the dma pulls RDY low after the opcode fetch
+        assert_eq!((0x42, 2 + 4 + 3 + 4), (cpu.ac, cpu.cycles()));
+        assert_eq!(4, cpu.accesses().len());
+    }
+
+    #[test]
+    fn test_so_falling_edge_sets_overflow() {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &[0xb8, 0xea]); // clv, nop
+        cpu.set_so(false);
+        assert_eq!(OVERFLOW_ON_MASK, cpu.sr & OVERFLOW_ON_MASK);
+        cpu.step();
+        cpu.step();
+        assert_eq!(0, cpu.sr & OVERFLOW_ON_MASK);
+        cpu.set_so(true);
+        cpu.set_so(false);
+        assert_eq!(OVERFLOW_ON_MASK, cpu.sr & OVERFLOW_ON_MASK);
+    }
+
+    #[test]
+    fn test_reset_runs_when_released() {
+        let mut cpu = cycle_accurate(&[0xea]);
+        cpu.mem[RESET_VECTOR as usize..RESET_VECTOR as usize + 2].copy_from_slice(&[0x00, 0x03]);
+        cpu.set_reset(true);
+        cpu.step();
+        cpu.step();
+        assert_eq!((0x0200, 2), (cpu.pc, cpu.cycles()));
+        cpu.set_reset(false);
+        cpu.step();
+        assert_eq!((0x0300, 0xfc, 9), (cpu.pc, cpu.sp, cpu.cycles()));
+        assert_eq!(INTERRUPT_ON_MASK, cpu.sr & INTERRUPT_ON_MASK);
+        let addresses: Vec<u16> = cpu.accesses().iter().map(|access| access.address).collect();
+        assert_eq!(
+            vec![0x0200, 0x0200, 0x01ff, 0x01fe, 0x01fd, 0xfffc, 0xfffd],
+            addresses
+        );
+    }
+
+    ///
+    /// asserts its output from the given cycle on
+    ///
+    struct Pulse {
+        at: u32,
+        ticks: u32,
+    }
+
+    impl Device for Pulse {
+        fn size(&self) -> u16 {
+            1
+        }
+
+        fn read(&mut self, _offset: u16) -> u8 {
+            0
+        }
+
+        fn write(&mut self, _offset: u16, _val: u8) {}
+
+        fn tick(&mut self, cycles: u32) {
+            self.ticks += cycles;
+        }
+
+        fn irq(&self) -> bool {
+            self.ticks >= self.at
+        }
+    }
+
+    ///
+    /// IRQ and BRK go to $0380, NMI to $0390, both run NOPs. `line` is
+    /// asserted from cycle `at` of the first step on.
+    ///
+    fn interrupted(program: &[u8], accurate: bool, line: InterruptLine, at: u32) -> Mos6502 {
+        let mut cpu = cycle_accurate(program);
+        cpu.set_cycle_accurate(accurate);
+        cpu.sr = 0;
+        cpu.mem[0x0380..0x03a0].fill(0xea);
+        cpu.mem[NMI_VECTOR as usize..NMI_VECTOR as usize + 2].copy_from_slice(&[0x90, 0x03]);
+        cpu.mem[IRQ_VECTOR as usize..IRQ_VECTOR as usize + 2].copy_from_slice(&[0x80, 0x03]);
+        cpu.attach_wired(0xc000, Box::new(Pulse { at, ticks: 0 }), line);
+        cpu
+    }
+
+    ///
+    /// return address and status the last interrupt pushed
+    ///
+    fn stacked(cpu: &Mos6502) -> (u16, u8) {
+        let at = |n: u8| cpu.mem[(STACK_PAGE | cpu.sp.wrapping_add(n) as u16) as usize];
+        ((at(3) as u16) << 8 | at(2) as u16, at(1))
+    }
+
+    // the cases below follow blargg's cpu_interrupts_v2 test roms
+
+    #[test]
+    fn test_cli_sei_and_plp_change_the_mask_late() {
+        for accurate in [false, true] {
+            // cli, nop: the IRQ waits for the nop
+            let mut cpu = interrupted(&[0x58, 0xea], accurate, InterruptLine::Disconnected, 0);
+            cpu.sr = INTERRUPT_ON_MASK;
+            cpu.set_irq(true);
+            cpu.step();
+            cpu.step();
+            cpu.step();
+            assert_eq!((0x0380, 0x0202), (cpu.pc, stacked(&cpu).0));
+
+            // plp of a clear I flag, nop
+            let mut cpu = interrupted(&[0x28, 0xea], accurate, InterruptLine::Disconnected, 0);
+            cpu.sr = INTERRUPT_ON_MASK;
+            cpu.set_irq(true);
+            cpu.step();
+            assert_eq!(0x0201, cpu.pc);
+            cpu.step();
+            cpu.step();
+            assert_eq!((0x0380, 0x0202), (cpu.pc, stacked(&cpu).0));
+
+            // sei: an IRQ already seen is taken, with I set on the stack
+            let mut cpu = interrupted(&[0x78, 0xea], accurate, InterruptLine::Irq, 1);
+            cpu.step();
+            cpu.step();
+            let (address, status) = stacked(&cpu);
+            assert_eq!((0x0380, 0x0201), (cpu.pc, address), "accurate {}", accurate);
+            assert_eq!(INTERRUPT_ON_MASK, status & INTERRUPT_ON_MASK);
+        }
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        for accurate in [false, true] {
+            // seen by the fourth cycle, BRK pushes B and goes to the NMI handler
+            let mut cpu = interrupted(&[0x00, 0x00], accurate, InterruptLine::Nmi, 4);
+            cpu.step();
+            let (address, status) = stacked(&cpu);
+            assert_eq!((0x0390, 0x0202), (cpu.pc, address));
+            assert_eq!(BREAK_ON_MASK, status & BREAK_ON_MASK);
+            cpu.step();
+            cpu.step();
+            assert_eq!(0x0392, cpu.pc, "the NMI is served once");
+
+            // a cycle later the BRK handler runs an instruction first
+            let mut cpu = interrupted(&[0x00, 0x00], accurate, InterruptLine::Nmi, 5);
+            cpu.step();
+            assert_eq!(0x0380, cpu.pc);
+            cpu.step();
+            cpu.step();
+            assert_eq!((0x0390, 0x0381), (cpu.pc, stacked(&cpu).0));
+        }
+    }
+
+    #[test]
+    fn test_nmi_hijacks_irq() {
+        for accurate in [false, true] {
+            let mut cpu = interrupted(&[0xea], accurate, InterruptLine::Nmi, 4);
+            cpu.set_irq(true);
+            cpu.step();
+            let (address, status) = stacked(&cpu);
+            assert_eq!((0x0390, 0x0200), (cpu.pc, address));
+            assert_eq!(0, status & BREAK_ON_MASK);
+
+            let mut cpu = interrupted(&[0xea], accurate, InterruptLine::Nmi, 5);
+            cpu.set_irq(true);
+            cpu.step();
+            cpu.step();
+            cpu.step();
+            assert_eq!((0x0390, 0x0381), (cpu.pc, stacked(&cpu).0));
+        }
+    }
+
+    #[test]
+    fn test_taken_branch_delays_irq() {
+        for accurate in [false, true] {
+            // bne to the next instruction: an IRQ in its second cycle waits
+            // for the nop after it
+            let mut cpu = interrupted(&[0xd0, 0x00, 0xea], accurate, InterruptLine::Irq, 2);
+            cpu.step();
+            cpu.step();
+            cpu.step();
+            assert_eq!((0x0380, 0x0203), (cpu.pc, stacked(&cpu).0));
+
+            // crossing a page the branch sees it in time
+            let mut cpu = interrupted(&[], accurate, InterruptLine::Irq, 1);
+            cpu.load(0x02fd, &[0xd0, 0x02]);
+            cpu.step();
+            cpu.step();
+            assert_eq!((0x0380, 0x0301), (cpu.pc, stacked(&cpu).0));
+        }
+    }
+
+    const FEEDBACK_PORT: u16 = 0xbffc;
+    const FEEDBACK_FILTER: u8 = 0x7f;
+
+    ///
+    /// the feedback port of Klaus Dormann's 6502_interrupt_test as it is
+    /// configured by default: open collector at $bffc, a set bit 0 pulls IRQ
+    /// and a set bit 1 NMI. The register is attached once for each line.
+    ///
+    struct Feedback {
+        port: Rc<Cell<u8>>,
+        bit: u8,
+    }
+
+    impl Device for Feedback {
+        fn size(&self) -> u16 {
+            // only the IRQ side answers on the bus
+            (self.bit == 0) as u16
+        }
+
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.port.get()
+        }
+
+        fn write(&mut self, _offset: u16, val: u8) {
+            self.port.set(val & FEEDBACK_FILTER);
+        }
+
+        fn irq(&self) -> bool {
+            self.port.get() & 1 << self.bit != 0
+        }
+    }
+
+    ///
+    /// run an interrupt test image from $0400 until it traps in a jump or
+    /// branch to itself, which is where the test ended up
+    ///
+    fn run_interrupt_test(image: &[u8], accurate: bool) -> (Option<u16>, Mos6502) {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0000, image);
+        cpu.set_cycle_accurate(accurate);
+        cpu.set_registers(Registers {
+            pc: 0x0400,
+            sp: 0xff,
+            ..Registers::default()
+        });
+        let port = Rc::new(Cell::new(0));
+        for (bit, line) in [(0, InterruptLine::Irq), (1, InterruptLine::Nmi)] {
+            let feedback = Feedback {
+                port: port.clone(),
+                bit,
+            };
+            cpu.attach_wired(FEEDBACK_PORT, Box::new(feedback), line);
+        }
+        while cpu.cycles() < 100_000_000 {
+            let pc = cpu.pc;
+            cpu.step();
+            if cpu.pc == pc {
+                return (Some(pc), cpu);
+            }
+        }
+        (None, cpu)
+    }
+
+    #[test]
+    fn test_feedback_port_drives_both_lines() {
+        let mut image = vec![0; 0x10000];
+        let program = [
+            0xa9, 0x01, 0x8d, 0xfc, 0xbf, 0xea, // lda #$01, sta $bffc, nop
+            0xa9, 0x02, 0x8d, 0xfc, 0xbf, 0xea, // lda #$02, sta $bffc, nop
+            0x4c, 0x0c, 0x04, // jmp *
+        ];
+        image[0x0400..0x0400 + program.len()].copy_from_slice(&program);
+        // IRQ: lda #$00, sta $bffc, inc $10, rti; NMI: inc $11, rti
+        image[0x0500..0x0508].copy_from_slice(&[0xa9, 0x00, 0x8d, 0xfc, 0xbf, 0xe6, 0x10, 0x40]);
+        image[0x0510..0x0513].copy_from_slice(&[0xe6, 0x11, 0x40]);
+        image[0xfffa..].copy_from_slice(&[0x10, 0x05, 0x00, 0x00, 0x00, 0x05]);
+        for accurate in [false, true] {
+            let (trap, cpu) = run_interrupt_test(&image, accurate);
+            assert_eq!(Some(0x040c), trap, "accurate {}", accurate);
+            assert_eq!([1, 1], cpu.mem[0x10..0x12]);
+        }
+    }
+
+    ///
+    /// Klaus Dormann's 6502_interrupt_test assembled with its default
+    /// configuration into a 64 KiB image: INTERRUPT_TEST names the image and
+    /// INTERRUPT_TEST_SUCCESS the address of the success trap in the listing.
+    /// The interrupt test workflow assembles it and runs this.
+    ///
+    #[test]
+    #[ignore = "needs the assembled 6502_interrupt_test, see INTERRUPT_TEST"]
+    fn test_interrupt_test_rom() {
+        let path = std::env::var("INTERRUPT_TEST").expect("INTERRUPT_TEST is not set");
+        let image = std::fs::read(path).unwrap();
+        assert_eq!(0x10000, image.len(), "the image has to cover the whole bus");
+        let success =
+            std::env::var("INTERRUPT_TEST_SUCCESS").expect("INTERRUPT_TEST_SUCCESS is not set");
+        let success = u16::from_str_radix(success.trim_start_matches('$'), 16).unwrap();
+        for accurate in [false, true] {
+            let (trap, _) = run_interrupt_test(&image, accurate);
+            assert_eq!(Some(success), trap, "accurate {}", accurate);
+        }
+    }
+
+    ///
+    /// keys the host typed, handed out through the journal
+    ///
+    struct Keys {
+        typed: Rc<RefCell<std::collections::VecDeque<u8>>>,
+        scheduler: Option<Scheduler>,
+    }
+
+    impl Device for Keys {
+        fn size(&self) -> u16 {
+            1
         }
+
+        fn read(&mut self, _offset: u16) -> u8 {
+            let typed = &self.typed;
+            let scheduler = self.scheduler.as_ref().unwrap();
+            scheduler
+                .input(|| typed.borrow_mut().pop_front())
+                .unwrap_or(0)
+        }
+
+        fn write(&mut self, _offset: u16, _val: u8) {}
+
+        fn connect(&mut self, scheduler: Scheduler) {
+            self.scheduler = Some(scheduler);
+        }
+    }
+
+    ///
+    /// stores every key at $0300,x and counts IRQs at $10
+    ///
+    fn typing() -> (Mos6502, Rc<RefCell<std::collections::VecDeque<u8>>>) {
+        let mut cpu = Mos6502::default();
+        // lda $c000, beq *-3, sta $0300,x, inx, jmp $0200
+        cpu.load(
+            0x0200,
+            &[
+                0xad, 0x00, 0xc0, 0xf0, 0xfb, 0x9d, 0x00, 0x03, 0xe8, 0x4c, 0x00, 0x02,
+            ],
+        );
+        cpu.load(0x0400, &[0xe6, 0x10, 0x40]); // inc $10, rti
+        cpu.load(IRQ_VECTOR, &[0x00, 0x04]);
+        cpu.set_registers(Registers {
+            pc: 0x0200,
+            sp: 0xff,
+            ..Registers::default()
+        });
+        let typed = Rc::new(RefCell::new(std::collections::VecDeque::new()));
+        cpu.attach(
+            0xc000,
+            Box::new(Keys {
+                typed: typed.clone(),
+                scheduler: None,
+            }),
+        );
+        (cpu, typed)
+    }
+
+    #[derive(Clone, Default)]
+    struct Sink(Rc<RefCell<Vec<u8>>>);
+
+    impl std::io::Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    ///
+    /// two keys and an IRQ pulse from the host, the recording as written
+    /// and the snapshot at the end
+    ///
+    fn record_typing() -> (Recording, Vec<u8>) {
+        let (mut cpu, typed) = typing();
+        let sink = Sink::default();
+        cpu.record(100, Some(Box::new(sink.clone())));
+        cpu.run_until(300);
+        typed.borrow_mut().push_back(b'a');
+        cpu.run_until(600);
+        typed.borrow_mut().push_back(b'b');
+        cpu.set_irq(true);
+        cpu.run_until(650);
+        cpu.set_irq(false);
+        cpu.run_until(1000);
+        let recorded = cpu.finish_journal().unwrap().unwrap();
+        let written = Recording::read(sink.0.borrow().as_slice()).unwrap();
+        assert_eq!(recorded, written);
+        assert_eq!(4, written.entries.len());
+        (written, cpu.snapshot())
+    }
+
+    #[test]
+    fn test_replay_repeats_the_recorded_run() {
+        let (recording, expected) = record_typing();
+        let (mut cpu, _) = typing();
+        cpu.replay(recording);
+        cpu.run_until(1000);
+        assert_eq!(None, cpu.halt_reason());
+        assert_eq!(b"ab", &cpu.mem[0x0300..0x0302]);
+        assert_ne!(0, cpu.mem[0x10]);
+        assert!(expected == cpu.snapshot());
+    }
+
+    #[test]
+    fn test_replay_ignores_the_host() {
+        let (recording, expected) = record_typing();
+        let (mut cpu, typed) = typing();
+        cpu.replay(recording);
+        typed.borrow_mut().push_back(b'x');
+        cpu.set_irq(true);
+        cpu.run_until(1000);
+        assert!(expected == cpu.snapshot());
+        assert!(cpu.finish_journal().is_none(), "nothing was recorded");
+    }
+
+    #[test]
+    fn test_replay_halts_where_the_state_differs() {
+        let (mut recording, _) = record_typing();
+        recording.checkpoints[3].1 ^= 1;
+        let cycle = recording.checkpoints[3].0;
+        let (mut cpu, _) = typing();
+        cpu.replay(recording);
+        cpu.run_until(1000);
+        assert_eq!(Some(HaltReason::Diverged(cycle)), cpu.halt_reason());
+        assert_eq!(cycle, cpu.cycles());
     }
 }
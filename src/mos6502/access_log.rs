@@ -0,0 +1,163 @@
+///
+/// bounded log of every bus access, the oldest entries make room for new
+/// ones. Each entry carries the pc of the instruction or interrupt that
+/// made it and a cycle: that of the access itself on a cycle accurate cpu,
+/// where every access is one cycle, otherwise the cycle the instruction
+/// started on.
+///
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+};
+
+use super::{Access, AccessKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoggedAccess {
+    pub cycle: u64,
+    pub pc: u16,
+    pub access: Access,
+}
+
+pub struct AccessLog {
+    capacity: usize,
+    entries: VecDeque<LoggedAccess>,
+    dropped: u64,     // entries that no longer fit
+    per_access: bool, // cycles are those of the accesses, not of the instructions
+}
+
+impl AccessLog {
+    pub fn new(capacity: usize, per_access: bool) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+            dropped: 0,
+            per_access,
+        }
+    }
+
+    ///
+    /// the accesses of the instruction at `pc` that started on `cycle`
+    ///
+    pub(super) fn end(&mut self, accesses: &[Access], pc: u16, cycle: u64) {
+        for (index, access) in accesses.iter().enumerate() {
+            if self.capacity == 0 {
+                self.dropped += 1;
+                continue;
+            }
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+                self.dropped += 1;
+            }
+            let cycle = match self.per_access {
+                true => cycle + index as u64,
+                false => cycle,
+            };
+            self.entries.push_back(LoggedAccess {
+                cycle,
+                pc,
+                access: *access,
+            });
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &LoggedAccess> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    ///
+    /// `cycle,pc,address,value,access` oldest first, the access R, W or X
+    /// for an opcode fetch. The first column is `instruction_cycle` when
+    /// the accesses were not timed on their own.
+    ///
+    pub fn write_csv(&self, out: &mut dyn Write) -> io::Result<()> {
+        let cycle = match self.per_access {
+            true => "cycle",
+            false => "instruction_cycle",
+        };
+        writeln!(out, "{},pc,address,value,access", cycle)?;
+        for entry in self.entries.iter() {
+            let kind = match entry.access.kind {
+                AccessKind::Fetch => 'X',
+                AccessKind::Read => 'R',
+                AccessKind::Write => 'W',
+            };
+            writeln!(
+                out,
+                "{},{:04x},{:04x},{:02x},{}",
+                entry.cycle, entry.pc, entry.access.address, entry.access.val, kind
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{Mos6502, Registers};
+
+    fn logged(capacity: usize, cycle_accurate: bool) -> Mos6502 {
+        // lda $10, sta $11
+        let mut cpu = Mos6502::default();
+        cpu.set_cycle_accurate(cycle_accurate);
+        cpu.load(0x0200, &[0xa5, 0x10, 0x85, 0x11]);
+        cpu.load(0x0010, &[0x42]);
+        cpu.set_registers(Registers {
+            pc: 0x0200,
+            ..Registers::default()
+        });
+        cpu.log_accesses(capacity);
+        cpu.step();
+        cpu.step();
+        cpu
+    }
+
+    #[test]
+    fn test_csv() {
+        let cpu = logged(100, false);
+        let mut csv = Vec::new();
+        cpu.access_log().unwrap().write_csv(&mut csv).unwrap();
+        assert_eq!(
+            "instruction_cycle,pc,address,value,access\n\
+             0,0200,0200,a5,X\n0,0200,0201,10,R\n0,0200,0010,42,R\n\
+             3,0202,0202,85,X\n3,0202,0203,11,R\n3,0202,0011,42,W\n",
+            String::from_utf8(csv).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cycle_accurate_accesses_have_their_own_cycle() {
+        let cpu = logged(100, true);
+        let cycles: Vec<u64> = cpu
+            .access_log()
+            .unwrap()
+            .entries()
+            .map(|entry| entry.cycle)
+            .collect();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], cycles);
+        let mut csv = Vec::new();
+        cpu.access_log().unwrap().write_csv(&mut csv).unwrap();
+        assert!(csv.starts_with(b"cycle,pc,"));
+    }
+
+    #[test]
+    fn test_oldest_entries_are_dropped() {
+        let cpu = logged(2, false);
+        let log = cpu.access_log().unwrap();
+        assert_eq!((2, 4), (log.len(), log.dropped()));
+        let last: Vec<u16> = log.entries().map(|entry| entry.access.address).collect();
+        assert_eq!(vec![0x0203, 0x0011], last);
+    }
+}
@@ -1,112 +1,145 @@
 use super::Mos6502;
 
-pub type AddressModeFn = fn(&Mos6502) -> u8;
+pub type AddressModeFn = fn(&mut Mos6502) -> u8;
 
-pub type AddressModeImmutableFn = fn(&mut Mos6502) -> &mut u8;
+///
+/// resolve the effective address of an operand that is written back to memory
+///
+pub type AddressModeImmutableFn = fn(&mut Mos6502) -> u16;
 
-pub fn immediate(cpu: &Mos6502) -> u8 {
+pub fn immediate(cpu: &mut Mos6502) -> u8 {
     next_nth_byte_from_pc(cpu, 1)
 }
 
-pub fn zero_page(cpu: &Mos6502) -> u8 {
-    let address = next_nth_byte_from_pc(cpu, 1) as usize;
-    cpu.mem[address]
+pub fn zero_page(cpu: &mut Mos6502) -> u8 {
+    let address = zero_page_immutatble(cpu);
+    cpu.read(address)
 }
 
-pub fn zero_page_immutatble(cpu: &mut Mos6502) -> &mut u8 {
-    let address = next_nth_byte_from_pc(cpu, 1) as usize;
-    &mut cpu.mem[address]
+pub fn zero_page_immutatble(cpu: &mut Mos6502) -> u16 {
+    next_nth_byte_from_pc(cpu, 1) as u16
+}
+
+pub fn zero_page_x(cpu: &mut Mos6502) -> u8 {
+    let address = zero_page_x_immutable(cpu);
+    cpu.read(address)
+}
+
+pub fn zero_page_y(cpu: &mut Mos6502) -> u8 {
+    let address = zero_page_y_immutable(cpu);
+    cpu.read(address)
 }
 
 #[allow(arithmetic_overflow)]
-pub fn zero_page_x(cpu: &Mos6502) -> u8 {
+pub fn zero_page_x_immutable(cpu: &mut Mos6502) -> u16 {
     let address: u8 = next_nth_byte_from_pc(cpu, 1);
+    cpu.dummy_read(address as u16);
     let effective_address: u8 = address + cpu.xr;
-    cpu.mem[effective_address as usize]
+    effective_address as u16
 }
 
 #[allow(arithmetic_overflow)]
-pub fn zero_page_y(cpu: &Mos6502) -> u8 {
+pub fn zero_page_y_immutable(cpu: &mut Mos6502) -> u16 {
     let address: u8 = next_nth_byte_from_pc(cpu, 1);
+    cpu.dummy_read(address as u16);
     let effective_address: u8 = address + cpu.yr;
-    cpu.mem[effective_address as usize]
+    effective_address as u16
 }
 
-#[allow(arithmetic_overflow)]
-pub fn zero_page_x_immutable(cpu: &mut Mos6502) -> &mut u8 {
-    let address: u8 = next_nth_byte_from_pc(cpu, 1);
-    let effective_address: u8 = address + cpu.xr;
-    &mut cpu.mem[effective_address as usize]
+pub fn absolute(cpu: &mut Mos6502) -> u8 {
+    let address = absolute_immutable(cpu);
+    cpu.read(address)
 }
 
-pub fn absolute(cpu: &Mos6502) -> u8 {
+pub fn absolute_immutable(cpu: &mut Mos6502) -> u16 {
     let address_lsb = next_nth_byte_from_pc(cpu, 1) as u16;
     let address_msb = next_nth_byte_from_pc(cpu, 2) as u16;
-    let address = (address_msb << 8 | address_lsb) as usize;
-    cpu.mem[address]
+    address_msb << 8 | address_lsb
 }
 
-pub fn absolute_immutable(cpu: &mut Mos6502) -> &mut u8 {
-    let address_lsb = next_nth_byte_from_pc(cpu, 1) as u16;
-    let address_msb = next_nth_byte_from_pc(cpu, 2) as u16;
-    let address = (address_msb << 8 | address_lsb) as usize;
-    &mut cpu.mem[address]
+pub fn absolute_x(cpu: &mut Mos6502) -> u8 {
+    let base = absolute_immutable(cpu);
+    let address = indexed(cpu, base, cpu.xr, false);
+    cpu.read(address)
 }
 
-pub fn absolute_x(cpu: &Mos6502) -> u8 {
-    let address_lsb = next_nth_byte_from_pc(cpu, 1) as u16;
-    let address_msb = next_nth_byte_from_pc(cpu, 2) as u16;
-    let address = address_msb << 8 | address_lsb;
-    let effective_address = address + cpu.xr as u16;
-    cpu.mem[effective_address as usize]
+pub fn absolute_x_immutable(cpu: &mut Mos6502) -> u16 {
+    let base = absolute_immutable(cpu);
+    indexed(cpu, base, cpu.xr, true)
 }
 
-pub fn absolute_x_immutable(cpu: &mut Mos6502) -> &mut u8 {
-    let address_lsb = next_nth_byte_from_pc(cpu, 1) as u16;
-    let address_msb = next_nth_byte_from_pc(cpu, 2) as u16;
-    let address = address_msb << 8 | address_lsb;
-    let effective_address = address + cpu.xr as u16;
-    &mut cpu.mem[effective_address as usize]
+pub fn absolute_y(cpu: &mut Mos6502) -> u8 {
+    let base = absolute_immutable(cpu);
+    let address = indexed(cpu, base, cpu.yr, false);
+    cpu.read(address)
 }
 
-pub fn absolute_y(cpu: &Mos6502) -> u8 {
-    let address_lsb = next_nth_byte_from_pc(cpu, 1) as u16;
-    let address_msb = next_nth_byte_from_pc(cpu, 2) as u16;
-    let address = address_msb << 8 | address_lsb;
-    let effective_address = address + cpu.yr as u16;
-    cpu.mem[effective_address as usize]
+pub fn absolute_y_immutable(cpu: &mut Mos6502) -> u16 {
+    let base = absolute_immutable(cpu);
+    indexed(cpu, base, cpu.yr, true)
+}
+
+pub fn indirect_x(cpu: &mut Mos6502) -> u8 {
+    let address = indirect_x_immutable(cpu);
+    cpu.read(address)
 }
 
 #[allow(arithmetic_overflow)]
-pub fn indirect_x(cpu: &Mos6502) -> u8 {
+pub fn indirect_x_immutable(cpu: &mut Mos6502) -> u16 {
     let table: u8 = next_nth_byte_from_pc(cpu, 1);
+    cpu.dummy_read(table as u16);
     let record_first_byte: u8 = table + cpu.xr;
     let record_second_byte: u8 = record_first_byte + 1;
-    let address_lsb = cpu.mem[record_first_byte as usize] as u16;
-    let address_msb = cpu.mem[record_second_byte as usize] as u16;
-    let effective_address = (address_msb << 8 | address_lsb) as usize;
-    cpu.mem[effective_address]
+    let address_lsb = cpu.read(record_first_byte as u16) as u16;
+    let address_msb = cpu.read(record_second_byte as u16) as u16;
+    address_msb << 8 | address_lsb
+}
+
+pub fn indirect_y(cpu: &mut Mos6502) -> u8 {
+    let base = indirect_y_base(cpu);
+    let address = indexed(cpu, base, cpu.yr, false);
+    cpu.read(address)
+}
+
+pub fn indirect_y_immutable(cpu: &mut Mos6502) -> u16 {
+    let base = indirect_y_base(cpu);
+    indexed(cpu, base, cpu.yr, true)
 }
 
 #[allow(arithmetic_overflow)]
-pub fn indirect_y(cpu: &Mos6502) -> u8 {
+fn indirect_y_base(cpu: &mut Mos6502) -> u16 {
     let indirect_position: u8 = next_nth_byte_from_pc(cpu, 1);
     let indirect_position_next: u8 = indirect_position + 1;
-    let address_lsb = cpu.mem[indirect_position as usize] as u16;
-    let address_msb = cpu.mem[indirect_position_next as usize] as u16;
-    let address = address_msb << 8 | address_lsb;
-    let effective_address = address + cpu.yr as u16;
-    cpu.mem[effective_address as usize]
+    let address_lsb = cpu.read(indirect_position as u16) as u16;
+    let address_msb = cpu.read(indirect_position_next as u16) as u16;
+    address_msb << 8 | address_lsb
+}
+
+///
+/// `base` plus `index`. The real cpu first reads the address before the
+/// carry into the high byte, always for a write, on a page crossing for
+/// a read, which costs the extra cycle.
+///
+fn indexed(cpu: &mut Mos6502, base: u16, index: u8, writing: bool) -> u16 {
+    let address = base.wrapping_add(index as u16);
+    let uncarried = base & 0xff00 | address & 0x00ff;
+    if writing {
+        // already part of the cycles of the instruction
+        cpu.dummy_read(uncarried);
+    } else if address & 0xff00 != base & 0xff00 {
+        cpu.extra_cycle(uncarried);
+    }
+    address
 }
 
 #[allow(arithmetic_overflow)]
-pub fn relative(cpu: &Mos6502) -> u16 {
+pub fn relative(cpu: &mut Mos6502) -> u16 {
     return next_nth_byte_from_pc(cpu, 1) as i8 as u16;
 }
 
-fn next_nth_byte_from_pc(cpu: &Mos6502, nth: u16) -> u8 {
+fn next_nth_byte_from_pc(cpu: &mut Mos6502, nth: u16) -> u8 {
     let nth_byte = cpu.pc + nth;
-    cpu.mem[nth_byte as usize]
+    cpu.read(nth_byte)
 }
 
 #[cfg(test)]
@@ -118,7 +151,7 @@ mod tests {
     fn test_immediate() {
         let mut cpu = Mos6502::default();
         cpu.mem[cpu.pc as usize + 1] = 0xaa;
-        let actual = immediate(&cpu);
+        let actual = immediate(&mut cpu);
         assert_eq!(0xaa, actual);
     }
 
@@ -128,7 +161,7 @@ mod tests {
         cpu.pc = 30;
         cpu.mem[31] = 0xaa;
         cpu.mem[0xaa] = 0x12;
-        let actual = zero_page(&cpu);
+        let actual = zero_page(&mut cpu);
         assert_eq!(0x12, actual);
     }
 
@@ -139,7 +172,7 @@ mod tests {
         cpu.mem[31] = 0xaa;
         cpu.xr = 0x1;
         cpu.mem[0xaa + 0x1] = 0x12;
-        let actual = zero_page_x(&cpu);
+        let actual = zero_page_x(&mut cpu);
         assert_eq!(0x12, actual);
     }
 
@@ -149,7 +182,7 @@ mod tests {
         cpu.mem[cpu.pc as usize + 1] = 0x30;
         cpu.mem[2] = 0x10;
         cpu.mem[0x1030] = 0xaa;
-        let actual = absolute(&cpu);
+        let actual = absolute(&mut cpu);
         assert_eq!(0xaa, actual)
     }
 
@@ -160,7 +193,7 @@ mod tests {
         cpu.mem[2] = 0x10;
         cpu.xr = 0x12;
         cpu.mem[0x1030 + 0x12] = 0xaa;
-        let actual = absolute_x(&cpu);
+        let actual = absolute_x(&mut cpu);
         assert_eq!(0xaa, actual)
     }
 
@@ -171,7 +204,7 @@ mod tests {
         cpu.mem[2] = 0x10;
         cpu.yr = 0x12;
         cpu.mem[0x1030 + 0x12] = 0xaa;
-        let actual = absolute_y(&cpu);
+        let actual = absolute_y(&mut cpu);
         assert_eq!(0xaa, actual)
     }
 
@@ -183,7 +216,7 @@ mod tests {
         cpu.mem[0x70 + 0x10] = 0x20;
         cpu.mem[0x70 + 0x10 + 0x1] = 0x10;
         cpu.mem[0x1020] = 0xaa;
-        let actual = indirect_x(&cpu);
+        let actual = indirect_x(&mut cpu);
         assert_eq!(0xaa, actual)
     }
 
@@ -195,7 +228,7 @@ mod tests {
         cpu.mem[0x70] = 0x20;
         cpu.mem[0x71] = 0x10;
         cpu.mem[0x1020 + 0x10] = 0xaa;
-        let actual = indirect_y(&cpu);
+        let actual = indirect_y(&mut cpu);
         assert_eq!(0xaa, actual)
     }
 
@@ -206,9 +239,9 @@ mod tests {
         cpu.mem[31] = 0xaa;
         cpu.mem[0xaa] = 0x12;
         let actual = zero_page_immutatble(&mut cpu);
-        assert_eq!(0x12, *actual);
-        *actual = 0xff;
-        assert_eq!(0xff, *actual);
+        assert_eq!(0x12, cpu.read(actual));
+        cpu.write(actual, 0xff);
+        assert_eq!(0xff, cpu.read(actual));
     }
 
     #[test]
@@ -219,9 +252,9 @@ mod tests {
         cpu.xr = 0x1;
         cpu.mem[0xaa + 0x1] = 0x12;
         let actual = zero_page_x_immutable(&mut cpu);
-        assert_eq!(0x12, *actual);
-        *actual = 0xff;
-        assert_eq!(0xff, *actual);
+        assert_eq!(0x12, cpu.read(actual));
+        cpu.write(actual, 0xff);
+        assert_eq!(0xff, cpu.read(actual));
     }
 
     #[test]
@@ -231,9 +264,9 @@ mod tests {
         cpu.mem[2] = 0x10;
         cpu.mem[0x1030] = 0xaa;
         let actual = absolute_immutable(&mut cpu);
-        assert_eq!(0xaa, *actual);
-        *actual = 0xff;
-        assert_eq!(0xff, *actual);
+        assert_eq!(0xaa, cpu.read(actual));
+        cpu.write(actual, 0xff);
+        assert_eq!(0xff, cpu.read(actual));
     }
 
     #[test]
@@ -244,9 +277,9 @@ mod tests {
         cpu.xr = 0x12;
         cpu.mem[0x1030 + 0x12] = 0xaa;
         let actual = absolute_x_immutable(&mut cpu);
-        assert_eq!(0xaa, *actual);
-        *actual = 0xff;
-        assert_eq!(0xff, *actual);
+        assert_eq!(0xaa, cpu.read(actual));
+        cpu.write(actual, 0xff);
+        assert_eq!(0xff, cpu.read(actual));
     }
 
     #[test]
@@ -256,7 +289,7 @@ mod tests {
         cpu.pc = 0x00f8;
         let expected: u8 = 0xff;
         cpu.mem[cpu.pc as usize + 1] = expected;
-        let actual = relative(&cpu);
+        let actual = relative(&mut cpu);
         assert_eq!(expected as i8 as u16, actual)
     }
 
@@ -267,7 +300,7 @@ mod tests {
         cpu.mem[31] = 0xaa;
         cpu.yr = 0x1;
         cpu.mem[0xaa + 0x1] = 0x12;
-        let actual = zero_page_y(&cpu);
+        let actual = zero_page_y(&mut cpu);
         assert_eq!(0x12, actual);
     }
 }
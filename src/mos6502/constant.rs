@@ -1,13 +1,15 @@
 ///
 /// this module hold all necessary constants
 ///
-pub const LOWER_NIBBLE_MASK: u8 = 0x01;
+pub const LOWER_NIBBLE_MASK: u8 = 0x0f;
 
 // NV-BDIZC
 pub const NEGATIVE_ON_MASK: u8 = 0b10000000;
 
 pub const OVERFLOW_ON_MASK: u8 = 0b01000000;
 
+pub const UNUSED_ON_MASK: u8 = 0b00100000;
+
 pub const BREAK_ON_MASK: u8 = 0b00010000;
 
 pub const DECIMAL_ON_MASK: u8 = 0b00001000;
@@ -19,3 +21,11 @@ pub const ZERO_ON_MASK: u8 = 0b00000010;
 pub const CARRY_ON_MASK: u8 = 0b00000001;
 
 pub const BIT_0_MASK: u8 = 0b00000001;
+
+pub const STACK_PAGE: u16 = 0x0100;
+
+pub const NMI_VECTOR: u16 = 0xfffa;
+
+pub const IRQ_VECTOR: u16 = 0xfffe;
+
+pub const RESET_VECTOR: u16 = 0xfffc;
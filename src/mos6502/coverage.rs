@@ -0,0 +1,252 @@
+///
+/// code coverage: for every address, whether it was executed as an opcode,
+/// executed as an operand, read as data or written, and how many times an
+/// instruction started there. Operands count as executed whether or not
+/// the instruction read them, a branch not taken still ran its offset.
+///
+use std::io::{self, Write};
+
+use super::{disasm, Access, AccessKind};
+
+const OPCODE: u8 = 0x01;
+const OPERAND: u8 = 0x02;
+const READ: u8 = 0x04;
+const WRITE: u8 = 0x08;
+
+const JSR: u8 = 0x20;
+
+///
+/// one line of the listing, an instruction or a lone byte
+///
+struct Line {
+    address: u16,
+    len: usize,
+    text: String,
+}
+
+pub struct Coverage {
+    flags: Vec<u8>,
+    executed: Vec<u32>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            flags: vec![0; 0x10000],
+            executed: vec![0; 0x10000],
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn end(&mut self, accesses: &[Access]) {
+        let mut operands = 0..0;
+        for access in accesses {
+            let address = access.address as usize;
+            match access.kind {
+                AccessKind::Fetch => {
+                    self.flags[address] |= OPCODE;
+                    self.executed[address] = self.executed[address].saturating_add(1);
+                    let len = disasm::length(access.val).unwrap_or(1);
+                    operands = address + 1..(address + len).min(0x10000);
+                    for operand in operands.clone() {
+                        self.flags[operand] |= OPERAND;
+                    }
+                }
+                AccessKind::Read if operands.contains(&address) => {}
+                AccessKind::Read => self.flags[address] |= READ,
+                AccessKind::Write => self.flags[address] |= WRITE,
+            }
+        }
+    }
+
+    ///
+    /// times an instruction started at `address`
+    ///
+    pub fn executed(&self, address: u16) -> u32 {
+        self.executed[address as usize]
+    }
+
+    ///
+    /// whether `address` ran as an opcode or an operand
+    ///
+    pub fn is_code(&self, address: u16) -> bool {
+        self.flags[address as usize] & (OPCODE | OPERAND) != 0
+    }
+
+    pub fn is_read(&self, address: u16) -> bool {
+        self.flags[address as usize] & READ != 0
+    }
+
+    pub fn is_written(&self, address: u16) -> bool {
+        self.flags[address as usize] & WRITE != 0
+    }
+
+    ///
+    /// lowest and highest address that ran as code
+    ///
+    pub fn code_range(&self) -> Option<(u16, u16)> {
+        let first = (0..=0xffff).find(|&address| self.is_code(address))?;
+        let last = (0..=0xffff).rev().find(|&address| self.is_code(address))?;
+        Some((first, last))
+    }
+
+    ///
+    /// disassembly of `bytes`, which sit at `start`, each line with the
+    /// times it ran and its flags: executed as opcode, operand, read, written
+    ///
+    pub fn write_listing(&self, out: &mut dyn Write, start: u16, bytes: &[u8]) -> io::Result<()> {
+        for line in self.sweep(start, bytes) {
+            let offset = line.address.wrapping_sub(start) as usize;
+            let hex: Vec<String> = bytes[offset..offset + line.len]
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            let flags = (line.address..=line.address.wrapping_add(line.len as u16 - 1))
+                .fold(0, |flags, address| flags | self.flags[address as usize]);
+            let marks: String = [(OPCODE, 'x'), (OPERAND, 'o'), (READ, 'r'), (WRITE, 'w')]
+                .iter()
+                .map(|&(flag, mark)| if flags & flag != 0 { mark } else { '-' })
+                .collect();
+            let count = match self.executed(line.address) {
+                0 => "-".to_string(),
+                count => count.to_string(),
+            };
+            writeln!(
+                out,
+                "{:>10} {} ${:04x}  {:<8}  {}",
+                count,
+                marks,
+                line.address,
+                hex.join(" "),
+                line.text
+            )?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// targets of the JSRs in `bytes` that never ran, in address order
+    ///
+    pub fn unexecuted_routines(&self, start: u16, bytes: &[u8]) -> Vec<u16> {
+        let end = start as usize + bytes.len();
+        let mut routines: Vec<u16> = self
+            .sweep(start, bytes)
+            .into_iter()
+            .filter_map(|line| {
+                let offset = line.address.wrapping_sub(start) as usize;
+                match &bytes[offset..offset + line.len] {
+                    [JSR, lsb, msb] if line.len == 3 => Some((*msb as u16) << 8 | *lsb as u16),
+                    _ => None,
+                }
+            })
+            .filter(|&target| (start as usize..end).contains(&(target as usize)))
+            .filter(|&target| self.executed(target) == 0)
+            .collect();
+        routines.sort_unstable();
+        routines.dedup();
+        routines
+    }
+
+    ///
+    /// linear sweep that keeps in step with the instructions that ran, data
+    /// and bytes that would hide an executed opcode become lone bytes
+    ///
+    fn sweep(&self, start: u16, bytes: &[u8]) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let address = start.wrapping_add(offset as u16);
+            let flags = self.flags[address as usize];
+            let decoded = if flags & OPCODE != 0 {
+                disasm::disassemble(&bytes[offset..], address)
+            } else if flags == 0 {
+                disasm::disassemble(&bytes[offset..], address).filter(|(_, len)| {
+                    (1..*len).all(|inner| {
+                        self.flags[address.wrapping_add(inner as u16) as usize] & OPCODE == 0
+                    })
+                })
+            } else {
+                None
+            };
+            let (text, len) =
+                decoded.unwrap_or_else(|| (format!(".byte ${:02x}", bytes[offset]), 1));
+            lines.push(Line { address, len, text });
+            offset += len;
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{Mos6502, Registers};
+
+    // lda $0300, sta $0301, jmp $0209; $0209: jsr $0220, which never runs
+    const PROGRAM: [u8; 10] = [0xad, 0x00, 0x03, 0x8d, 0x01, 0x03, 0x4c, 0x09, 0x02, 0x20];
+
+    fn covered() -> Mos6502 {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &PROGRAM);
+        cpu.load(0x0300, &[0x41]);
+        cpu.set_registers(Registers {
+            pc: 0x0200,
+            sp: 0xff,
+            ..Registers::default()
+        });
+        cpu.cover();
+        for _ in 0..3 {
+            cpu.step();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_bytes_are_classified() {
+        let cpu = covered();
+        let coverage = cpu.coverage().unwrap();
+        assert_eq!(1, coverage.executed(0x0200));
+        assert!(coverage.is_code(0x0201) && !coverage.is_read(0x0201));
+        assert!(coverage.is_read(0x0300) && !coverage.is_code(0x0300));
+        assert!(coverage.is_written(0x0301));
+        assert_eq!(Some((0x0200, 0x0208)), coverage.code_range());
+    }
+
+    #[test]
+    fn test_listing() {
+        let cpu = covered();
+        let mut listing = Vec::new();
+        let mut bytes = PROGRAM.to_vec();
+        bytes.extend([0x20, 0x02]);
+        let coverage = cpu.coverage().unwrap();
+        coverage
+            .write_listing(&mut listing, 0x0200, &bytes)
+            .unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!("         1 xo-- $0200  ad 00 03  lda $0300", lines[0]);
+        assert_eq!("         - ---- $0209  20 20 02  jsr $0220", lines[3]);
+        assert_eq!(
+            vec![0x0220],
+            coverage.unexecuted_routines(0x0200, &[bytes, vec![0; 0x20]].concat())
+        );
+    }
+
+    #[test]
+    fn test_data_is_not_disassembled() {
+        let cpu = covered();
+        let mut listing = Vec::new();
+        let coverage = cpu.coverage().unwrap();
+        coverage
+            .write_listing(&mut listing, 0x0300, &[0x41, 0xa9, 0x00])
+            .unwrap();
+        assert_eq!(
+            "         - --r- $0300  41        .byte $41\n         - ---w $0301  a9        .byte $a9\n         - ---- $0302  00        brk\n",
+            String::from_utf8(listing).unwrap()
+        );
+    }
+}
@@ -0,0 +1,253 @@
+///
+/// disassembler for the documented NMOS 6502 opcodes, in the syntax ca65
+/// accepts
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndexedIndirect, // (zp,x)
+    IndirectIndexed, // (zp),y
+    Relative,
+}
+
+use Mode::*;
+
+impl Mode {
+    fn len(self) -> usize {
+        match self {
+            Implied | Accumulator => 1,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 3,
+            _ => 2,
+        }
+    }
+}
+
+fn decode(opcode: u8) -> Option<(&'static str, Mode)> {
+    let decoded = match opcode {
+        0x00 => ("brk", Implied),
+        0x01 => ("ora", IndexedIndirect),
+        0x05 => ("ora", ZeroPage),
+        0x06 => ("asl", ZeroPage),
+        0x08 => ("php", Implied),
+        0x09 => ("ora", Immediate),
+        0x0a => ("asl", Accumulator),
+        0x0d => ("ora", Absolute),
+        0x0e => ("asl", Absolute),
+        0x10 => ("bpl", Relative),
+        0x11 => ("ora", IndirectIndexed),
+        0x15 => ("ora", ZeroPageX),
+        0x16 => ("asl", ZeroPageX),
+        0x18 => ("clc", Implied),
+        0x19 => ("ora", AbsoluteY),
+        0x1d => ("ora", AbsoluteX),
+        0x1e => ("asl", AbsoluteX),
+        0x20 => ("jsr", Absolute),
+        0x21 => ("and", IndexedIndirect),
+        0x24 => ("bit", ZeroPage),
+        0x25 => ("and", ZeroPage),
+        0x26 => ("rol", ZeroPage),
+        0x28 => ("plp", Implied),
+        0x29 => ("and", Immediate),
+        0x2a => ("rol", Accumulator),
+        0x2c => ("bit", Absolute),
+        0x2d => ("and", Absolute),
+        0x2e => ("rol", Absolute),
+        0x30 => ("bmi", Relative),
+        0x31 => ("and", IndirectIndexed),
+        0x35 => ("and", ZeroPageX),
+        0x36 => ("rol", ZeroPageX),
+        0x38 => ("sec", Implied),
+        0x39 => ("and", AbsoluteY),
+        0x3d => ("and", AbsoluteX),
+        0x3e => ("rol", AbsoluteX),
+        0x40 => ("rti", Implied),
+        0x41 => ("eor", IndexedIndirect),
+        0x45 => ("eor", ZeroPage),
+        0x46 => ("lsr", ZeroPage),
+        0x48 => ("pha", Implied),
+        0x49 => ("eor", Immediate),
+        0x4a => ("lsr", Accumulator),
+        0x4c => ("jmp", Absolute),
+        0x4d => ("eor", Absolute),
+        0x4e => ("lsr", Absolute),
+        0x50 => ("bvc", Relative),
+        0x51 => ("eor", IndirectIndexed),
+        0x55 => ("eor", ZeroPageX),
+        0x56 => ("lsr", ZeroPageX),
+        0x58 => ("cli", Implied),
+        0x59 => ("eor", AbsoluteY),
+        0x5d => ("eor", AbsoluteX),
+        0x5e => ("lsr", AbsoluteX),
+        0x60 => ("rts", Implied),
+        0x61 => ("adc", IndexedIndirect),
+        0x65 => ("adc", ZeroPage),
+        0x66 => ("ror", ZeroPage),
+        0x68 => ("pla", Implied),
+        0x69 => ("adc", Immediate),
+        0x6a => ("ror", Accumulator),
+        0x6c => ("jmp", Indirect),
+        0x6d => ("adc", Absolute),
+        0x6e => ("ror", Absolute),
+        0x70 => ("bvs", Relative),
+        0x71 => ("adc", IndirectIndexed),
+        0x75 => ("adc", ZeroPageX),
+        0x76 => ("ror", ZeroPageX),
+        0x78 => ("sei", Implied),
+        0x79 => ("adc", AbsoluteY),
+        0x7d => ("adc", AbsoluteX),
+        0x7e => ("ror", AbsoluteX),
+        0x81 => ("sta", IndexedIndirect),
+        0x84 => ("sty", ZeroPage),
+        0x85 => ("sta", ZeroPage),
+        0x86 => ("stx", ZeroPage),
+        0x88 => ("dey", Implied),
+        0x8a => ("txa", Implied),
+        0x8c => ("sty", Absolute),
+        0x8d => ("sta", Absolute),
+        0x8e => ("stx", Absolute),
+        0x90 => ("bcc", Relative),
+        0x91 => ("sta", IndirectIndexed),
+        0x94 => ("sty", ZeroPageX),
+        0x95 => ("sta", ZeroPageX),
+        0x96 => ("stx", ZeroPageY),
+        0x98 => ("tya", Implied),
+        0x99 => ("sta", AbsoluteY),
+        0x9a => ("txs", Implied),
+        0x9d => ("sta", AbsoluteX),
+        0xa0 => ("ldy", Immediate),
+        0xa1 => ("lda", IndexedIndirect),
+        0xa2 => ("ldx", Immediate),
+        0xa4 => ("ldy", ZeroPage),
+        0xa5 => ("lda", ZeroPage),
+        0xa6 => ("ldx", ZeroPage),
+        0xa8 => ("tay", Implied),
+        0xa9 => ("lda", Immediate),
+        0xaa => ("tax", Implied),
+        0xac => ("ldy", Absolute),
+        0xad => ("lda", Absolute),
+        0xae => ("ldx", Absolute),
+        0xb0 => ("bcs", Relative),
+        0xb1 => ("lda", IndirectIndexed),
+        0xb4 => ("ldy", ZeroPageX),
+        0xb5 => ("lda", ZeroPageX),
+        0xb6 => ("ldx", ZeroPageY),
+        0xb8 => ("clv", Implied),
+        0xb9 => ("lda", AbsoluteY),
+        0xba => ("tsx", Implied),
+        0xbc => ("ldy", AbsoluteX),
+        0xbd => ("lda", AbsoluteX),
+        0xbe => ("ldx", AbsoluteY),
+        0xc0 => ("cpy", Immediate),
+        0xc1 => ("cmp", IndexedIndirect),
+        0xc4 => ("cpy", ZeroPage),
+        0xc5 => ("cmp", ZeroPage),
+        0xc6 => ("dec", ZeroPage),
+        0xc8 => ("iny", Implied),
+        0xc9 => ("cmp", Immediate),
+        0xca => ("dex", Implied),
+        0xcc => ("cpy", Absolute),
+        0xcd => ("cmp", Absolute),
+        0xce => ("dec", Absolute),
+        0xd0 => ("bne", Relative),
+        0xd1 => ("cmp", IndirectIndexed),
+        0xd5 => ("cmp", ZeroPageX),
+        0xd6 => ("dec", ZeroPageX),
+        0xd8 => ("cld", Implied),
+        0xd9 => ("cmp", AbsoluteY),
+        0xdd => ("cmp", AbsoluteX),
+        0xde => ("dec", AbsoluteX),
+        0xe0 => ("cpx", Immediate),
+        0xe1 => ("sbc", IndexedIndirect),
+        0xe4 => ("cpx", ZeroPage),
+        0xe5 => ("sbc", ZeroPage),
+        0xe6 => ("inc", ZeroPage),
+        0xe8 => ("inx", Implied),
+        0xe9 => ("sbc", Immediate),
+        0xea => ("nop", Implied),
+        0xec => ("cpx", Absolute),
+        0xed => ("sbc", Absolute),
+        0xee => ("inc", Absolute),
+        0xf0 => ("beq", Relative),
+        0xf1 => ("sbc", IndirectIndexed),
+        0xf5 => ("sbc", ZeroPageX),
+        0xf6 => ("inc", ZeroPageX),
+        0xf8 => ("sed", Implied),
+        0xf9 => ("sbc", AbsoluteY),
+        0xfd => ("sbc", AbsoluteX),
+        0xfe => ("inc", AbsoluteX),
+        _ => return None,
+    };
+    Some(decoded)
+}
+
+///
+/// length in bytes of the instruction starting with `opcode`
+///
+pub fn length(opcode: u8) -> Option<usize> {
+    decode(opcode).map(|(_, mode)| mode.len())
+}
+
+///
+/// the instruction at the start of `bytes`, which sit at `address`, and its
+/// length. None for undocumented opcodes or a truncated instruction.
+///
+pub fn disassemble(bytes: &[u8], address: u16) -> Option<(String, usize)> {
+    let (mnemonic, mode) = decode(*bytes.first()?)?;
+    let len = mode.len();
+    let operand = bytes.get(1..len)?;
+    let byte = operand.first().copied().unwrap_or(0);
+    let word = operand
+        .get(1)
+        .map_or(0, |msb| (*msb as u16) << 8 | byte as u16);
+    let operand = match mode {
+        Implied => String::new(),
+        Accumulator => " a".to_string(),
+        Immediate => format!(" #${:02x}", byte),
+        ZeroPage => format!(" ${:02x}", byte),
+        ZeroPageX => format!(" ${:02x},x", byte),
+        ZeroPageY => format!(" ${:02x},y", byte),
+        Absolute => format!(" ${:04x}", word),
+        AbsoluteX => format!(" ${:04x},x", word),
+        AbsoluteY => format!(" ${:04x},y", word),
+        Indirect => format!(" (${:04x})", word),
+        IndexedIndirect => format!(" (${:02x},x)", byte),
+        IndirectIndexed => format!(" (${:02x}),y", byte),
+        Relative => format!(
+            " ${:04x}",
+            address.wrapping_add(2).wrapping_add(byte as i8 as u16)
+        ),
+    };
+    Some((format!("{}{}", mnemonic, operand), len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(
+            Some(("jsr $0210".to_string(), 3)),
+            disassemble(&[0x20, 0x10, 0x02], 0x0200)
+        );
+        assert_eq!(
+            Some(("lda ($10),y".to_string(), 2)),
+            disassemble(&[0xb1, 0x10], 0)
+        );
+        assert_eq!(
+            Some(("bne $01fe".to_string(), 2)),
+            disassemble(&[0xd0, 0xfc], 0x0200)
+        );
+        assert_eq!(None, disassemble(&[0x02], 0));
+        assert_eq!(None, disassemble(&[0xad, 0x00], 0));
+    }
+}
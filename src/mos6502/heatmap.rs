@@ -0,0 +1,229 @@
+///
+/// per address read, write and execute counters, and the 256x256 image of
+/// the address space they render to: one row per page, zero page at the
+/// top. Opcode fetches count as executes, not as reads.
+///
+use std::io::{self, Write};
+
+use super::{Access, AccessKind};
+
+// grayscale PNG, eight bits per pixel
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const STORED_BLOCK: usize = 0xffff;
+
+pub struct Heatmap {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+    executes: Vec<u64>,
+}
+
+impl Default for Heatmap {
+    fn default() -> Self {
+        Self {
+            reads: vec![0; 0x10000],
+            writes: vec![0; 0x10000],
+            executes: vec![0; 0x10000],
+        }
+    }
+}
+
+impl Heatmap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn end(&mut self, accesses: &[Access]) {
+        for access in accesses {
+            let counter = match access.kind {
+                AccessKind::Fetch => &mut self.executes,
+                AccessKind::Read => &mut self.reads,
+                AccessKind::Write => &mut self.writes,
+            };
+            counter[access.address as usize] += 1;
+        }
+    }
+
+    pub fn reads(&self, address: u16) -> u64 {
+        self.reads[address as usize]
+    }
+
+    pub fn writes(&self, address: u16) -> u64 {
+        self.writes[address as usize]
+    }
+
+    pub fn executes(&self, address: u16) -> u64 {
+        self.executes[address as usize]
+    }
+
+    ///
+    /// `address,reads,writes,executes` for every address used
+    ///
+    pub fn write_csv(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "address,reads,writes,executes")?;
+        for address in 0..=0xffff {
+            let counts = (
+                self.reads(address),
+                self.writes(address),
+                self.executes(address),
+            );
+            if counts != (0, 0, 0) {
+                writeln!(
+                    out,
+                    "{:04x},{},{},{}",
+                    address, counts.0, counts.1, counts.2
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// binary PGM of all accesses, the busiest address white
+    ///
+    pub fn write_pgm(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "P5\n256 256\n255\n")?;
+        out.write_all(&self.pixels())
+    }
+
+    ///
+    /// the PGM image as a PNG, stored without compression
+    ///
+    pub fn write_png(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut header = Vec::new();
+        header.extend(256u32.to_be_bytes());
+        header.extend(256u32.to_be_bytes());
+        header.extend([8, 0, 0, 0, 0]); // depth, grayscale, deflate, filter, no interlace
+
+        // every row starts with filter type none
+        let mut rows = Vec::with_capacity(256 * 257);
+        for row in self.pixels().chunks(256) {
+            rows.push(0);
+            rows.extend(row);
+        }
+        let mut zlib = vec![0x78, 0x01];
+        let blocks = rows.chunks(STORED_BLOCK).count();
+        for (index, block) in rows.chunks(STORED_BLOCK).enumerate() {
+            zlib.push((index + 1 == blocks) as u8);
+            zlib.extend((block.len() as u16).to_le_bytes());
+            zlib.extend((!(block.len() as u16)).to_le_bytes());
+            zlib.extend(block);
+        }
+        zlib.extend(adler32(&rows).to_be_bytes());
+
+        out.write_all(&PNG_SIGNATURE)?;
+        write_chunk(out, b"IHDR", &header)?;
+        write_chunk(out, b"IDAT", &zlib)?;
+        write_chunk(out, b"IEND", &[])
+    }
+
+    ///
+    /// accesses per address on a log scale, so a quiet variable still shows
+    /// next to a busy loop
+    ///
+    fn pixels(&self) -> Vec<u8> {
+        let totals: Vec<u64> = (0..0x10000)
+            .map(|address| self.reads[address] + self.writes[address] + self.executes[address])
+            .collect();
+        let busiest = (totals.iter().copied().max().unwrap_or(0) as f64).ln_1p();
+        totals
+            .iter()
+            .map(|&total| match total {
+                0 => 0,
+                total => (1.0 + 254.0 * (total as f64).ln_1p() / busiest).round() as u8,
+            })
+            .collect()
+    }
+}
+
+fn write_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data));
+    out.write_all(&crc.to_be_bytes())
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mos6502::{Mos6502, Registers};
+
+    fn counted() -> Mos6502 {
+        // inc $10 twice
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &[0xe6, 0x10, 0xe6, 0x10]);
+        cpu.set_registers(Registers {
+            pc: 0x0200,
+            ..Registers::default()
+        });
+        cpu.count_accesses();
+        cpu.step();
+        cpu.step();
+        cpu
+    }
+
+    #[test]
+    fn test_counters_and_csv() {
+        let cpu = counted();
+        let heatmap = cpu.heatmap().unwrap();
+        assert_eq!(
+            (2, 2, 0),
+            (
+                heatmap.reads(0x10),
+                heatmap.writes(0x10),
+                heatmap.executes(0x10)
+            )
+        );
+        assert_eq!((1, 1), (heatmap.executes(0x0200), heatmap.reads(0x0201)));
+        let mut csv = Vec::new();
+        heatmap.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("address,reads,writes,executes\n0010,2,2,0\n0200,0,0,1\n"));
+    }
+
+    #[test]
+    fn test_pgm_rows_are_pages() {
+        let mut pgm = Vec::new();
+        counted().heatmap().unwrap().write_pgm(&mut pgm).unwrap();
+        let pixels = &pgm[b"P5\n256 256\n255\n".len()..];
+        assert_eq!(0x10000, pixels.len());
+        assert_eq!(255, pixels[0x10]);
+        assert!(pixels[0x0200] > 0 && pixels[0x0200] < 255);
+        assert_eq!(0, pixels[0x0300]);
+    }
+
+    #[test]
+    fn test_png_checksums() {
+        assert_eq!(0xcbf43926, crc32(b"123456789".iter()));
+        assert_eq!(0x091e01de, adler32(b"123456789"));
+        let mut png = Vec::new();
+        counted().heatmap().unwrap().write_png(&mut png).unwrap();
+        assert_eq!(PNG_SIGNATURE, png[..8]);
+        assert_eq!(b"IHDR", &png[12..16]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+}
@@ -0,0 +1,232 @@
+///
+/// execution history for stepping backwards. Every instruction leaves an
+/// undo record, the registers before it ran and the bus accesses it made,
+/// and every `snapshot_interval` instructions the whole machine is saved.
+/// Undoing a record puts back registers and RAM, landing on an instruction
+/// a snapshot was taken before also brings back the devices.
+///
+/// Records and snapshots are dropped oldest first once they need more than
+/// the byte budget.
+///
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use super::{Access, AccessKind, HaltReason, Mos6502, Registers};
+
+pub const DEFAULT_BUDGET: usize = 16 * 1024 * 1024;
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 10_000;
+
+///
+/// the last write to an address found in the history
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteRecord {
+    pub instruction: u64, // number of the instruction since recording started
+    pub pc: u16,
+    pub cycles: u64,     // cycle counter before the instruction
+    pub old: Option<u8>, // RAM contents overwritten, none for devices
+    pub val: u8,
+}
+
+pub(super) struct Record {
+    pub(super) instruction: u64,
+    pub(super) registers: Registers,
+    pub(super) cycles: u64,
+    pub(super) halt_reason: Option<HaltReason>,
+    pub(super) accesses: Vec<Access>,
+}
+
+impl Record {
+    fn cost(&self) -> usize {
+        size_of::<Record>() + self.accesses.len() * size_of::<Access>()
+    }
+}
+
+pub struct History {
+    budget: usize,
+    snapshot_interval: u64,
+    executed: u64,
+    records: VecDeque<Record>,
+    snapshots: VecDeque<(u64, Vec<u8>)>, // taken before the instruction numbered
+    used: usize,
+    pending: Option<Record>, // the instruction being executed
+}
+
+impl History {
+    pub fn new(budget: usize, snapshot_interval: u64) -> Self {
+        Self {
+            budget,
+            snapshot_interval: snapshot_interval.max(1),
+            executed: 0,
+            records: VecDeque::new(),
+            snapshots: VecDeque::new(),
+            used: 0,
+            pending: None,
+        }
+    }
+
+    ///
+    /// number of instructions that can be undone
+    ///
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    ///
+    /// most recent write to `address` still in the history
+    ///
+    pub fn last_write(&self, address: u16) -> Option<WriteRecord> {
+        self.records.iter().rev().find_map(|record| {
+            record
+                .accesses
+                .iter()
+                .rev()
+                .find(|access| access.kind == AccessKind::Write && access.address == address)
+                .map(|access| WriteRecord {
+                    instruction: record.instruction,
+                    pc: record.registers.pc,
+                    cycles: record.cycles,
+                    old: access.old,
+                    val: access.val,
+                })
+        })
+    }
+
+    pub(super) fn begin(&mut self, cpu: &Mos6502) {
+        let due = self.executed.is_multiple_of(self.snapshot_interval);
+        let taken = self
+            .snapshots
+            .back()
+            .is_some_and(|(at, _)| *at == self.executed);
+        if due && !taken {
+            let snapshot = cpu.snapshot();
+            self.used += snapshot.len();
+            self.snapshots.push_back((self.executed, snapshot));
+        }
+        self.pending = Some(Record {
+            instruction: self.executed,
+            registers: cpu.registers(),
+            cycles: cpu.cycles,
+            halt_reason: cpu.halt_reason,
+            accesses: Vec::new(),
+        });
+    }
+
+    pub(super) fn end(&mut self, cpu: &Mos6502) {
+        let Some(mut record) = self.pending.take() else {
+            return;
+        };
+        record.accesses = cpu.accesses.clone();
+        self.used += record.cost();
+        self.records.push_back(record);
+        self.executed += 1;
+        self.trim();
+    }
+
+    ///
+    /// take back the latest record, snapshots of the future it leaves are dropped
+    ///
+    pub(super) fn pop(&mut self) -> Option<Record> {
+        let record = self.records.pop_back()?;
+        self.used -= record.cost();
+        self.executed = record.instruction;
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|(at, _)| *at > self.executed)
+        {
+            let (_, snapshot) = self.snapshots.pop_back().unwrap();
+            self.used -= snapshot.len();
+        }
+        Some(record)
+    }
+
+    pub(super) fn snapshot_before(&self, instruction: u64) -> Option<&[u8]> {
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|(at, _)| *at == instruction)
+            .map(|(_, snapshot)| snapshot.as_slice())
+    }
+
+    fn trim(&mut self) {
+        while self.used > self.budget && self.records.len() > 1 {
+            let record = self.records.pop_front().unwrap();
+            self.used -= record.cost();
+            let oldest = record.instruction + 1;
+            while self.snapshots.front().is_some_and(|(at, _)| *at < oldest) {
+                let (_, snapshot) = self.snapshots.pop_front().unwrap();
+                self.used -= snapshot.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(program: &[u8]) -> Mos6502 {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, program);
+        cpu.record_history(DEFAULT_BUDGET, 2);
+        cpu
+    }
+
+    #[test]
+    fn test_reverse_step_undoes_registers_and_memory() {
+        // lda #$42, sta $10, inc $10
+        let mut cpu = recording(&[0xa9, 0x42, 0x85, 0x10, 0xe6, 0x10]);
+        cpu.poke(0x10, 0x07);
+        for _ in 0..3 {
+            cpu.step();
+        }
+        assert_eq!(0x43, cpu.peek(0x10));
+        cpu.reverse_step().unwrap();
+        assert_eq!(0x42, cpu.peek(0x10));
+        assert_eq!(0x0204, cpu.registers().pc);
+        cpu.reverse_step().unwrap();
+        cpu.reverse_step().unwrap();
+        assert_eq!(0x07, cpu.peek(0x10));
+        assert_eq!(
+            Registers {
+                pc: 0x0200,
+                ..Registers::default()
+            },
+            cpu.registers()
+        );
+        assert_eq!(0, cpu.cycles());
+        assert!(cpu.reverse_step().is_none());
+    }
+
+    #[test]
+    fn test_last_write_names_the_instruction() {
+        // sta $10, stx $10, lda $10
+        let mut cpu = recording(&[0x85, 0x10, 0x86, 0x10, 0xa5, 0x10]);
+        for _ in 0..3 {
+            cpu.step();
+        }
+        let write = cpu.history().unwrap().last_write(0x10).unwrap();
+        assert_eq!(1, write.instruction);
+        assert_eq!(0x0202, write.pc);
+        assert!(cpu.history().unwrap().last_write(0x11).is_none());
+    }
+
+    #[test]
+    fn test_budget_drops_oldest_records() {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &[0xea; 64]); // nop
+        cpu.record_history(0, 1000);
+        for _ in 0..10 {
+            cpu.step();
+        }
+        assert_eq!(1, cpu.history().unwrap().len());
+        cpu.reverse_step().unwrap();
+        assert_eq!(0x0209, cpu.registers().pc);
+        assert!(cpu.reverse_step().is_none());
+    }
+}
@@ -53,3 +53,14 @@ impl InsAttr {
         self.cyc
     }
 }
+
+///
+/// load `program` at $0200 and run its first `instructions`
+///
+#[cfg(test)]
+fn run_program(cpu: &mut Mos6502, program: &[u8], instructions: usize) {
+    cpu.load(0x0200, program);
+    for _ in 0..instructions {
+        cpu.step();
+    }
+}
@@ -88,7 +88,7 @@ fn do_add(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeFn) {
 }
 
 #[allow(arithmetic_overflow)]
-fn add_and_update_status_register(cpu: &mut Mos6502, operand: u8) {
+pub(super) fn add_and_update_status_register(cpu: &mut Mos6502, operand: u8) {
     let carry: u8 = cpu.is_carried();
     let result: u8 = cpu.ac + operand + carry;
     let acc_bit7: u8 = cpu.ac >> 7;
@@ -96,9 +96,53 @@ fn add_and_update_status_register(cpu: &mut Mos6502, operand: u8) {
     let result_bit7: u8 = result >> 7;
 
     update_overflow_flag(cpu, acc_bit7 == operand_bit7 && acc_bit7 != result_bit7);
-    update_carry_flag(cpu, u8::MAX - cpu.ac - carry < operand);
+    update_carry_flag(cpu, cpu.ac as u16 + operand as u16 + carry as u16 > u8::MAX as u16);
     update_zero_flag(cpu, result == 0);
     update_negative_flag(cpu, result_bit7 == 0b1);
 
     cpu.ac = result
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{
+        constant::{CARRY_ON_MASK, NEGATIVE_ON_MASK, OVERFLOW_ON_MASK, ZERO_ON_MASK},
+        insset::run_program,
+        Mos6502,
+    };
+
+    #[test]
+    fn adc_should_carry_out_of_the_accumulator() {
+        let mut cpu = Mos6502 {
+            ac: 0xff,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0x69, 0x01], 1);
+        assert_eq!(0x00, cpu.ac);
+        assert_eq!(CARRY_ON_MASK | ZERO_ON_MASK, cpu.sr);
+    }
+
+    #[test]
+    fn adc_should_carry_when_only_the_carry_in_overflows() {
+        let mut cpu = Mos6502 {
+            ac: 0xff,
+            sr: CARRY_ON_MASK,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0x69, 0x00], 1);
+        assert_eq!(0x00, cpu.ac);
+        assert_eq!(CARRY_ON_MASK | ZERO_ON_MASK, cpu.sr);
+    }
+
+    #[test]
+    fn adc_should_flag_a_signed_overflow() {
+        let mut cpu = Mos6502 {
+            ac: 0x50,
+            sr: CARRY_ON_MASK,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0x69, 0x2f], 1);
+        assert_eq!(0x80, cpu.ac);
+        assert_eq!(NEGATIVE_ON_MASK | OVERFLOW_ON_MASK, cpu.sr);
+    }
+}
@@ -63,10 +63,12 @@ impl Mos6502Ins for AslAbsX {
 }
 
 fn do_asl(cpu: &mut Mos6502, attr: &InsAttr, immutable_fn: AddressModeImmutableFn) {
-    let address: &mut u8 = immutable_fn(cpu);
-    let old_val_bit_7: u8 = *address >> 7;
-    let result: u8 = *address << 1;
-    *address = result;
+    let address: u16 = immutable_fn(cpu);
+    let operand: u8 = cpu.read(address);
+    cpu.dummy_write(address, operand);
+    let old_val_bit_7: u8 = operand >> 7;
+    let result: u8 = operand << 1;
+    cpu.write(address, result);
 
     update_carry_flag(cpu, old_val_bit_7 == 0b1);
     update_zero_flag(cpu, result == 0);
@@ -39,6 +39,8 @@ impl Mos6502Ins for Bcc {
         let carry_flag: u8 = cpu.sr & BIT_0_MASK;
         if carry_flag == 0b0 {
             move_to_offset(cpu, &self.attr);
+        } else {
+            cpu.next_instruction(&self.attr);
         }
     }
 }
@@ -48,6 +50,8 @@ impl Mos6502Ins for Bcs {
         let carry_flag: u8 = cpu.sr & BIT_0_MASK;
         if carry_flag == 0b1 {
             move_to_offset(cpu, &self.attr);
+        } else {
+            cpu.next_instruction(&self.attr);
         }
     }
 }
@@ -57,6 +61,8 @@ impl Mos6502Ins for Beq {
         let zero_flag: u8 = (cpu.sr >> 1) & BIT_0_MASK;
         if zero_flag == 0b1 {
             move_to_offset(cpu, &self.attr)
+        } else {
+            cpu.next_instruction(&self.attr);
         }
     }
 }
@@ -66,6 +72,8 @@ impl Mos6502Ins for Bmi {
         let negative_flag: u8 = (cpu.sr >> 7) & BIT_0_MASK;
         if negative_flag == 0b1 {
             move_to_offset(cpu, &self.attr)
+        } else {
+            cpu.next_instruction(&self.attr);
         }
     }
 }
@@ -75,6 +83,8 @@ impl Mos6502Ins for Bne {
         let zero_flag: u8 = (cpu.sr >> 1) & BIT_0_MASK;
         if zero_flag == 0b0 {
             move_to_offset(cpu, &self.attr)
+        } else {
+            cpu.next_instruction(&self.attr);
         }
     }
 }
@@ -84,6 +94,8 @@ impl Mos6502Ins for Bpl {
         let negative_flag: u8 = (cpu.sr >> 7) & BIT_0_MASK;
         if negative_flag == 0b0 {
             move_to_offset(cpu, &self.attr)
+        } else {
+            cpu.next_instruction(&self.attr);
         }
     }
 }
@@ -93,6 +105,8 @@ impl Mos6502Ins for Bvc {
         let overflow_flag: u8 = (cpu.sr >> 6) & BIT_0_MASK;
         if overflow_flag == 0b0 {
             move_to_offset(cpu, &self.attr)
+        } else {
+            cpu.next_instruction(&self.attr);
         }
     }
 }
@@ -102,12 +116,54 @@ impl Mos6502Ins for Bvs {
         let overflow_flag: u8 = (cpu.sr >> 6) & BIT_0_MASK;
         if overflow_flag == 0b1 {
             move_to_offset(cpu, &self.attr)
+        } else {
+            cpu.next_instruction(&self.attr);
         }
     }
 }
 
+///
+/// a taken branch reads the next opcode while adding the offset, and the
+/// address before the carry into the high byte when it crosses a page.
+/// Staying in the page it delays interrupts by an instruction.
+///
 fn move_to_offset(cpu: &mut Mos6502, attr: &InsAttr) {
     let offset: u16 = relative(cpu);
+    let next = cpu.pc.wrapping_add(attr.len() as u16);
+    let target = next.wrapping_add(offset);
+    cpu.extra_cycle(next);
+    if target & 0xff00 != next & 0xff00 {
+        cpu.extra_cycle(next & 0xff00 | target & 0x00ff);
+    } else {
+        cpu.poll_early();
+    }
     cpu.pc += offset;
     cpu.next_instruction(attr);
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{constant::ZERO_ON_MASK, insset::run_program};
+
+    use super::*;
+
+    #[test]
+    fn beq_should_move_to_the_next_instruction_when_not_taken() {
+        let mut cpu = Mos6502::default();
+        run_program(&mut cpu, &[0xf0, 0x04], 1);
+        assert_eq!(0x0202, cpu.pc);
+        assert_eq!(2, cpu.cycles());
+    }
+
+    #[test]
+    fn beq_should_move_by_the_offset_when_taken() {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &[0xf0, 0x04]);
+        cpu.sr = ZERO_ON_MASK;
+        cpu.step();
+        assert_eq!((0x0206, 3), (cpu.pc, cpu.cycles()));
+        // one more cycle to cross into the previous page
+        run_program(&mut cpu, &[0xf0, 0xfc], 1);
+        assert_eq!((0x01fe, 3 + 4), (cpu.pc, cpu.cycles()));
+    }
+}
@@ -78,14 +78,12 @@ impl Mos6502Ins for Dey {
     }
 }
 
-fn do_decrement_at_mem<'a>(
-    cpu: &'a mut Mos6502,
-    attr: &InsAttr,
-    address_mode_fn: AddressModeImmutableFn,
-) {
-    let operand: &mut u8 = address_mode_fn(cpu);
-    *operand -= 1;
-    let result: u8 = *operand;
+fn do_decrement_at_mem(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeImmutableFn) {
+    let address: u16 = address_mode_fn(cpu);
+    let operand: u8 = cpu.read(address);
+    cpu.dummy_write(address, operand);
+    let result: u8 = operand - 1;
+    cpu.write(address, result);
 
     update_zero_flag(cpu, result == 0);
     update_negative_flag(cpu, (result as i8) < 0);
@@ -86,7 +86,7 @@ impl Mos6502Ins for EorIndY {
 }
 
 fn do_exclusive_or(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeFn) {
-    let operand: u8 = address_mode_fn(&cpu);
+    let operand: u8 = address_mode_fn(cpu);
     cpu.ac ^= operand;
 
     update_zero_flag(cpu, cpu.ac == 0);
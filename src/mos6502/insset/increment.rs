@@ -68,9 +68,11 @@ impl Mos6502Ins for Iny {
 }
 
 fn do_increment_at_mem(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeImmutableFn) {
-    let mem: &mut u8 = address_mode_fn(cpu);
-    *mem += 1;
-    let rs: u8 = *mem;
+    let address: u16 = address_mode_fn(cpu);
+    let operand: u8 = cpu.read(address);
+    cpu.dummy_write(address, operand);
+    let rs: u8 = operand + 1;
+    cpu.write(address, rs);
 
     update_zero_flag(cpu, rs == 0);
     update_negative_flag(cpu, (rs as i8) < 0);
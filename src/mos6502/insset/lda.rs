@@ -86,7 +86,7 @@ impl Mos6502Ins for LdaIndY {
 }
 
 fn do_load_accumulator(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeFn) {
-    let mem_val: u8 = address_mode_fn(&cpu);
+    let mem_val: u8 = address_mode_fn(cpu);
     cpu.ac = mem_val;
 
     update_zero_flag(cpu, cpu.ac == 0);
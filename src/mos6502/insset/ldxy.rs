@@ -103,7 +103,7 @@ impl Mos6502Ins for LdyAbsX {
 }
 
 fn do_load_x(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeFn) {
-    let mem_val: u8 = address_mode_fn(&cpu);
+    let mem_val: u8 = address_mode_fn(cpu);
     cpu.xr = mem_val;
 
     update_zero_flag(cpu, cpu.xr == 0);
@@ -113,7 +113,7 @@ fn do_load_x(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeFn)
 }
 
 fn do_load_y(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeFn) {
-    let mem_val: u8 = address_mode_fn(&cpu);
+    let mem_val: u8 = address_mode_fn(cpu);
     cpu.yr = mem_val;
 
     update_zero_flag(cpu, cpu.yr == 0);
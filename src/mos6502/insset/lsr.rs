@@ -1,4 +1,12 @@
-use super::{InsAttr, Mos6502, Mos6502Ins};
+use crate::mos6502::address_mode::{
+    absolute_immutable, absolute_x_immutable, zero_page_immutatble, zero_page_x_immutable,
+    AddressModeImmutableFn,
+};
+
+use super::{
+    utils::{update_carry_flag, update_negative_flag, update_zero_flag},
+    InsAttr, Mos6502, Mos6502Ins,
+};
 
 pub struct LsrAcc {
     pub attr: InsAttr,
@@ -22,30 +30,81 @@ pub struct LsrAbsX {
 
 impl Mos6502Ins for LsrAcc {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        let result: u8 = cpu.ac >> 1;
+
+        update_carry_flag(cpu, cpu.ac & 0b1 == 0b1);
+        update_zero_flag(cpu, result == 0);
+        update_negative_flag(cpu, false);
+
+        cpu.ac = result;
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for LsrZP {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_lsr(cpu, &self.attr, zero_page_immutatble);
     }
 }
 
 impl Mos6502Ins for LsrZPX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_lsr(cpu, &self.attr, zero_page_x_immutable);
     }
 }
 
 impl Mos6502Ins for LsrAbs {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_lsr(cpu, &self.attr, absolute_immutable);
     }
 }
 
 impl Mos6502Ins for LsrAbsX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_lsr(cpu, &self.attr, absolute_x_immutable);
+    }
+}
+
+fn do_lsr(cpu: &mut Mos6502, attr: &InsAttr, immutable_fn: AddressModeImmutableFn) {
+    let address: u16 = immutable_fn(cpu);
+    let operand: u8 = cpu.read(address);
+    cpu.dummy_write(address, operand);
+    let result: u8 = operand >> 1;
+    cpu.write(address, result);
+
+    update_carry_flag(cpu, operand & 0b1 == 0b1);
+    update_zero_flag(cpu, result == 0);
+    update_negative_flag(cpu, false);
+
+    cpu.next_instruction(attr);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{
+        constant::{CARRY_ON_MASK, ZERO_ON_MASK},
+        insset::run_program,
+        Mos6502,
+    };
+
+    #[test]
+    fn lsr_should_shift_the_accumulator_into_carry() {
+        let mut cpu = Mos6502 {
+            ac: 0x03,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0x4a], 1);
+        assert_eq!(0x01, cpu.ac);
+        assert_eq!(CARRY_ON_MASK, cpu.sr);
+    }
+
+    #[test]
+    fn lsr_should_shift_memory_in_place() {
+        let mut cpu = Mos6502::default();
+        cpu.mem[0x10] = 0x01;
+        run_program(&mut cpu, &[0x46, 0x10], 1);
+        assert_eq!(0x00, cpu.mem[0x10]);
+        assert_eq!(CARRY_ON_MASK | ZERO_ON_MASK, cpu.sr);
+        assert_eq!(5, cpu.cycles());
     }
 }
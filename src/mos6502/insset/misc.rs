@@ -1,3 +1,7 @@
+use crate::mos6502::constant::{
+    BREAK_ON_MASK, CARRY_ON_MASK, DECIMAL_ON_MASK, INTERRUPT_ON_MASK, IRQ_VECTOR, UNUSED_ON_MASK,
+};
+
 use super::{InsAttr, Mos6502, Mos6502Ins};
 
 pub struct Brk {
@@ -49,40 +53,57 @@ pub struct Ilg {
 
 impl Mos6502Ins for Brk {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        // the return address skips the padding byte after the opcode
+        let return_address: u16 = cpu.pc.wrapping_add(2);
+        cpu.push((return_address >> 8) as u8);
+        cpu.push(return_address as u8);
+        let vector = cpu.sequence_vector(IRQ_VECTOR);
+        cpu.push(cpu.sr | BREAK_ON_MASK | UNUSED_ON_MASK);
+        cpu.sr |= INTERRUPT_ON_MASK;
+        let handler_lsb = cpu.read(vector) as u16;
+        let handler_msb = cpu.read(vector + 1) as u16;
+        cpu.jump(&self.attr, (handler_msb << 8) | handler_lsb)
     }
 }
 
 impl Mos6502Ins for JmpAbs {
     fn execute(&self, cpu: &mut Mos6502) {
-        let address_lsb = cpu.mem[cpu.pc as usize + 1] as u16;
-        let address_msb = cpu.mem[cpu.pc as usize + 2] as u16;
+        let address_lsb = cpu.read(cpu.pc + 1) as u16;
+        let address_msb = cpu.read(cpu.pc + 2) as u16;
         let address: u16 = (address_msb << 8) | address_lsb;
-        cpu.pc = address
+        cpu.jump(&self.attr, address)
     }
 }
 
 #[allow(arithmetic_overflow)]
 impl Mos6502Ins for JmpInd {
     fn execute(&self, cpu: &mut Mos6502) {
-        let address_lsb: u8 = cpu.mem[cpu.pc as usize + 1];
-        let address_msb = cpu.mem[cpu.pc as usize + 2] as u16;
+        let address_lsb: u8 = cpu.read(cpu.pc + 1);
+        let address_msb = cpu.read(cpu.pc + 2) as u16;
 
         let next_address_lsb: u8 = address_lsb + 1;
 
         let address: u16 = (address_msb << 8) | address_lsb as u16;
         let next_address: u16 = (address_msb << 8) | next_address_lsb as u16;
 
-        let effect_address_lsb = cpu.mem[address as usize] as u16;
-        let effect_address_msb = cpu.mem[next_address as usize] as u16;
+        let effect_address_lsb = cpu.read(address) as u16;
+        let effect_address_msb = cpu.read(next_address) as u16;
 
-        cpu.pc = (effect_address_msb << 8) | effect_address_lsb
+        cpu.jump(&self.attr, (effect_address_msb << 8) | effect_address_lsb)
     }
 }
 
 impl Mos6502Ins for Jsr {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        // the high byte of the target is read last, after the pushes
+        let address_lsb = cpu.read(cpu.pc + 1) as u16;
+        cpu.stack_dummy_read();
+        // the pushed address is the last byte of the JSR, RTS adds one
+        let return_address: u16 = cpu.pc.wrapping_add(2);
+        cpu.push((return_address >> 8) as u8);
+        cpu.push(return_address as u8);
+        let address_msb = cpu.read(cpu.pc + 2) as u16;
+        cpu.jump(&self.attr, (address_msb << 8) | address_lsb)
     }
 }
 
@@ -94,31 +115,45 @@ impl Mos6502Ins for Nop {
 
 impl Mos6502Ins for Rti {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        // B and the unused bit only exist on the stack copy of the status register
+        cpu.stack_dummy_read();
+        let status: u8 = cpu.pull();
+        cpu.sr = status & !(BREAK_ON_MASK | UNUSED_ON_MASK);
+        let address_lsb = cpu.pull() as u16;
+        let address_msb = cpu.pull() as u16;
+        cpu.jump(&self.attr, (address_msb << 8) | address_lsb)
     }
 }
 
 impl Mos6502Ins for Rts {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.stack_dummy_read();
+        let address_lsb = cpu.pull() as u16;
+        let address_msb = cpu.pull() as u16;
+        let address: u16 = (address_msb << 8) | address_lsb;
+        cpu.dummy_read(address);
+        cpu.jump(&self.attr, address.wrapping_add(1))
     }
 }
 
 impl Mos6502Ins for Sec {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.sr |= CARRY_ON_MASK;
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Sed {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.sr |= DECIMAL_ON_MASK;
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Sei {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.sr |= INTERRUPT_ON_MASK;
+        cpu.next_instruction(&self.attr);
     }
 }
 
@@ -132,6 +167,7 @@ impl Mos6502Ins for Ilg {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mos6502::insset::run_program;
 
     #[test]
     fn JmpInd_should_jump_indirect() {
@@ -168,4 +204,78 @@ mod tests {
         ins.execute(&mut cpu);
         assert_eq!(0xaaaa, cpu.pc)
     }
+
+    #[test]
+    fn Jsr_should_return_after_the_call_with_Rts() {
+        let mut cpu = Mos6502::default();
+        cpu.pc = 0x0200;
+        cpu.mem[0x0201] = 0x00;
+        cpu.mem[0x0202] = 0x03;
+        let jsr = Jsr {
+            attr: InsAttr {
+                opcode: 0x20,
+                len: 0x3,
+                cyc: 0x6,
+            },
+        };
+        jsr.execute(&mut cpu);
+        assert_eq!(0x0300, cpu.pc);
+        let rts = Rts {
+            attr: InsAttr {
+                opcode: 0x60,
+                len: 0x1,
+                cyc: 0x6,
+            },
+        };
+        rts.execute(&mut cpu);
+        assert_eq!(0x0203, cpu.pc)
+    }
+
+    #[test]
+    fn sei_should_mask_interrupts() {
+        let mut cpu = Mos6502::default();
+        run_program(&mut cpu, &[0x78], 1);
+        assert_eq!(INTERRUPT_ON_MASK, cpu.sr);
+        assert_eq!(0x0201, cpu.pc);
+        assert_eq!(2, cpu.cycles());
+    }
+
+    #[test]
+    fn rti_should_restore_status_and_pc() {
+        let mut cpu = Mos6502::default();
+        cpu.mem[0x01fd] = BREAK_ON_MASK | UNUSED_ON_MASK | CARRY_ON_MASK;
+        cpu.mem[0x01fe] = 0x34;
+        cpu.mem[0x01ff] = 0x12;
+        cpu.sp = 0xfc;
+        run_program(&mut cpu, &[0x40], 1);
+        assert_eq!(CARRY_ON_MASK, cpu.sr);
+        assert_eq!(0x1234, cpu.pc);
+        assert_eq!(0xff, cpu.sp);
+        assert_eq!(6, cpu.cycles());
+    }
+
+    #[test]
+    fn brk_should_push_pc_and_status_then_vector() {
+        let mut cpu = Mos6502 {
+            sp: 0xff,
+            ..Default::default()
+        };
+        cpu.mem[IRQ_VECTOR as usize + 1] = 0x03;
+        run_program(&mut cpu, &[0x00], 1);
+        assert_eq!(0x0300, cpu.pc);
+        assert_eq!(
+            [BREAK_ON_MASK | UNUSED_ON_MASK, 0x02, 0x02],
+            cpu.mem[0x01fd..0x0200]
+        );
+        assert_eq!(0xfc, cpu.sp);
+        assert_eq!(INTERRUPT_ON_MASK, cpu.sr);
+        assert_eq!(7, cpu.cycles());
+    }
+
+    #[test]
+    fn sec_and_sed_should_set_their_flag() {
+        let mut cpu = Mos6502::default();
+        run_program(&mut cpu, &[0x38, 0xf8], 2);
+        assert_eq!(CARRY_ON_MASK | DECIMAL_ON_MASK, cpu.sr);
+    }
 }
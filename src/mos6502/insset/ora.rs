@@ -1,4 +1,12 @@
-use super::{InsAttr, Mos6502, Mos6502Ins};
+use crate::mos6502::address_mode::{
+    absolute, absolute_x, absolute_y, immediate, indirect_x, indirect_y, zero_page, zero_page_x,
+    AddressModeFn,
+};
+
+use super::{
+    utils::{update_negative_flag, update_zero_flag},
+    InsAttr, Mos6502, Mos6502Ins,
+};
 
 pub struct OraImm {
     pub attr: InsAttr,
@@ -34,48 +42,86 @@ pub struct OraIndY {
 
 impl Mos6502Ins for OraImm {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_or(cpu, &self.attr, immediate);
     }
 }
 
 impl Mos6502Ins for OraZP {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_or(cpu, &self.attr, zero_page);
     }
 }
 
 impl Mos6502Ins for OraZPX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_or(cpu, &self.attr, zero_page_x);
     }
 }
 
 impl Mos6502Ins for OraAbs {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_or(cpu, &self.attr, absolute);
     }
 }
 
 impl Mos6502Ins for OraAbsX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_or(cpu, &self.attr, absolute_x);
     }
 }
 
 impl Mos6502Ins for OraAbsY {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_or(cpu, &self.attr, absolute_y);
     }
 }
 
 impl Mos6502Ins for OraIndX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_or(cpu, &self.attr, indirect_x);
     }
 }
 
 impl Mos6502Ins for OraIndY {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_or(cpu, &self.attr, indirect_y);
+    }
+}
+
+fn do_or(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeFn) {
+    let operand: u8 = address_mode_fn(cpu);
+    cpu.ac |= operand;
+
+    update_zero_flag(cpu, cpu.ac == 0);
+    update_negative_flag(cpu, (cpu.ac as i8) < 0);
+
+    cpu.next_instruction(attr);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{
+        constant::{NEGATIVE_ON_MASK, ZERO_ON_MASK},
+        insset::run_program,
+        Mos6502,
+    };
+
+    #[test]
+    fn ora_should_or_into_the_accumulator() {
+        let mut cpu = Mos6502 {
+            ac: 0x80,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0x09, 0x01], 1);
+        assert_eq!(0x81, cpu.ac);
+        assert_eq!(NEGATIVE_ON_MASK, cpu.sr);
+    }
+
+    #[test]
+    fn ora_should_set_zero_for_an_empty_result() {
+        let mut cpu = Mos6502::default();
+        run_program(&mut cpu, &[0x05, 0x10], 1);
+        assert_eq!(0x00, cpu.ac);
+        assert_eq!(ZERO_ON_MASK, cpu.sr);
     }
 }
@@ -59,7 +59,7 @@ fn parse_prefix_0_instruction(opcode: u8) -> Box<dyn Mos6502Ins> {
             attr: InsAttr::new(opcode, 1, 7),
         }),
         0x1 => Box::new(OraIndX {
-            attr: InsAttr::new(opcode, 2, 5),
+            attr: InsAttr::new(opcode, 2, 6),
         }),
         0x5 => Box::new(OraZP {
             attr: InsAttr::new(opcode, 2, 3),
@@ -202,8 +202,8 @@ fn parse_prefix_4_instruction(opcode: u8) -> Box<dyn Mos6502Ins> {
         0x0 => Box::new(Rti {
             attr: InsAttr::new(opcode, 1, 6),
         }),
-        0x1 => Box::new(EorImm {
-            attr: InsAttr::new(opcode, 2, 2),
+        0x1 => Box::new(EorIndX {
+            attr: InsAttr::new(opcode, 2, 6),
         }),
         0x5 => Box::new(EorZP {
             attr: InsAttr::new(opcode, 2, 3),
@@ -479,7 +479,7 @@ fn parse_prefix_b_instruction(opcode: u8) -> Box<dyn Mos6502Ins> {
         0x8 => Box::new(Clv {
             attr: InsAttr::new(opcode, 1, 2),
         }),
-        0x9 => Box::new(LdaAbsX {
+        0x9 => Box::new(LdaAbsY {
             attr: InsAttr::new(opcode, 3, 4),
         }),
         0xa => Box::new(Tsx {
@@ -488,7 +488,7 @@ fn parse_prefix_b_instruction(opcode: u8) -> Box<dyn Mos6502Ins> {
         0xc => Box::new(LdyAbsX {
             attr: InsAttr::new(opcode, 3, 4),
         }),
-        0xd => Box::new(LdaAbsY {
+        0xd => Box::new(LdaAbsX {
             attr: InsAttr::new(opcode, 3, 4),
         }),
         0xe => Box::new(LdxAbsY {
@@ -649,3 +649,55 @@ fn parse_prefix_f_instruction(opcode: u8) -> Box<dyn Mos6502Ins> {
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{insset::run_program, Mos6502};
+
+    #[test]
+    fn opcodes_should_be_decoded_from_their_whole_lower_nibble() {
+        let mut cpu = Mos6502::default();
+        cpu.mem[0x0300] = 0x0f;
+        cpu.ac = 0xf0;
+        // ora $0300, ldx $0300, inc $0300
+        let program = [0x0d, 0x00, 0x03, 0xae, 0x00, 0x03, 0xee, 0x00, 0x03];
+        run_program(&mut cpu, &program, 3);
+        assert_eq!(0xff, cpu.ac);
+        assert_eq!(0x0f, cpu.xr);
+        assert_eq!(0x10, cpu.mem[0x0300]);
+        assert_eq!(0x0209, cpu.pc);
+        assert_eq!(4 + 4 + 6, cpu.cycles());
+    }
+
+    #[test]
+    fn indexed_loads_should_use_their_own_index_register() {
+        let mut cpu = Mos6502 {
+            xr: 0x01,
+            yr: 0x02,
+            ..Default::default()
+        };
+        cpu.mem[0x0301] = 0x11;
+        cpu.mem[0x0302] = 0x22;
+        // lda $0300,x, then lda $0300,y
+        run_program(&mut cpu, &[0xbd, 0x00, 0x03], 1);
+        assert_eq!(0x11, cpu.ac);
+        run_program(&mut cpu, &[0xb9, 0x00, 0x03], 1);
+        assert_eq!(0x22, cpu.ac);
+    }
+
+    #[test]
+    fn eor_indirect_x_should_not_be_decoded_as_immediate() {
+        let mut cpu = Mos6502 {
+            ac: 0xff,
+            xr: 0x02,
+            ..Default::default()
+        };
+        cpu.mem[0x0012] = 0x00;
+        cpu.mem[0x0013] = 0x03;
+        cpu.mem[0x0300] = 0x0f;
+        // eor ($10,x)
+        run_program(&mut cpu, &[0x41, 0x10], 1);
+        assert_eq!(0xf0, cpu.ac);
+        assert_eq!(6, cpu.cycles());
+    }
+}
@@ -1,4 +1,9 @@
-use super::{InsAttr, Mos6502, Mos6502Ins};
+use crate::mos6502::constant::{BREAK_ON_MASK, UNUSED_ON_MASK};
+
+use super::{
+    utils::{update_negative_flag, update_zero_flag},
+    InsAttr, Mos6502, Mos6502Ins,
+};
 
 pub struct Pha {
     pub attr: InsAttr,
@@ -18,24 +23,65 @@ pub struct Plp {
 
 impl Mos6502Ins for Pha {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.push(cpu.ac);
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Php {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        // a pushed status always has B and the unused bit set
+        cpu.push(cpu.sr | BREAK_ON_MASK | UNUSED_ON_MASK);
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Pla {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.stack_dummy_read();
+        cpu.ac = cpu.pull();
+
+        update_zero_flag(cpu, cpu.ac == 0);
+        update_negative_flag(cpu, (cpu.ac as i8) < 0);
+
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Plp {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.stack_dummy_read();
+        let status: u8 = cpu.pull();
+        cpu.sr = status & !(BREAK_ON_MASK | UNUSED_ON_MASK);
+        cpu.next_instruction(&self.attr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{
+        constant::{OVERFLOW_ON_MASK, ZERO_ON_MASK},
+        insset::run_program,
+        Mos6502,
+    };
+
+    use super::*;
+
+    #[test]
+    fn stack_should_give_back_what_was_pushed() {
+        let mut cpu = Mos6502 {
+            ac: 0x42,
+            sp: 0xff,
+            ..Default::default()
+        };
+        // pha, php, pla, plp
+        run_program(&mut cpu, &[0x48, 0x08, 0x68, 0x28], 4);
+        assert_eq!(0x42, cpu.mem[0x01ff]);
+        assert_eq!(BREAK_ON_MASK | UNUSED_ON_MASK, cpu.mem[0x01fe]);
+        assert_eq!(BREAK_ON_MASK | UNUSED_ON_MASK, cpu.ac);
+        // B and the unused bit do not make it back into the status register
+        assert_eq!(OVERFLOW_ON_MASK | ZERO_ON_MASK, cpu.sr);
+        assert_eq!(0xff, cpu.sp);
+        assert_eq!(3 + 3 + 4 + 4, cpu.cycles());
     }
 }
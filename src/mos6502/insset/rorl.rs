@@ -1,4 +1,12 @@
-use super::{InsAttr, Mos6502, Mos6502Ins};
+use crate::mos6502::address_mode::{
+    absolute_immutable, absolute_x_immutable, zero_page_immutatble, zero_page_x_immutable,
+    AddressModeImmutableFn,
+};
+
+use super::{
+    utils::{update_carry_flag, update_negative_flag, update_zero_flag},
+    InsAttr, Mos6502, Mos6502Ins,
+};
 
 pub struct RolAcc {
     pub attr: InsAttr,
@@ -42,60 +50,146 @@ pub struct RorAbsX {
 
 impl Mos6502Ins for RolAcc {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.ac = rotate_left(cpu, cpu.ac);
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for RolZP {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_rotate(cpu, &self.attr, zero_page_immutatble, rotate_left);
     }
 }
 
 impl Mos6502Ins for RolZPX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_rotate(cpu, &self.attr, zero_page_x_immutable, rotate_left);
     }
 }
 
 impl Mos6502Ins for RolAbs {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_rotate(cpu, &self.attr, absolute_immutable, rotate_left);
     }
 }
 
 impl Mos6502Ins for RolAbsX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_rotate(cpu, &self.attr, absolute_x_immutable, rotate_left);
     }
 }
 
 impl Mos6502Ins for RorAcc {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.ac = rotate_right(cpu, cpu.ac);
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for RorZP {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_rotate(cpu, &self.attr, zero_page_immutatble, rotate_right);
     }
 }
 
 impl Mos6502Ins for RorZPX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_rotate(cpu, &self.attr, zero_page_x_immutable, rotate_right);
     }
 }
 
 impl Mos6502Ins for RorAbs {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_rotate(cpu, &self.attr, absolute_immutable, rotate_right);
     }
 }
 
 impl Mos6502Ins for RorAbsX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_rotate(cpu, &self.attr, absolute_x_immutable, rotate_right);
+    }
+}
+
+fn do_rotate(
+    cpu: &mut Mos6502,
+    attr: &InsAttr,
+    immutable_fn: AddressModeImmutableFn,
+    rotate_fn: fn(&mut Mos6502, u8) -> u8,
+) {
+    let address: u16 = immutable_fn(cpu);
+    let operand: u8 = cpu.read(address);
+    cpu.dummy_write(address, operand);
+    let result: u8 = rotate_fn(cpu, operand);
+    cpu.write(address, result);
+    cpu.next_instruction(attr);
+}
+
+///
+/// shift left through the carry, the old carry enters bit 0
+///
+fn rotate_left(cpu: &mut Mos6502, operand: u8) -> u8 {
+    let result: u8 = operand << 1 | cpu.is_carried();
+
+    update_carry_flag(cpu, operand >> 7 == 0b1);
+    update_zero_flag(cpu, result == 0);
+    update_negative_flag(cpu, (result as i8) < 0);
+
+    result
+}
+
+///
+/// shift right through the carry, the old carry enters bit 7
+///
+fn rotate_right(cpu: &mut Mos6502, operand: u8) -> u8 {
+    let result: u8 = operand >> 1 | cpu.is_carried() << 7;
+
+    update_carry_flag(cpu, operand & 0b1 == 0b1);
+    update_zero_flag(cpu, result == 0);
+    update_negative_flag(cpu, (result as i8) < 0);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{
+        constant::{CARRY_ON_MASK, NEGATIVE_ON_MASK, ZERO_ON_MASK},
+        insset::run_program,
+        Mos6502,
+    };
+
+    #[test]
+    fn rol_should_rotate_bit_7_into_carry() {
+        let mut cpu = Mos6502 {
+            ac: 0x80,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0x2a], 1);
+        assert_eq!(0x00, cpu.ac);
+        assert_eq!(CARRY_ON_MASK | ZERO_ON_MASK, cpu.sr);
+    }
+
+    #[test]
+    fn ror_should_rotate_carry_into_bit_7() {
+        let mut cpu = Mos6502 {
+            ac: 0x01,
+            sr: CARRY_ON_MASK,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0x6a], 1);
+        assert_eq!(0x80, cpu.ac);
+        assert_eq!(CARRY_ON_MASK | NEGATIVE_ON_MASK, cpu.sr);
+    }
+
+    #[test]
+    fn rol_should_rotate_memory_in_place() {
+        let mut cpu = Mos6502 {
+            sr: CARRY_ON_MASK,
+            ..Default::default()
+        };
+        cpu.mem[0x10] = 0x40;
+        run_program(&mut cpu, &[0x26, 0x10], 1);
+        assert_eq!(0x81, cpu.mem[0x10]);
+        assert_eq!(NEGATIVE_ON_MASK, cpu.sr);
     }
 }
@@ -1,4 +1,9 @@
-use super::{InsAttr, Mos6502, Mos6502Ins};
+use crate::mos6502::address_mode::{
+    absolute, absolute_x, absolute_y, immediate, indirect_x, indirect_y, zero_page, zero_page_x,
+    AddressModeFn,
+};
+
+use super::{adc::add_and_update_status_register, InsAttr, Mos6502, Mos6502Ins};
 
 pub struct SbcImm {
     pub attr: InsAttr,
@@ -34,48 +39,102 @@ pub struct SbcIndY {
 
 impl Mos6502Ins for SbcImm {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_subtract(cpu, &self.attr, immediate);
     }
 }
 
 impl Mos6502Ins for SbcZP {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_subtract(cpu, &self.attr, zero_page);
     }
 }
 
 impl Mos6502Ins for SbcZPX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_subtract(cpu, &self.attr, zero_page_x);
     }
 }
 
 impl Mos6502Ins for SbcAbs {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_subtract(cpu, &self.attr, absolute);
     }
 }
 
 impl Mos6502Ins for SbcAbsX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_subtract(cpu, &self.attr, absolute_x);
     }
 }
 
 impl Mos6502Ins for SbcAbsY {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_subtract(cpu, &self.attr, absolute_y);
     }
 }
 
 impl Mos6502Ins for SbcIndX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_subtract(cpu, &self.attr, indirect_x);
     }
 }
 
 impl Mos6502Ins for SbcIndY {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_subtract(cpu, &self.attr, indirect_y);
+    }
+}
+
+///
+/// in binary mode A - M - !C is A + !M + C
+///
+fn do_subtract(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeFn) {
+    let operand: u8 = address_mode_fn(cpu);
+    add_and_update_status_register(cpu, !operand);
+    cpu.next_instruction(attr);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{
+        constant::{CARRY_ON_MASK, NEGATIVE_ON_MASK},
+        insset::run_program,
+        Mos6502,
+    };
+
+    #[test]
+    fn sbc_should_subtract_without_borrow_when_carry_is_set() {
+        let mut cpu = Mos6502 {
+            ac: 0x50,
+            sr: CARRY_ON_MASK,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0xe9, 0x10], 1);
+        assert_eq!(0x40, cpu.ac);
+        assert_eq!(CARRY_ON_MASK, cpu.sr);
+    }
+
+    #[test]
+    fn sbc_should_borrow_when_carry_is_clear() {
+        let mut cpu = Mos6502 {
+            ac: 0x50,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0xe9, 0x10], 1);
+        assert_eq!(0x3f, cpu.ac);
+        assert_eq!(CARRY_ON_MASK, cpu.sr);
+    }
+
+    #[test]
+    fn sbc_should_clear_carry_when_the_result_borrows() {
+        let mut cpu = Mos6502 {
+            ac: 0x10,
+            sr: CARRY_ON_MASK,
+            ..Default::default()
+        };
+        cpu.mem[0x10] = 0x20;
+        run_program(&mut cpu, &[0xe5, 0x10], 1);
+        assert_eq!(0xf0, cpu.ac);
+        assert_eq!(NEGATIVE_ON_MASK, cpu.sr);
     }
 }
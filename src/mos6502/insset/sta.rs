@@ -1,3 +1,8 @@
+use crate::mos6502::address_mode::{
+    absolute_immutable, absolute_x_immutable, absolute_y_immutable, indirect_x_immutable,
+    indirect_y_immutable, zero_page_immutatble, zero_page_x_immutable, AddressModeImmutableFn,
+};
+
 use super::{InsAttr, Mos6502, Mos6502Ins};
 
 pub struct StaImm {
@@ -40,42 +45,52 @@ impl Mos6502Ins for StaImm {
 
 impl Mos6502Ins for StaZP {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store_accumulator(cpu, &self.attr, zero_page_immutatble);
     }
 }
 
 impl Mos6502Ins for StaZPX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store_accumulator(cpu, &self.attr, zero_page_x_immutable);
     }
 }
 
 impl Mos6502Ins for StaAbs {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store_accumulator(cpu, &self.attr, absolute_immutable);
     }
 }
 
 impl Mos6502Ins for StaAbsX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store_accumulator(cpu, &self.attr, absolute_x_immutable);
     }
 }
 
 impl Mos6502Ins for StaAbsY {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store_accumulator(cpu, &self.attr, absolute_y_immutable);
     }
 }
 
 impl Mos6502Ins for StaIndX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store_accumulator(cpu, &self.attr, indirect_x_immutable);
     }
 }
 
 impl Mos6502Ins for StaIndY {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store_accumulator(cpu, &self.attr, indirect_y_immutable);
     }
 }
+
+fn do_store_accumulator(
+    cpu: &mut Mos6502,
+    attr: &InsAttr,
+    address_mode_fn: AddressModeImmutableFn,
+) {
+    let address: u16 = address_mode_fn(cpu);
+    cpu.write(address, cpu.ac);
+    cpu.next_instruction(attr);
+}
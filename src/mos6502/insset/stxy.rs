@@ -1,3 +1,8 @@
+use crate::mos6502::address_mode::{
+    absolute_immutable, zero_page_immutatble, zero_page_x_immutable, zero_page_y_immutable,
+    AddressModeImmutableFn,
+};
+
 use super::{InsAttr, Mos6502, Mos6502Ins};
 
 pub struct StxZP {
@@ -26,36 +31,42 @@ pub struct StyAbs {
 
 impl Mos6502Ins for StxZP {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store(cpu, &self.attr, zero_page_immutatble, cpu.xr);
     }
 }
 
 impl Mos6502Ins for StxZPY {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store(cpu, &self.attr, zero_page_y_immutable, cpu.xr);
     }
 }
 
 impl Mos6502Ins for StxAbs {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store(cpu, &self.attr, absolute_immutable, cpu.xr);
     }
 }
 
 impl Mos6502Ins for StyZP {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store(cpu, &self.attr, zero_page_immutatble, cpu.yr);
     }
 }
 
 impl Mos6502Ins for StyZPX {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store(cpu, &self.attr, zero_page_x_immutable, cpu.yr);
     }
 }
 
 impl Mos6502Ins for StyAbs {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        do_store(cpu, &self.attr, absolute_immutable, cpu.yr);
     }
 }
+
+fn do_store(cpu: &mut Mos6502, attr: &InsAttr, address_mode_fn: AddressModeImmutableFn, val: u8) {
+    let address: u16 = address_mode_fn(cpu);
+    cpu.write(address, val);
+    cpu.next_instruction(attr);
+}
@@ -1,4 +1,7 @@
-use super::{InsAttr, Mos6502, Mos6502Ins};
+use super::{
+    utils::{update_negative_flag, update_zero_flag},
+    InsAttr, Mos6502, Mos6502Ins,
+};
 
 pub struct Tax {
     pub attr: InsAttr,
@@ -26,36 +29,89 @@ pub struct Tya {
 
 impl Mos6502Ins for Tax {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.xr = transfer(cpu, cpu.ac);
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Tay {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.yr = transfer(cpu, cpu.ac);
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Tsx {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.xr = transfer(cpu, cpu.sp);
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Txa {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.ac = transfer(cpu, cpu.xr);
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Txs {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        // the only transfer that leaves the flags alone
+        cpu.sp = cpu.xr;
+        cpu.next_instruction(&self.attr);
     }
 }
 
 impl Mos6502Ins for Tya {
     fn execute(&self, cpu: &mut Mos6502) {
-        todo!()
+        cpu.ac = transfer(cpu, cpu.yr);
+        cpu.next_instruction(&self.attr);
+    }
+}
+
+fn transfer(cpu: &mut Mos6502, val: u8) -> u8 {
+    update_zero_flag(cpu, val == 0);
+    update_negative_flag(cpu, (val as i8) < 0);
+    val
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mos6502::{
+        constant::{NEGATIVE_ON_MASK, ZERO_ON_MASK},
+        insset::run_program,
+        Mos6502,
+    };
+
+    #[test]
+    fn transfers_should_copy_registers_and_set_flags() {
+        let mut cpu = Mos6502 {
+            ac: 0x80,
+            ..Default::default()
+        };
+        // tax, tya
+        run_program(&mut cpu, &[0xaa, 0x98], 2);
+        assert_eq!((0x00, 0x80), (cpu.ac, cpu.xr));
+        assert_eq!(ZERO_ON_MASK, cpu.sr);
+        // txa, tay
+        run_program(&mut cpu, &[0x8a, 0xa8], 2);
+        assert_eq!((0x80, 0x80), (cpu.ac, cpu.yr));
+        assert_eq!(NEGATIVE_ON_MASK, cpu.sr);
+    }
+
+    #[test]
+    fn txs_should_leave_the_flags_alone_unlike_tsx() {
+        let mut cpu = Mos6502 {
+            xr: 0x80,
+            ..Default::default()
+        };
+        run_program(&mut cpu, &[0x9a], 1);
+        assert_eq!(0x80, cpu.sp);
+        assert_eq!(0, cpu.sr);
+        cpu.xr = 0;
+        run_program(&mut cpu, &[0xba], 1);
+        assert_eq!(0x80, cpu.xr);
+        assert_eq!(NEGATIVE_ON_MASK, cpu.sr);
     }
 }
@@ -0,0 +1,392 @@
+///
+/// inputs from the host written down with the cycle they reached the
+/// machine at: bytes devices took from the keyboard or a serial line, and
+/// the levels of IRQ and NMI driven from outside. Replayed into a machine
+/// built the same way, from the same state, they repeat the run exactly.
+/// Checkpoints hash the machine state every `interval` cycles so a replay
+/// can tell where it went its own way.
+///
+/// Recordings are text, one input per line:
+///
+/// ```text
+/// martian6502 recording
+/// interval 1000000
+/// key 52113 device 0 41
+/// key 60210 trap 1e5a 0d
+/// irq 70000 1
+/// check 1000000 9c1f0e2ab3d4c5f6
+/// ```
+///
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, ErrorKind, Write},
+};
+
+const HEADER: &str = "martian6502 recording";
+
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 1_000_000;
+
+///
+/// where a byte came from: a device, numbered in the order it was
+/// attached, or a trap standing in for one, named by its address
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    Device(usize),
+    Trap(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Byte(Source, u8),
+    Irq(bool),
+    Nmi(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub cycle: u64,
+    pub input: Input,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    pub interval: u64, // cycles between checkpoints
+    pub entries: Vec<Entry>,
+    pub checkpoints: Vec<(u64, u64)>, // cycle and state hash
+}
+
+impl Recording {
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            entries: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    ///
+    /// the cycle of the last input or checkpoint
+    ///
+    pub fn end(&self) -> u64 {
+        let last_entry = self.entries.last().map(|entry| entry.cycle);
+        let last_check = self.checkpoints.last().map(|&(cycle, _)| cycle);
+        last_entry.max(last_check).unwrap_or(0)
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{}", HEADER)?;
+        writeln!(out, "interval {}", self.interval)?;
+        for entry in self.entries.iter() {
+            write_entry(out, entry)?;
+        }
+        for &(cycle, hash) in self.checkpoints.iter() {
+            writeln!(out, "check {} {:016x}", cycle, hash)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// inputs and checkpoints may come in any order, each kind is sorted by
+    /// cycle when read back
+    ///
+    pub fn read(input: impl BufRead) -> io::Result<Self> {
+        let mut lines = input.lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid("not a recording".to_string()));
+        }
+        let mut recording = Recording::new(DEFAULT_CHECKPOINT_INTERVAL);
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parsed = match fields.as_slice() {
+                [] => Some(()),
+                ["interval", interval] => interval
+                    .parse()
+                    .ok()
+                    .filter(|&interval| interval > 0)
+                    .map(|interval| recording.interval = interval),
+                ["check", cycle, hash] => cycle
+                    .parse()
+                    .ok()
+                    .zip(u64::from_str_radix(hash, 16).ok())
+                    .map(|checkpoint| recording.checkpoints.push(checkpoint)),
+                [kind, cycle, args @ ..] => cycle
+                    .parse()
+                    .ok()
+                    .zip(parse_input(kind, args))
+                    .map(|(cycle, input)| recording.entries.push(Entry { cycle, input })),
+                _ => None,
+            };
+            if parsed.is_none() {
+                return Err(invalid(format!("bad recording line: {}", line)));
+            }
+        }
+        recording.entries.sort_by_key(|entry| entry.cycle);
+        recording.checkpoints.sort_by_key(|&(cycle, _)| cycle);
+        Ok(recording)
+    }
+}
+
+fn write_entry(out: &mut impl Write, entry: &Entry) -> io::Result<()> {
+    match entry.input {
+        Input::Byte(Source::Device(index), byte) => {
+            writeln!(out, "key {} device {} {:02x}", entry.cycle, index, byte)
+        }
+        Input::Byte(Source::Trap(address), byte) => {
+            writeln!(out, "key {} trap {:04x} {:02x}", entry.cycle, address, byte)
+        }
+        Input::Irq(level) => writeln!(out, "irq {} {}", entry.cycle, level as u8),
+        Input::Nmi(level) => writeln!(out, "nmi {} {}", entry.cycle, level as u8),
+    }
+}
+
+fn parse_input(kind: &str, args: &[&str]) -> Option<Input> {
+    let level = |level: &str| match level {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    };
+    match (kind, args) {
+        ("key", ["device", index, byte]) => Some(Input::Byte(
+            Source::Device(index.parse().ok()?),
+            u8::from_str_radix(byte, 16).ok()?,
+        )),
+        ("key", ["trap", address, byte]) => Some(Input::Byte(
+            Source::Trap(u16::from_str_radix(address, 16).ok()?),
+            u8::from_str_radix(byte, 16).ok()?,
+        )),
+        ("irq", [level_arg]) => level(level_arg).map(Input::Irq),
+        ("nmi", [level_arg]) => level(level_arg).map(Input::Nmi),
+        _ => None,
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+///
+/// FNV-1a, the hash has to stay the same across builds and hosts
+///
+pub fn state_hash(state: &[u8]) -> u64 {
+    state.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Default)]
+enum Mode {
+    #[default]
+    Live,
+    Record {
+        recording: Recording,
+        sink: Option<Box<dyn Write>>, // gets every line as it happens
+        error: Option<io::Error>,
+    },
+    Replay {
+        bytes: HashMap<Source, VecDeque<(u64, u8)>>,
+        lines: VecDeque<Entry>,
+        checkpoints: VecDeque<(u64, u64)>,
+    },
+}
+
+///
+/// what the cpu and its devices go through for their inputs, passing the
+/// host's inputs along unless it records or replays them
+///
+#[derive(Default)]
+pub(super) struct Journal {
+    mode: Mode,
+    interval: u64,
+    next_check: u64,
+}
+
+impl Journal {
+    pub(super) fn record(&mut self, interval: u64, cycles: u64, mut sink: Option<Box<dyn Write>>) {
+        let recording = Recording::new(interval);
+        let error = sink.as_mut().and_then(|sink| {
+            writeln!(sink, "{}", HEADER)
+                .and_then(|_| writeln!(sink, "interval {}", recording.interval))
+                .and_then(|_| sink.flush())
+                .err()
+        });
+        self.start(recording.interval, cycles);
+        self.mode = Mode::Record {
+            recording,
+            sink,
+            error,
+        };
+    }
+
+    pub(super) fn replay(&mut self, recording: Recording, cycles: u64) {
+        let mut bytes: HashMap<Source, VecDeque<(u64, u8)>> = HashMap::new();
+        let mut lines = VecDeque::new();
+        for entry in recording.entries {
+            match entry.input {
+                Input::Byte(source, byte) => bytes
+                    .entry(source)
+                    .or_default()
+                    .push_back((entry.cycle, byte)),
+                _ => lines.push_back(entry),
+            }
+        }
+        self.start(recording.interval, cycles);
+        self.mode = Mode::Replay {
+            bytes,
+            lines,
+            checkpoints: recording.checkpoints.into(),
+        };
+    }
+
+    fn start(&mut self, interval: u64, cycles: u64) {
+        self.interval = interval;
+        self.next_check = (cycles / interval + 1) * interval;
+    }
+
+    ///
+    /// back to passing the host's inputs along, with what was recorded
+    ///
+    pub(super) fn finish(&mut self) -> Option<io::Result<Recording>> {
+        match std::mem::take(&mut self.mode) {
+            Mode::Record {
+                error: Some(err), ..
+            } => Some(Err(err)),
+            Mode::Record { recording, .. } => Some(Ok(recording)),
+            _ => None,
+        }
+    }
+
+    pub(super) fn is_replaying(&self) -> bool {
+        matches!(self.mode, Mode::Replay { .. })
+    }
+
+    ///
+    /// a byte for `source` at `cycle`: what `poll` got from the host, or
+    /// what the recording has due for it by then
+    ///
+    pub(super) fn byte(
+        &mut self,
+        source: Source,
+        cycle: u64,
+        poll: impl FnOnce() -> Option<u8>,
+    ) -> Option<u8> {
+        match &mut self.mode {
+            Mode::Live => poll(),
+            Mode::Record { .. } => {
+                let byte = poll()?;
+                self.log(cycle, Input::Byte(source, byte));
+                Some(byte)
+            }
+            Mode::Replay { bytes, .. } => {
+                let pending = bytes.get_mut(&source)?;
+                match pending.front() {
+                    Some(&(at, byte)) if at <= cycle => {
+                        pending.pop_front();
+                        Some(byte)
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    pub(super) fn log(&mut self, cycle: u64, input: Input) {
+        if let Mode::Record {
+            recording,
+            sink,
+            error,
+        } = &mut self.mode
+        {
+            let entry = Entry { cycle, input };
+            if let (Some(out), None) = (sink.as_mut(), error.as_ref()) {
+                *error = write_entry(out, &entry).and_then(|_| out.flush()).err();
+            }
+            recording.entries.push(entry);
+        }
+    }
+
+    ///
+    /// the next interrupt level a replay drives by `cycle`
+    ///
+    pub(super) fn line_due(&mut self, cycle: u64) -> Option<Input> {
+        match &mut self.mode {
+            Mode::Replay { lines, .. } if lines.front()?.cycle <= cycle => {
+                lines.pop_front().map(|entry| entry.input)
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn checkpoint_due(&self, cycle: u64) -> bool {
+        !matches!(self.mode, Mode::Live) && cycle >= self.next_check
+    }
+
+    ///
+    /// record the state hash, or compare it with the recorded one. False
+    /// when a replay went its own way.
+    ///
+    pub(super) fn checkpoint(&mut self, cycle: u64, hash: u64) -> bool {
+        self.next_check = (cycle / self.interval + 1) * self.interval;
+        match &mut self.mode {
+            Mode::Live => true,
+            Mode::Record {
+                recording,
+                sink,
+                error,
+            } => {
+                if let (Some(out), None) = (sink.as_mut(), error.as_ref()) {
+                    *error = writeln!(out, "check {} {:016x}", cycle, hash)
+                        .and_then(|_| out.flush())
+                        .err();
+                }
+                recording.checkpoints.push((cycle, hash));
+                true
+            }
+            Mode::Replay { checkpoints, .. } => match checkpoints.pop_front() {
+                Some(expected) => expected == (cycle, hash),
+                None => true,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_reads_back() {
+        let mut recording = Recording::new(500);
+        recording.entries = vec![
+            Entry {
+                cycle: 12,
+                input: Input::Byte(Source::Device(1), 0x41),
+            },
+            Entry {
+                cycle: 20,
+                input: Input::Byte(Source::Trap(0x1e5a), 0x0d),
+            },
+            Entry {
+                cycle: 30,
+                input: Input::Irq(true),
+            },
+            Entry {
+                cycle: 31,
+                input: Input::Nmi(false),
+            },
+        ];
+        recording.checkpoints = vec![(500, 0xdead_beef)];
+        let mut text = Vec::new();
+        recording.write(&mut text).unwrap();
+        assert_eq!(recording, Recording::read(text.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_bad_lines_are_rejected() {
+        let text = "martian6502 recording\nkey 12 device 0 zz\n";
+        let err = Recording::read(text.as_bytes()).err().unwrap();
+        assert_eq!("bad recording line: key 12 device 0 zz", err.to_string());
+        assert!(Recording::read("hello\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,378 @@
+///
+/// sim65 compatible paravirtualization hooks. Programs built with
+/// `cl65 --target sim6502` reach the host by calling into a handful of fixed
+/// addresses at the top of memory, this module traps those addresses and
+/// services the calls on the host.
+///
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+};
+
+use super::{constant::RESET_VECTOR, HaltReason, Mos6502};
+
+pub const PARAVIRT_BASE: u16 = 0xfff4;
+
+const PV_OPEN: u16 = 0xfff4;
+const PV_CLOSE: u16 = 0xfff5;
+const PV_READ: u16 = 0xfff6;
+const PV_WRITE: u16 = 0xfff7;
+const PV_ARGS: u16 = 0xfff8;
+const PV_EXIT: u16 = 0xfff9;
+
+const HEADER_MAGIC: &[u8; 5] = b"sim65";
+const HEADER_LEN: usize = 12;
+
+// open(2) flags as they are defined by the cc65 runtime
+const O_RDONLY: u16 = 0x01;
+const O_WRONLY: u16 = 0x02;
+const O_RDWR: u16 = 0x03;
+const O_CREAT: u16 = 0x10;
+const O_TRUNC: u16 = 0x20;
+const O_APPEND: u16 = 0x40;
+const O_EXCL: u16 = 0x80;
+
+const FAILURE: u16 = 0xffff;
+
+enum Handle {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+pub struct Paravirt {
+    sp_addr: u8, // zero page location of the cc65 C stack pointer
+    args: Vec<String>,
+    handles: Vec<Option<Handle>>, // indexed by the file descriptor the program sees
+}
+
+impl Paravirt {
+    pub fn new(sp_addr: u8, args: Vec<String>) -> Self {
+        Self {
+            sp_addr,
+            args,
+            handles: vec![
+                Some(Handle::Stdin),
+                Some(Handle::Stdout),
+                Some(Handle::Stderr),
+            ],
+        }
+    }
+
+    fn call(&mut self, cpu: &mut Mos6502) {
+        match cpu.pc {
+            PV_OPEN => self.open(cpu),
+            PV_CLOSE => self.close(cpu),
+            PV_READ => self.read(cpu),
+            PV_WRITE => self.write(cpu),
+            PV_ARGS => self.copy_args(cpu),
+            PV_EXIT => {
+                cpu.halt(HaltReason::Exit(cpu.ac));
+                return;
+            }
+            _ => return,
+        }
+        // every hook is reached through a jsr
+        cpu.return_from_subroutine();
+    }
+
+    fn open(&mut self, cpu: &mut Mos6502) {
+        // open() is variadic, so every argument is on the C stack and Y holds their size,
+        // the optional mode is dropped since the host decides on file permissions
+        self.pop_param(cpu, cpu.yr.wrapping_sub(4));
+        let flags = self.pop_param(cpu, 2);
+        let name = self.pop_param(cpu, 2);
+
+        let path = read_c_string(cpu, name);
+        let mut options = OpenOptions::new();
+        match flags & O_RDWR {
+            O_RDONLY => options.read(true),
+            O_WRONLY => options.write(true),
+            _ => options.read(true).write(true),
+        };
+        if flags & O_CREAT != 0 {
+            if flags & O_EXCL != 0 {
+                options.create_new(true);
+            } else {
+                options.create(true);
+            }
+        }
+        if flags & O_TRUNC != 0 {
+            options.truncate(true);
+        }
+        if flags & O_APPEND != 0 {
+            options.append(true);
+        }
+
+        let result = match options.open(path) {
+            Ok(file) => self.allocate(Handle::File(file)),
+            Err(_) => FAILURE,
+        };
+        set_ax(cpu, result);
+    }
+
+    fn close(&mut self, cpu: &mut Mos6502) {
+        let fd = get_ax(cpu) as usize;
+        let result = match self.handles.get_mut(fd).and_then(Option::take) {
+            Some(_) => 0,
+            None => FAILURE,
+        };
+        set_ax(cpu, result);
+    }
+
+    fn read(&mut self, cpu: &mut Mos6502) {
+        let count = get_ax(cpu) as usize;
+        let buf = self.pop_param(cpu, 2);
+        let fd = self.pop_param(cpu, 2) as usize;
+
+        let mut data = vec![0; count];
+        let read = match self.handles.get_mut(fd) {
+            Some(Some(Handle::Stdin)) => io::stdin().read(&mut data),
+            Some(Some(Handle::File(file))) => file.read(&mut data),
+            _ => Err(io::ErrorKind::InvalidInput.into()),
+        };
+        let result = match read {
+            Ok(len) => {
+                for (i, byte) in data[..len].iter().enumerate() {
+                    cpu.mem[buf.wrapping_add(i as u16) as usize] = *byte;
+                }
+                len as u16
+            }
+            Err(_) => FAILURE,
+        };
+        set_ax(cpu, result);
+    }
+
+    fn write(&mut self, cpu: &mut Mos6502) {
+        let count = get_ax(cpu);
+        let buf = self.pop_param(cpu, 2);
+        let fd = self.pop_param(cpu, 2) as usize;
+
+        let data: Vec<u8> = (0..count)
+            .map(|i| cpu.mem[buf.wrapping_add(i) as usize])
+            .collect();
+        let written = match self.handles.get_mut(fd) {
+            Some(Some(Handle::Stdout)) => write_all_flushed(&mut io::stdout(), &data),
+            Some(Some(Handle::Stderr)) => write_all_flushed(&mut io::stderr(), &data),
+            Some(Some(Handle::File(file))) => file.write(&data),
+            _ => Err(io::ErrorKind::InvalidInput.into()),
+        };
+        let result = match written {
+            Ok(len) => len as u16,
+            Err(_) => FAILURE,
+        };
+        set_ax(cpu, result);
+    }
+
+    ///
+    /// copy argv onto the C stack, right below the current stack pointer,
+    /// then store its address into the pointer AX points to
+    ///
+    fn copy_args(&mut self, cpu: &mut Mos6502) {
+        let argc = self.args.len() as u16;
+        let argv = get_ax(cpu);
+        let mut sp = read_word(cpu, self.sp_addr as u16);
+        let mut arg_ptr = sp.wrapping_sub((argc + 1) * 2);
+
+        write_word(cpu, argv, arg_ptr);
+        sp = arg_ptr;
+        for arg in &self.args {
+            let bytes = arg.as_bytes();
+            sp = sp.wrapping_sub(bytes.len() as u16 + 1);
+            for (i, byte) in bytes.iter().chain(&[0]).enumerate() {
+                cpu.mem[sp.wrapping_add(i as u16) as usize] = *byte;
+            }
+            write_word(cpu, arg_ptr, sp);
+            arg_ptr = arg_ptr.wrapping_add(2);
+        }
+        write_word(cpu, arg_ptr, 0);
+
+        write_word(cpu, self.sp_addr as u16, sp);
+        set_ax(cpu, argc);
+    }
+
+    fn allocate(&mut self, handle: Handle) -> u16 {
+        match self.handles.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.handles[fd] = Some(handle);
+                fd as u16
+            }
+            None => {
+                self.handles.push(Some(handle));
+                (self.handles.len() - 1) as u16
+            }
+        }
+    }
+
+    ///
+    /// read a word from the C stack, then drop `size` bytes from it
+    ///
+    fn pop_param(&self, cpu: &mut Mos6502, size: u8) -> u16 {
+        let sp = read_word(cpu, self.sp_addr as u16);
+        let val = read_word(cpu, sp);
+        write_word(cpu, self.sp_addr as u16, sp.wrapping_add(size as u16));
+        val
+    }
+}
+
+pub fn is_hook(pc: u16) -> bool {
+    (PV_OPEN..=PV_EXIT).contains(&pc)
+}
+
+pub fn call(cpu: &mut Mos6502) {
+    if let Some(mut paravirt) = cpu.paravirt.take() {
+        paravirt.call(cpu);
+        cpu.paravirt = Some(paravirt);
+    }
+}
+
+///
+/// load a sim65 image, the header tells where the C stack pointer lives,
+/// where the image has to be placed and where execution starts
+///
+pub fn load(cpu: &mut Mos6502, image: &[u8], args: Vec<String>) -> io::Result<()> {
+    if image.len() < HEADER_LEN || &image[..HEADER_MAGIC.len()] != HEADER_MAGIC {
+        return Err(invalid_image("missing sim65 header"));
+    }
+    let version = image[5];
+    if version != 2 {
+        return Err(invalid_image("unsupported sim65 header version"));
+    }
+    let sp_addr = image[7];
+    let load_addr = u16::from_le_bytes([image[8], image[9]]) as usize;
+    let reset_addr = u16::from_le_bytes([image[10], image[11]]);
+
+    let program = &image[HEADER_LEN..];
+    if load_addr + program.len() > PARAVIRT_BASE as usize {
+        return Err(invalid_image("program overlaps the paravirtualization hooks"));
+    }
+    cpu.mem[load_addr..load_addr + program.len()].copy_from_slice(program);
+    write_word(cpu, RESET_VECTOR, reset_addr);
+
+    cpu.pc = reset_addr;
+    cpu.sp = 0xff;
+    cpu.paravirt = Some(Paravirt::new(sp_addr, args));
+    Ok(())
+}
+
+fn invalid_image(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_all_flushed(out: &mut dyn Write, data: &[u8]) -> io::Result<usize> {
+    out.write_all(data)?;
+    out.flush()?;
+    Ok(data.len())
+}
+
+fn read_c_string(cpu: &Mos6502, address: u16) -> String {
+    let bytes: Vec<u8> = (0..=u16::MAX)
+        .map(|i| cpu.mem[address.wrapping_add(i) as usize])
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn read_word(cpu: &Mos6502, address: u16) -> u16 {
+    let lsb = cpu.mem[address as usize] as u16;
+    let msb = cpu.mem[address.wrapping_add(1) as usize] as u16;
+    msb << 8 | lsb
+}
+
+fn write_word(cpu: &mut Mos6502, address: u16, val: u16) {
+    cpu.mem[address as usize] = val as u8;
+    cpu.mem[address.wrapping_add(1) as usize] = (val >> 8) as u8;
+}
+
+fn get_ax(cpu: &Mos6502) -> u16 {
+    (cpu.xr as u16) << 8 | cpu.ac as u16
+}
+
+fn set_ax(cpu: &mut Mos6502, val: u16) {
+    cpu.ac = val as u8;
+    cpu.xr = (val >> 8) as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sim65_image(program: &[u8]) -> Vec<u8> {
+        let mut image = HEADER_MAGIC.to_vec();
+        image.extend_from_slice(&[2, 0, 0x00, 0x00, 0x02, 0x00, 0x02]);
+        image.extend_from_slice(program);
+        image
+    }
+
+    #[test]
+    fn test_load_places_program_at_load_address() {
+        let mut cpu = Mos6502::default();
+        load(&mut cpu, &sim65_image(&[0xea, 0xea]), vec![]).unwrap();
+        assert_eq!(0x0200, cpu.pc);
+        assert_eq!(0xea, cpu.mem[0x0201]);
+        assert_eq!(0x0200, read_word(&cpu, RESET_VECTOR));
+    }
+
+    #[test]
+    fn test_load_rejects_foreign_image() {
+        let mut cpu = Mos6502::default();
+        assert!(load(&mut cpu, b"not a sim65 image", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_exit_halts_with_accumulator() {
+        let mut cpu = Mos6502::default();
+        load(&mut cpu, &sim65_image(&[]), vec![]).unwrap();
+        cpu.pc = PV_EXIT;
+        cpu.ac = 42;
+        call(&mut cpu);
+        assert_eq!(Some(HaltReason::Exit(42)), cpu.halt_reason());
+    }
+
+    #[test]
+    fn test_program_writes_then_exits() {
+        let program = [
+            0xa9, 0xfc, 0x85, 0x00, 0xa9, 0x0f, 0x85, 0x01, // C stack pointer = $0ffc
+            0xa9, 0x26, 0x8d, 0xfc, 0x0f, 0xa9, 0x02, 0x8d, 0xfd, 0x0f, // buf = $0226
+            0xa9, 0x03, 0x8d, 0xfe, 0x0f, 0xa9, 0x00, 0x8d, 0xff, 0x0f, // fd = 3
+            0xa9, 0x05, 0xa2, 0x00, 0x20, 0xf7, 0xff, // write(fd, buf, 5)
+            0x20, 0xf9, 0xff, // exit(a)
+            b'h', b'e', b'l', b'l', b'o',
+        ];
+        let path = std::env::temp_dir().join(format!("martian6502-sim65-{}", std::process::id()));
+        let mut cpu = Mos6502::default();
+        cpu.load_sim65(&sim65_image(&program), vec![]).unwrap();
+        let handles = &mut cpu.paravirt.as_mut().unwrap().handles;
+        handles.push(Some(Handle::File(File::create(&path).unwrap())));
+        cpu.run();
+
+        assert_eq!(Some(HaltReason::Exit(5)), cpu.halt_reason());
+        assert_eq!(0x1000, read_word(&cpu, 0x00));
+        assert_eq!(b"hello", std::fs::read(&path).unwrap().as_slice());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_args_copies_argv_below_c_stack() {
+        let mut cpu = Mos6502::default();
+        load(&mut cpu, &sim65_image(&[]), vec!["prog".into(), "x".into()]).unwrap();
+        write_word(&mut cpu, 0x00, 0x1000);
+        // return address of the jsr that reached the hook
+        cpu.sp = 0xfd;
+        cpu.mem[0x01fe] = 0x33;
+        cpu.mem[0x01ff] = 0x12;
+        set_ax(&mut cpu, 0x0300);
+        cpu.pc = PV_ARGS;
+        call(&mut cpu);
+
+        assert_eq!(2, get_ax(&cpu));
+        assert_eq!(0x1234, cpu.pc);
+        let argv = read_word(&cpu, 0x0300);
+        assert_eq!(0x1000 - 6, argv);
+        assert_eq!("prog", read_c_string(&cpu, read_word(&cpu, argv)));
+        assert_eq!("x", read_c_string(&cpu, read_word(&cpu, argv + 2)));
+        assert_eq!(0, read_word(&cpu, argv + 4));
+        assert_eq!(read_word(&cpu, 0x00), read_word(&cpu, argv + 2));
+    }
+}
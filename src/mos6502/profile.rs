@@ -0,0 +1,385 @@
+///
+/// execution profiler. Every step's cycles, penalties included, go to the
+/// instruction's address and to the routine running it. Routines are
+/// entered by JSR, BRK and interrupts, and left once the stack pointer
+/// climbs above where the call left it, which covers RTS, RTI and code
+/// dropping its return address.
+///
+/// Calls are kept as a tree of call paths starting at the routine the
+/// profile started in, so a routine called from two places has two nodes.
+///
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use super::{AccessKind, Mos6502};
+
+const BRK: u8 = 0x00;
+const JSR: u8 = 0x20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionProfile {
+    pub pc: u16,
+    pub executed: u64,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoutineProfile {
+    pub address: u16,
+    pub calls: u64,
+    pub exclusive: u64, // cycles spent in the routine itself
+    pub inclusive: u64, // and in everything it called
+}
+
+///
+/// one call path of the call tree, `depth` levels below the start
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallProfile {
+    pub depth: usize,
+    pub address: u16,
+    pub calls: u64,
+    pub exclusive: u64,
+    pub inclusive: u64,
+}
+
+struct Node {
+    routine: u16,
+    parent: Option<usize>, // always a lower index
+    children: HashMap<u16, usize>,
+    calls: u64,
+    cycles: u64,
+}
+
+struct Frame {
+    node: usize,
+    sp: u8, // stack pointer right after the call
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    instructions: HashMap<u16, (u64, u64)>, // executed and cycles by pc
+    nodes: Vec<Node>,
+    frames: Vec<Frame>, // shadow call stack, the starting routine at the bottom
+    before: (u16, u8, u64), // pc, sp and cycles before the step
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn begin(&mut self, cpu: &Mos6502) {
+        if self.nodes.is_empty() {
+            self.nodes.push(Node {
+                routine: cpu.pc,
+                parent: None,
+                children: HashMap::new(),
+                calls: 1,
+                cycles: 0,
+            });
+            self.frames.push(Frame {
+                node: 0,
+                sp: cpu.sp,
+            });
+        }
+        self.before = (cpu.pc, cpu.sp, cpu.cycles);
+    }
+
+    pub(super) fn end(&mut self, cpu: &Mos6502) {
+        let (pc, sp, cycles) = self.before;
+        let elapsed = cpu.cycles - cycles;
+        let instruction = self.instructions.entry(pc).or_default();
+        instruction.0 += 1;
+        instruction.1 += elapsed;
+        let top = self.frames.last().map_or(0, |frame| frame.node);
+        self.nodes[top].cycles += elapsed;
+
+        let opcode = cpu
+            .accesses
+            .iter()
+            .find(|access| access.kind == AccessKind::Fetch)
+            .map(|access| access.val);
+        let call = match opcode {
+            Some(JSR) | Some(BRK) => true,
+            Some(_) => false,
+            // no opcode fetched, an interrupt pushes pc and status
+            None => cpu.sp == sp.wrapping_sub(3),
+        };
+        if call {
+            let node = self.child(top, cpu.pc);
+            self.nodes[node].calls += 1;
+            self.frames.push(Frame { node, sp: cpu.sp });
+        } else {
+            while self.frames.len() > 1 && self.frames.last().is_some_and(|frame| frame.sp < cpu.sp)
+            {
+                self.frames.pop();
+            }
+        }
+    }
+
+    fn child(&mut self, parent: usize, routine: u16) -> usize {
+        if let Some(&node) = self.nodes[parent].children.get(&routine) {
+            return node;
+        }
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            routine,
+            parent: Some(parent),
+            children: HashMap::new(),
+            calls: 0,
+            cycles: 0,
+        });
+        self.nodes[parent].children.insert(routine, node);
+        node
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.nodes.iter().map(|node| node.cycles).sum()
+    }
+
+    ///
+    /// flat profile of the executed addresses, most cycles first
+    ///
+    pub fn instructions(&self) -> Vec<InstructionProfile> {
+        let mut profile: Vec<InstructionProfile> = self
+            .instructions
+            .iter()
+            .map(|(&pc, &(executed, cycles))| InstructionProfile {
+                pc,
+                executed,
+                cycles,
+            })
+            .collect();
+        profile.sort_by_key(|instruction| (u64::MAX - instruction.cycles, instruction.pc));
+        profile
+    }
+
+    ///
+    /// flat profile of the routines, most inclusive cycles first. Recursive
+    /// calls count once towards the inclusive cycles.
+    ///
+    pub fn routines(&self) -> Vec<RoutineProfile> {
+        let inclusive = self.inclusive();
+        let mut routines: HashMap<u16, RoutineProfile> = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let routine = routines.entry(node.routine).or_insert(RoutineProfile {
+                address: node.routine,
+                calls: 0,
+                exclusive: 0,
+                inclusive: 0,
+            });
+            routine.calls += node.calls;
+            routine.exclusive += node.cycles;
+            if !self.recursive(index) {
+                routine.inclusive += inclusive[index];
+            }
+        }
+        let mut routines: Vec<RoutineProfile> = routines.into_values().collect();
+        routines.sort_by_key(|routine| (u64::MAX - routine.inclusive, routine.address));
+        routines
+    }
+
+    ///
+    /// the call tree depth first, callees with the most cycles first
+    ///
+    pub fn call_tree(&self) -> Vec<CallProfile> {
+        let inclusive = self.inclusive();
+        let mut tree = Vec::new();
+        let mut pending = if self.nodes.is_empty() {
+            Vec::new()
+        } else {
+            vec![(0, 0)]
+        };
+        while let Some((index, depth)) = pending.pop() {
+            let node = &self.nodes[index];
+            tree.push(CallProfile {
+                depth,
+                address: node.routine,
+                calls: node.calls,
+                exclusive: node.cycles,
+                inclusive: inclusive[index],
+            });
+            let mut children: Vec<usize> = node.children.values().copied().collect();
+            children.sort_by_key(|&child| (inclusive[child], u16::MAX - self.nodes[child].routine));
+            pending.extend(children.into_iter().map(|child| (child, depth + 1)));
+        }
+        tree
+    }
+
+    ///
+    /// one `caller;callee cycles` line per call path, the folded stacks
+    /// flame graph tools read, with routines named by `name`
+    ///
+    pub fn write_folded(
+        &self,
+        out: &mut dyn Write,
+        name: &dyn Fn(u16) -> String,
+    ) -> io::Result<()> {
+        for node in self.nodes.iter() {
+            if node.cycles == 0 {
+                continue;
+            }
+            let mut path = vec![name(node.routine)];
+            let mut parent = node.parent;
+            while let Some(index) = parent {
+                path.push(name(self.nodes[index].routine));
+                parent = self.nodes[index].parent;
+            }
+            path.reverse();
+            writeln!(out, "{} {}", path.join(";"), node.cycles)?;
+        }
+        Ok(())
+    }
+
+    fn inclusive(&self) -> Vec<u64> {
+        let mut inclusive: Vec<u64> = self.nodes.iter().map(|node| node.cycles).collect();
+        for (index, node) in self.nodes.iter().enumerate().rev() {
+            if let Some(parent) = node.parent {
+                inclusive[parent] += inclusive[index];
+            }
+        }
+        inclusive
+    }
+
+    ///
+    /// whether the routine of `index` also runs further up its call path
+    ///
+    fn recursive(&self, index: usize) -> bool {
+        let routine = self.nodes[index].routine;
+        let mut parent = self.nodes[index].parent;
+        while let Some(index) = parent {
+            if self.nodes[index].routine == routine {
+                return true;
+            }
+            parent = self.nodes[index].parent;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mos6502::Registers;
+
+    fn profiled(program: &[u8], steps: usize) -> Mos6502 {
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, program);
+        cpu.set_registers(Registers {
+            pc: 0x0200,
+            sp: 0xff,
+            ..Registers::default()
+        });
+        cpu.profile();
+        for _ in 0..steps {
+            cpu.step();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_calls_split_inclusive_and_exclusive() {
+        // jsr $0210, nop; $0210: jsr $0220, rts; $0220: nop, rts
+        let mut program = vec![0xea; 0x30];
+        program[..3].copy_from_slice(&[0x20, 0x10, 0x02]);
+        program[0x10..0x14].copy_from_slice(&[0x20, 0x20, 0x02, 0x60]);
+        program[0x20..0x22].copy_from_slice(&[0xea, 0x60]);
+        let cpu = profiled(&program, 7);
+        let profiler = cpu.profiler().unwrap();
+        assert_eq!(6 + 6 + 2 + 6 + 6 + 2 + 2, profiler.total_cycles());
+        let tree = profiler.call_tree();
+        let summary: Vec<(usize, u16, u64, u64)> = tree
+            .iter()
+            .map(|call| (call.depth, call.address, call.exclusive, call.inclusive))
+            .collect();
+        assert_eq!(
+            vec![(0, 0x0200, 10, 30), (1, 0x0210, 12, 20), (2, 0x0220, 8, 8)],
+            summary
+        );
+        let routines = profiler.routines();
+        assert_eq!(
+            (0x0210, 1, 20),
+            (
+                routines[1].address,
+                routines[1].calls,
+                routines[1].inclusive
+            )
+        );
+    }
+
+    #[test]
+    fn test_penalties_are_counted() {
+        // ldx #$01, ora $02ff,x, bne *+2, beq *+2, bne $01ff
+        let mut program = vec![
+            0xa2, 0x01, 0x1d, 0xff, 0x02, 0xd0, 0x00, 0xf0, 0x00, 0xd0, 0xf4,
+        ];
+        program.resize(0x101, 0x01);
+        let cpu = profiled(&program, 5);
+        let mut cycles: Vec<(u16, u64)> = cpu
+            .profiler()
+            .unwrap()
+            .instructions()
+            .iter()
+            .map(|instruction| (instruction.pc, instruction.cycles))
+            .collect();
+        cycles.sort();
+        let expected = [
+            (0x0200, 2),
+            (0x0202, 5),
+            (0x0205, 3),
+            (0x0207, 2),
+            (0x0209, 4),
+        ];
+        assert_eq!(expected.to_vec(), cycles);
+        assert_eq!(0x01ff, cpu.registers().pc);
+    }
+
+    #[test]
+    fn test_interrupt_handler_is_a_routine() {
+        let mut program = vec![0xea; 0x20];
+        program[0x10] = 0x40; // $0210: rti
+        let mut cpu = Mos6502::default();
+        cpu.load(0x0200, &program);
+        cpu.load(0xfffe, &[0x10, 0x02]);
+        cpu.set_registers(Registers {
+            pc: 0x0200,
+            sp: 0xff,
+            ..Registers::default()
+        });
+        cpu.profile();
+        cpu.set_irq(true);
+        cpu.step();
+        cpu.set_irq(false);
+        cpu.step();
+        cpu.step();
+        let tree = cpu.profiler().unwrap().call_tree();
+        // the interrupt sequence is paid by the interrupted code
+        assert_eq!(
+            (1, 0x0210, 6),
+            (tree[1].depth, tree[1].address, tree[1].exclusive)
+        );
+        assert_eq!((0x0200, 9), (tree[0].address, tree[0].exclusive));
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        // jsr $0210, nop; $0210: rts
+        let mut program = vec![0xea; 0x20];
+        program[..3].copy_from_slice(&[0x20, 0x10, 0x02]);
+        program[0x10] = 0x60;
+        let cpu = profiled(&program, 3);
+        let mut folded = Vec::new();
+        let name = |address: u16| format!("${:04x}", address);
+        cpu.profiler()
+            .unwrap()
+            .write_folded(&mut folded, &name)
+            .unwrap();
+        assert_eq!(
+            "$0200 8\n$0200;$0210 6\n",
+            String::from_utf8(folded).unwrap()
+        );
+    }
+}
@@ -0,0 +1,136 @@
+///
+/// attributes of the address space. Addresses outside every region behave
+/// as executable RAM, the way the flat memory always did.
+///
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Ram,
+    Rom,     // writes are ignored
+    Io,      // only the devices attached inside decode anything
+    Unmapped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u16,
+    pub end: u16, // last address of the region
+    pub kind: RegionKind,
+    pub executable: bool,
+}
+
+impl Region {
+    ///
+    /// RAM and ROM are executable, I/O and unmapped space are not
+    ///
+    pub fn new(start: u16, end: u16, kind: RegionKind) -> Self {
+        Self {
+            start,
+            end,
+            kind,
+            executable: matches!(kind, RegionKind::Ram | RegionKind::Rom),
+        }
+    }
+
+    pub fn no_execute(mut self) -> Self {
+        self.executable = false;
+        self
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        self.start <= address && address <= self.end
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    RomWrite,
+    UnmappedRead,
+    UnmappedWrite,
+    Execute,
+}
+
+///
+/// an access the memory map does not allow, strict mode halts on the first one
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessViolation {
+    pub kind: ViolationKind,
+    pub address: u16,
+    pub pc: u16, // instruction that made the access
+}
+
+impl fmt::Display for AccessViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.kind {
+            ViolationKind::RomWrite => "write to rom",
+            ViolationKind::UnmappedRead => "read from unmapped space",
+            ViolationKind::UnmappedWrite => "write to unmapped space",
+            ViolationKind::Execute => "execution of non-executable memory",
+        };
+        write!(
+            f,
+            "{} at ${:04x} by the instruction at ${:04x}",
+            access, self.address, self.pc
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mos6502::{HaltReason, Mos6502};
+
+    ///
+    /// sta $f000 from $0200, with rom at $f000
+    ///
+    fn rom_writer(strict: bool) -> Mos6502 {
+        let mut cpu = Mos6502::default();
+        cpu.map(Region::new(0xf000, 0xffff, RegionKind::Rom));
+        cpu.set_strict(strict);
+        cpu.load(0xf000, &[0x11]);
+        cpu.load(0x0200, &[0xa9, 0x22, 0x8d, 0x00, 0xf0]);
+        cpu.step();
+        cpu.step();
+        cpu
+    }
+
+    #[test]
+    fn test_strict_rom_write_halts() {
+        let mut cpu = rom_writer(true);
+        let violation = AccessViolation {
+            kind: ViolationKind::RomWrite,
+            address: 0xf000,
+            pc: 0x0202,
+        };
+        assert_eq!(Some(HaltReason::AccessViolation(violation)), cpu.halt_reason());
+        assert_eq!(0x11, cpu.inspect(0xf000));
+    }
+
+    #[test]
+    fn test_rom_writes_are_ignored_unless_strict() {
+        let mut cpu = rom_writer(false);
+        assert_eq!(None, cpu.halt_reason());
+        assert_eq!(0x11, cpu.inspect(0xf000));
+        assert_eq!(0x0205, cpu.registers().pc);
+    }
+
+    #[test]
+    fn test_strict_execution_of_data_halts_before_the_fetch() {
+        let mut cpu = Mos6502::default();
+        cpu.map(Region::new(0x0300, 0x03ff, RegionKind::Ram).no_execute());
+        cpu.set_strict(true);
+        cpu.load(0x0300, &[0xe8]); // inx
+        cpu.load(0x0200, &[0x4c, 0x00, 0x03]); // jmp $0300
+        cpu.step();
+        cpu.step();
+        let violation = AccessViolation {
+            kind: ViolationKind::Execute,
+            address: 0x0300,
+            pc: 0x0300,
+        };
+        assert_eq!(Some(HaltReason::AccessViolation(violation)), cpu.halt_reason());
+        assert_eq!(0, cpu.registers().xr);
+    }
+}
@@ -0,0 +1,250 @@
+///
+/// events at absolute cycle counts, owned by the cpu. A device gets a
+/// `Scheduler` handle when it is attached and schedules its own events
+/// instead of counting down in `tick`. The cpu runs instructions until one
+/// ends at or past the earliest deadline, then dispatches every event due.
+///
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use super::{
+    journal::{Journal, Source},
+    Mos6502,
+};
+
+pub type EventId = u64;
+
+///
+/// host code run when its event is due
+///
+pub type Callback = Box<dyn FnMut(&mut Mos6502)>;
+
+pub(super) enum Target {
+    Device(usize, u32), // index of the attached device and its event
+    Host(Callback),
+}
+
+#[derive(Default)]
+pub(super) struct Queue {
+    now: u64,
+    next_id: EventId,
+    events: BTreeMap<(u64, EventId), Target>, // equal deadlines fire in order
+    deadlines: HashMap<EventId, u64>,
+}
+
+impl Queue {
+    pub(super) fn set_now(&mut self, now: u64) {
+        self.now = now;
+    }
+
+    pub(super) fn schedule(&mut self, at: u64, target: Target) -> EventId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.events.insert((at, id), target);
+        self.deadlines.insert(id, at);
+        id
+    }
+
+    pub(super) fn cancel(&mut self, id: EventId) -> bool {
+        match self.deadlines.remove(&id) {
+            Some(at) => self.events.remove(&(at, id)).is_some(),
+            None => false,
+        }
+    }
+
+    pub(super) fn next_deadline(&self) -> Option<u64> {
+        self.events.keys().next().map(|&(at, _)| at)
+    }
+
+    ///
+    /// the earliest event if it is due at `now`
+    ///
+    pub(super) fn pop_due(&mut self, now: u64) -> Option<Target> {
+        let (&(at, id), _) = self.events.first_key_value()?;
+        if at > now {
+            return None;
+        }
+        self.deadlines.remove(&id);
+        self.events.remove(&(at, id))
+    }
+}
+
+///
+/// a device's way into the cpu's event queue, its events come back through
+/// `Device::event`
+///
+#[derive(Clone)]
+pub struct Scheduler {
+    device: usize,
+    base: u32, // added to the events of a chip inside a composite device
+    queue: Rc<RefCell<Queue>>,
+    journal: Rc<RefCell<Journal>>,
+}
+
+impl Scheduler {
+    pub(super) fn new(
+        device: usize,
+        queue: Rc<RefCell<Queue>>,
+        journal: Rc<RefCell<Journal>>,
+    ) -> Self {
+        Self {
+            device,
+            base: 0,
+            queue,
+            journal,
+        }
+    }
+
+    ///
+    /// the same queue for one of the chips of a composite device, the events
+    /// it schedules come back to the composite numbered from `base`
+    ///
+    pub fn offset(&self, base: u32) -> Self {
+        Self {
+            base: self.base + base,
+            ..self.clone()
+        }
+    }
+
+    ///
+    /// the cycle counter as of the bus access being made, or of the start
+    /// of the instruction when bus cycles are not counted
+    ///
+    pub fn now(&self) -> u64 {
+        self.queue.borrow().now
+    }
+
+    ///
+    /// deliver `event` to the device once the cycle counter reaches `at`
+    ///
+    pub fn schedule(&self, at: u64, event: u32) -> EventId {
+        self.queue
+            .borrow_mut()
+            .schedule(at, Target::Device(self.device, self.base + event))
+    }
+
+    pub fn schedule_in(&self, cycles: u64, event: u32) -> EventId {
+        let at = self.now() + cycles;
+        self.schedule(at, event)
+    }
+
+    ///
+    /// false if the event already fired or was cancelled
+    ///
+    pub fn cancel(&self, id: EventId) -> bool {
+        self.queue.borrow_mut().cancel(id)
+    }
+
+    ///
+    /// when a pending event is due, devices keep it in their state
+    ///
+    pub fn deadline(&self, id: EventId) -> Option<u64> {
+        self.queue.borrow().deadlines.get(&id).copied()
+    }
+
+    ///
+    /// a byte from the host, devices take their input through here so runs
+    /// can be recorded and replayed. `poll` is not called on a replay.
+    ///
+    pub fn input(&self, poll: impl FnOnce() -> Option<u8>) -> Option<u8> {
+        let now = self.now();
+        self.journal
+            .borrow_mut()
+            .byte(Source::Device(self.device), now, poll)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::device::Device;
+    use crate::mos6502::Mos6502;
+
+    use super::*;
+
+    ///
+    /// fires every `period` cycles and records when it was dispatched
+    ///
+    #[derive(Default)]
+    struct Periodic {
+        period: u64,
+        next: u64,
+        scheduler: Option<Scheduler>,
+        fired: Vec<u64>,
+    }
+
+    impl Device for Periodic {
+        fn size(&self) -> u16 {
+            1
+        }
+
+        fn read(&mut self, _offset: u16) -> u8 {
+            0
+        }
+
+        fn write(&mut self, _offset: u16, _val: u8) {}
+
+        fn connect(&mut self, scheduler: Scheduler) {
+            self.next = scheduler.now() + self.period;
+            scheduler.schedule(self.next, 7);
+            self.scheduler = Some(scheduler);
+        }
+
+        fn event(&mut self, event: u32) {
+            assert_eq!(7, event);
+            let scheduler = self.scheduler.as_ref().unwrap();
+            self.fired.push(scheduler.now());
+            // from the deadline, a late dispatch does not drift
+            self.next += self.period;
+            scheduler.schedule(self.next, 7);
+        }
+    }
+
+    #[test]
+    fn test_events_fire_after_the_instruction_reaching_them() {
+        let mut cpu = Mos6502::nops();
+        let fired = Rc::new(RefCell::new(Vec::new()));
+        for at in [5, 3, 4] {
+            let fired = fired.clone();
+            cpu.schedule(
+                at,
+                Box::new(move |cpu: &mut Mos6502| fired.borrow_mut().push((at, cpu.cycles()))),
+            );
+        }
+        assert_eq!(Some(3), cpu.next_event());
+        cpu.run_until(10);
+        assert_eq!(vec![(3, 4), (4, 4), (5, 6)], *fired.borrow());
+        assert_eq!(None, cpu.next_event());
+    }
+
+    #[test]
+    fn test_cancelled_events_do_not_fire() {
+        let mut cpu = Mos6502::nops();
+        let fired = Rc::new(RefCell::new(false));
+        let flag = fired.clone();
+        let id = cpu.schedule(
+            2,
+            Box::new(move |_: &mut Mos6502| *flag.borrow_mut() = true),
+        );
+        assert!(cpu.cancel(id));
+        assert!(!cpu.cancel(id));
+        cpu.run_until(10);
+        assert!(!*fired.borrow());
+    }
+
+    #[test]
+    fn test_devices_reschedule_themselves() {
+        let mut cpu = Mos6502::nops();
+        let device = Rc::new(RefCell::new(Periodic {
+            period: 5,
+            ..Periodic::default()
+        }));
+        cpu.attach(0xc000, Box::new(device.clone()));
+        cpu.run_until(20);
+        assert_eq!(vec![6, 10, 16, 20], device.borrow().fired);
+    }
+}
@@ -0,0 +1,277 @@
+///
+/// save states. A snapshot starts with a magic and a format version and is
+/// followed by tagged chunks, each a 4 byte tag, a little endian u32 length
+/// and the payload. Readers skip chunks they do not know, so snapshots keep
+/// loading after chunks are added; the version only moves when an existing
+/// chunk changes its layout. Device states carry a version of their own,
+/// devices convert older layouts when they are restored.
+///
+/// A snapshot holds state, not wiring: it is restored into a machine built
+/// the same way as the one it was taken from, devices are matched by their
+/// order and base address.
+///
+use std::io::{self, ErrorKind};
+
+use super::{Mos6502, Poll, Registers};
+use crate::device::{StateReader, StateWriter};
+
+const MAGIC: &[u8; 8] = b"M6502SNP";
+pub const VERSION: u16 = 3; // 2: device timers keep absolute deadlines, 3: device state versions
+
+const REGISTERS: &[u8; 4] = b"REGS";
+const MEMORY: &[u8; 4] = b"MEMO";
+const CYCLES: &[u8; 4] = b"CYCL";
+const LINES: &[u8; 4] = b"LINE"; // interrupt inputs and pins
+const DEVICE: &[u8; 4] = b"DEVC"; // one per attached device
+
+pub fn save(cpu: &Mos6502) -> Vec<u8> {
+    let mut snapshot = StateWriter::default();
+    snapshot.bytes(MAGIC).u16(VERSION);
+
+    let registers = cpu.registers();
+    let mut payload = StateWriter::default();
+    payload
+        .u16(registers.pc)
+        .u8(registers.sp)
+        .u8(registers.ac)
+        .u8(registers.xr)
+        .u8(registers.yr)
+        .u8(registers.sr);
+    chunk(&mut snapshot, REGISTERS, &payload.into_bytes());
+    chunk(&mut snapshot, MEMORY, &cpu.mem);
+    chunk(&mut snapshot, CYCLES, &cpu.cycles.to_le_bytes());
+    let lines = [
+        cpu.irq,
+        cpu.nmi,
+        cpu.nmi_seen,
+        cpu.not_ready,
+        cpu.so_low,
+        cpu.so_seen,
+        cpu.reset_held,
+        cpu.reset_seen,
+        cpu.nmi_latched,
+        cpu.poll.is_some(),
+        cpu.poll.is_some_and(|poll| poll.irq),
+        cpu.poll.is_some_and(|poll| poll.nmi),
+    ];
+    chunk(&mut snapshot, LINES, &lines.map(|line| line as u8));
+    for mapped in cpu.devices.iter() {
+        let mut payload = StateWriter::default();
+        payload
+            .u16(mapped.start)
+            .u8(mapped.device.state_version())
+            .bytes(&mapped.device.state());
+        chunk(&mut snapshot, DEVICE, &payload.into_bytes());
+    }
+    snapshot.into_bytes()
+}
+
+///
+/// the snapshot is checked against the machine before anything is touched
+///
+pub fn restore(cpu: &mut Mos6502, snapshot: &[u8]) -> io::Result<()> {
+    let Some(body) = snapshot.strip_prefix(MAGIC) else {
+        return Err(invalid("not a snapshot"));
+    };
+    if body.len() < 2 {
+        return Err(invalid("truncated snapshot"));
+    }
+    let (version, mut rest) = body.split_at(2);
+    let version = u16::from_le_bytes([version[0], version[1]]);
+    if version == 0 || version > VERSION {
+        return Err(invalid("snapshot from a newer version"));
+    }
+
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        if rest.len() < 8 {
+            return Err(invalid("truncated snapshot"));
+        }
+        let (header, body) = rest.split_at(8);
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if body.len() < len {
+            return Err(invalid("truncated snapshot"));
+        }
+        let (payload, next) = body.split_at(len);
+        chunks.push((&header[..4], payload));
+        rest = next;
+    }
+    let device_starts: Vec<u16> = chunks
+        .iter()
+        .filter(|(tag, _)| tag == DEVICE)
+        .map(|(_, payload)| StateReader::new(payload).u16())
+        .collect();
+    if !device_starts
+        .iter()
+        .eq(cpu.devices.iter().map(|mapped| &mapped.start))
+    {
+        return Err(invalid("snapshot was taken on a different machine"));
+    }
+
+    let mut device = 0;
+    for (tag, payload) in chunks {
+        let mut state = StateReader::new(payload);
+        match tag {
+            tag if tag == REGISTERS => cpu.set_registers(Registers {
+                pc: state.u16(),
+                sp: state.u8(),
+                ac: state.u8(),
+                xr: state.u8(),
+                yr: state.u8(),
+                sr: state.u8(),
+            }),
+            tag if tag == MEMORY => state.bytes(&mut cpu.mem),
+            tag if tag == CYCLES => {
+                cpu.cycles = state.u64();
+                // devices schedule their events again as of then
+                cpu.events.borrow_mut().set_now(cpu.cycles);
+            }
+            tag if tag == LINES => {
+                cpu.irq = state.bool();
+                cpu.nmi = state.bool();
+                cpu.nmi_seen = state.bool();
+                // older snapshots stop here, the pins read as idle
+                cpu.not_ready = state.bool();
+                cpu.so_low = state.bool();
+                cpu.so_seen = state.bool();
+                cpu.reset_held = state.bool();
+                cpu.reset_seen = state.bool();
+                cpu.nmi_latched = state.bool();
+                let polled = state.bool();
+                let poll = Poll {
+                    irq: state.bool(),
+                    nmi: state.bool(),
+                };
+                cpu.poll = polled.then_some(poll);
+            }
+            tag if tag == DEVICE => {
+                // before version 3 devices had the layout of the snapshot version
+                let (state_version, state) = match version {
+                    1 | 2 => (version as u8, payload.get(2..)),
+                    _ => (payload.get(2).copied().unwrap_or(1), payload.get(3..)),
+                };
+                let state = state.unwrap_or_default();
+                cpu.devices[device].device.restore(state, state_version);
+                device += 1;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn chunk(snapshot: &mut StateWriter, tag: &[u8; 4], payload: &[u8]) {
+    snapshot.bytes(tag).u32(payload.len() as u32).bytes(payload);
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        device::{Acia, BufferHost, Riot, Via},
+        mos6502::InterruptLine,
+    };
+
+    fn machine() -> Mos6502 {
+        let mut cpu = Mos6502::default();
+        cpu.attach(0x6000, Box::new(Via::default()));
+        cpu
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut cpu = machine();
+        cpu.load(0x0200, &[0xa9, 0x42]); // lda #$42
+        cpu.poke(0x6003, 0xff); // VIA DDRA
+        cpu.step();
+        let snapshot = save(&cpu);
+
+        let mut restored = machine();
+        restore(&mut restored, &snapshot).unwrap();
+        assert_eq!(cpu.registers(), restored.registers());
+        assert_eq!(cpu.cycles(), restored.cycles());
+        assert_eq!(0xff, restored.peek(0x6003));
+        assert_eq!(0xa9, restored.peek(0x0200));
+    }
+
+    #[test]
+    fn test_unknown_chunks_are_skipped() {
+        let cpu = machine();
+        let mut snapshot = save(&cpu);
+        chunk_into(&mut snapshot, b"NEW!", &[1, 2, 3]);
+        restore(&mut machine(), &snapshot).unwrap();
+    }
+
+    ///
+    /// taken by version 1 on a machine running NOPs, 302 cycles in: timer 1
+    /// of the VIA free running from 301 and just run out, timer 2 counting
+    /// from 3000, the shift register shifting out under timer 2, the RIOT
+    /// counting 50 at ÷64 and the ACIA sending an 'A' at 4800 baud
+    ///
+    const SNAPSHOT_V1: &[u8] = include_bytes!("testdata/snapshot_v1.bin");
+
+    const READ: [u16; 8] = [
+        0x600d, 0x6004, 0x6005, 0x6008, 0x6009, 0x6185, 0x6184, 0x6201,
+    ];
+
+    #[test]
+    fn test_version_1_timers_carry_on() {
+        let host = BufferHost::default();
+        let mut cpu = Mos6502::default();
+        let acia = Acia::new(Box::new(host.clone()), 1_000_000);
+        let line = InterruptLine::Disconnected;
+        cpu.attach_wired(0x6000, Box::new(Via::default()), line);
+        cpu.attach_wired(0x6100, Box::new(Riot::default()), line);
+        cpu.attach_wired(0x6200, Box::new(acia), line);
+        restore(&mut cpu, SNAPSHOT_V1).unwrap();
+        host.type_bytes(b"z");
+        // as version 1 went on from there: the cycles, VIA IFR, T1 and T2,
+        // RIOT flags and timer, ACIA RDRF and the characters sent
+        let expected: [(u64, [u8; 8], usize); 17] = [
+            (302, [0x40, 0xff, 0xff, 0x8a, 0x0a, 0x00, 0x2d, 0x00], 0),
+            (596, [0x00, 0x08, 0x00, 0x64, 0x09, 0x00, 0x28, 0x00], 0),
+            (888, [0x40, 0x13, 0x00, 0x40, 0x08, 0x00, 0x24, 0x00], 0),
+            (1182, [0x40, 0x1c, 0x00, 0x1a, 0x07, 0x00, 0x1f, 0x00], 0),
+            (1474, [0x40, 0x27, 0x00, 0xf6, 0x05, 0x00, 0x1a, 0x00], 0),
+            (1768, [0x40, 0x30, 0x00, 0xd0, 0x04, 0x00, 0x16, 0x00], 0),
+            (2060, [0x40, 0x3b, 0x00, 0xac, 0x03, 0x00, 0x11, 0x00], 0),
+            (2354, [0x40, 0x44, 0x00, 0x86, 0x02, 0x00, 0x0d, 0x08], 1),
+            (2646, [0x40, 0x4f, 0x00, 0x62, 0x01, 0x00, 0x08, 0x08], 1),
+            (2940, [0x40, 0x58, 0x00, 0x3c, 0x00, 0x00, 0x04, 0x08], 1),
+            (3232, [0x60, 0x63, 0x00, 0x18, 0xff, 0x80, 0xe0, 0x08], 1),
+            (3526, [0x40, 0x6c, 0x00, 0xf2, 0xfd, 0x00, 0xba, 0x08], 1),
+            (3818, [0x40, 0x77, 0x00, 0xce, 0xfc, 0x00, 0x96, 0x08], 1),
+            (4112, [0x40, 0x80, 0x00, 0xa8, 0xfb, 0x00, 0x70, 0x08], 1),
+            (4404, [0x40, 0x8b, 0x00, 0x84, 0xfa, 0x00, 0x4c, 0x08], 1),
+            (4698, [0x40, 0x94, 0x00, 0x5e, 0xf9, 0x00, 0x26, 0x08], 1),
+            (4990, [0x40, 0x9f, 0x00, 0x3a, 0xf8, 0x00, 0x02, 0x08], 1),
+        ];
+        for (k, (cycles, registers, sent)) in expected.into_iter().enumerate() {
+            cpu.run_until(302 + k as u64 * 293);
+            let mut read = READ.map(|address| cpu.peek(address));
+            read[7] &= 0x08;
+            let now = (cpu.cycles(), read, host.output().len());
+            assert_eq!((cycles, registers, sent), now);
+        }
+        assert_eq!(b"A".to_vec(), host.output());
+        assert_eq!(0x06, cpu.peek(0x600a));
+        assert_eq!(b'z', cpu.peek(0x6200));
+    }
+
+    #[test]
+    fn test_different_machine_is_refused() {
+        let snapshot = save(&machine());
+        let err = restore(&mut Mos6502::default(), &snapshot).unwrap_err();
+        assert_eq!("snapshot was taken on a different machine", err.to_string());
+    }
+
+    fn chunk_into(snapshot: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+        let mut extra = StateWriter::default();
+        chunk(&mut extra, tag, payload);
+        snapshot.extend(extra.into_bytes());
+    }
+}
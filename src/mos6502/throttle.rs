@@ -0,0 +1,152 @@
+///
+/// paces execution to a clock frequency. Every slice of cycles the host
+/// sleeps until the wall clock catches up with the time the cycles take on
+/// the real chip. Deadlines are measured from a fixed anchor, so sleeping
+/// too long is made up by the next slice instead of adding up; a host that
+/// fell far behind, or a cpu stopped in a debugger, starts a new anchor
+/// rather than racing to catch up.
+///
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+// clock speeds of common 6502 machines
+pub const NTSC_CLOCK_HZ: u32 = 1_789_773;
+pub const PAL_CLOCK_HZ: u32 = 1_662_607;
+
+// slices per second of emulated time
+const SLICES_PER_SECOND: u32 = 1000;
+
+// how far behind the wall clock execution may fall before pacing starts over
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+// the effective speed is measured over at least this much wall clock time
+const SPEED_WINDOW: Duration = Duration::from_millis(500);
+
+pub struct Throttle {
+    clock_hz: u32,
+    turbo: bool,
+    anchor: (Instant, u64),
+    window: (Instant, u64),
+    speed: Option<f64>, // cycles per second in the last full window
+}
+
+impl Throttle {
+    pub fn new(clock_hz: u32, cycles: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            clock_hz: clock_hz.max(1),
+            turbo: false,
+            anchor: (now, cycles),
+            window: (now, cycles),
+            speed: None,
+        }
+    }
+
+    pub fn clock_hz(&self) -> u32 {
+        self.clock_hz
+    }
+
+    ///
+    /// cycles between two calls of `pace`
+    ///
+    pub fn slice(&self) -> u64 {
+        (self.clock_hz / SLICES_PER_SECOND).max(1) as u64
+    }
+
+    pub fn is_turbo(&self) -> bool {
+        self.turbo
+    }
+
+    ///
+    /// run as fast as the host allows, the speed is still measured
+    ///
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+    }
+
+    ///
+    /// cycles per second the cpu actually ran at, once enough time passed
+    ///
+    pub fn speed(&self) -> Option<f64> {
+        self.speed
+    }
+
+    ///
+    /// wait until `cycles` are due on the wall clock
+    ///
+    pub fn pace(&mut self, cycles: u64) {
+        let now = Instant::now();
+        self.measure(now, cycles);
+        // a restored snapshot can take the cycle counter back
+        if self.turbo || cycles < self.anchor.1 {
+            self.anchor = (now, cycles);
+            return;
+        }
+        let (start, start_cycles) = self.anchor;
+        let elapsed = (cycles - start_cycles) as f64 / self.clock_hz as f64;
+        let due = start + Duration::from_secs_f64(elapsed);
+        if due > now {
+            thread::sleep(due - now);
+        } else if now - due > MAX_LAG {
+            self.anchor = (now, cycles);
+        }
+    }
+
+    fn measure(&mut self, now: Instant, cycles: u64) {
+        let (start, start_cycles) = self.window;
+        if cycles < start_cycles {
+            self.window = (now, cycles);
+            return;
+        }
+        let elapsed = now - start;
+        if elapsed >= SPEED_WINDOW {
+            self.speed = Some((cycles - start_cycles) as f64 / elapsed.as_secs_f64());
+            self.window = (now, cycles);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mos6502::Mos6502;
+
+    fn nops(clock_hz: u32) -> Mos6502 {
+        let mut cpu = Mos6502::nops();
+        cpu.throttle(clock_hz);
+        cpu
+    }
+
+    #[test]
+    fn test_runs_no_faster_than_the_clock() {
+        let mut cpu = nops(1_000_000);
+        let start = Instant::now();
+        cpu.run_until(20_000);
+        assert!(start.elapsed() >= Duration::from_millis(19));
+    }
+
+    #[test]
+    fn test_turbo_does_not_wait() {
+        // 100 seconds worth of cycles at 1 kHz
+        let mut cpu = nops(1000);
+        cpu.set_turbo(true);
+        let start = Instant::now();
+        cpu.run_until(100_000);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_falling_behind_starts_over() {
+        let mut throttle = Throttle::new(1_000_000, 0);
+        throttle.anchor.0 -= Duration::from_secs(1);
+        let start = Instant::now();
+        throttle.pace(1000);
+        assert_eq!(1000, throttle.anchor.1);
+        assert!(start.elapsed() < MAX_LAG);
+        throttle.window.0 -= SPEED_WINDOW;
+        throttle.pace(2000);
+        assert!(throttle.speed().is_some());
+    }
+}